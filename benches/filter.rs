@@ -0,0 +1,113 @@
+//! cost of the filters over DataId against the Vec<usize> one.
+//!
+//! `membership` checks every id of an index of 127.6K documents (the size of the news index) with
+//! one document in 10 accepted. `search` runs filtered searches on the news index (`news.hnsw.*`)
+//! when it is found in the working directory, it is skipped otherwise.
+//!
+//! cargo +nightly bench --bench filter
+
+use std::collections::HashSet;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use roaring::RoaringBitmap;
+use semantic_search::hnsw_index::dist::DistDot;
+use semantic_search::hnsw_index::filter::{And, BitSetFilter, FilterT, Not, Or};
+use semantic_search::hnsw_index::hnsw::{DataId, Hnsw};
+use semantic_search::utils::load_index;
+
+const NB_POINT: usize = 127_600;
+const SELECTIVITY: f64 = 0.1;
+const K: usize = 10;
+const EF: usize = 64;
+
+/// sorted ids, each one kept with probability SELECTIVITY
+fn sample_ids(seed: u64) -> Vec<DataId> {
+    let mut rng: StdRng = StdRng::seed_from_u64(seed);
+    (0..NB_POINT)
+        .filter(|_: &DataId| rng.gen_bool(SELECTIVITY))
+        .collect()
+}
+
+fn filters() -> Vec<(&'static str, Box<dyn FilterT + Send + Sync>)> {
+    let ids: Vec<DataId> = sample_ids(42);
+    let others: Vec<DataId> = sample_ids(7);
+
+    let bitset: BitSetFilter = ids.iter().copied().collect();
+    let bitmap: RoaringBitmap = ids.iter().map(|id: &DataId| *id as u32).collect();
+    let set: HashSet<DataId> = ids.iter().copied().collect();
+    let hset: hashbrown::HashSet<DataId> = ids.iter().copied().collect();
+    let other_bitset: BitSetFilter = others.iter().copied().collect();
+
+    vec![
+        ("vec", Box::new(ids.clone())),
+        ("bitset", Box::new(bitset.clone())),
+        ("roaring", Box::new(bitmap)),
+        ("hashset", Box::new(set)),
+        ("hashbrown", Box::new(hset)),
+        ("range", Box::new(0..(NB_POINT as f64 * SELECTIVITY) as DataId)),
+        ("and_vec", Box::new(And(ids.clone(), Not(others.clone())))),
+        ("and_bitset", Box::new(And(bitset.clone(), Not(other_bitset.clone())))),
+        ("or_bitset", Box::new(Or(bitset, other_bitset))),
+    ]
+}
+
+fn bench_membership(c: &mut Criterion) {
+    let mut group = c.benchmark_group("membership");
+    for (name, filter) in filters() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &filter, |b, filter| {
+            b.iter(|| {
+                (0..NB_POINT)
+                    .filter(|id: &DataId| filter.hnsw_filter(black_box(id)))
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_search(c: &mut Criterion) {
+    let index: Hnsw<f32, DistDot> = match load_index("news") {
+        Ok(index) => index,
+        Err(e) => {
+            eprintln!("search benchmarks skipped, no news index : {}", e);
+            return;
+        },
+    };
+
+    // unit queries, as the embeddings of the index
+    let mut rng: StdRng = StdRng::seed_from_u64(0);
+    let queries: Vec<Vec<f32>> = (0..32)
+        .map(|_| {
+            let v: Vec<f32> = (0..index.get_point_indexation().get_data_dimension())
+                .map(|_| rng.gen_range(-1.0..1.0))
+                .collect();
+            let norm: f32 = v.iter().map(|x: &f32| x * x).sum::<f32>().sqrt();
+            v.iter().map(|x: &f32| x / norm).collect()
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("search");
+    group.bench_function("none", |b| {
+        b.iter(|| {
+            for query in &queries {
+                black_box(index.search_filter(query, K, EF, None));
+            }
+        })
+    });
+    for (name, filter) in filters() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &filter, |b, filter| {
+            b.iter(|| {
+                for query in &queries {
+                    black_box(index.search_filter(query, K, EF, Some(filter.as_ref())));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_membership, bench_search);
+criterion_main!(benches);
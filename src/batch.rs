@@ -0,0 +1,346 @@
+//! Dynamic batching of the requests of the server.
+//!
+//! Concurrent requests are queued and coalesced : a worker takes the requests waiting, up to
+//! max_batch_size queries, as soon as the batch is full or its first request waited max_wait. The
+//! queries of the batch are embedded by one call of the model and searched in parallel, then the
+//! responses are sent back to each request. There is one worker by model of the pool.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use parking_lot::{Condvar, Mutex, MutexGuard};
+use tokio::sync::oneshot;
+use tokio::task::JoinError;
+
+use crate::error::SearchError;
+use crate::search::{PreparedSearch, Searcher};
+use crate::ss::{PredictRequest, PredictResponse};
+
+/// limits of a batch, see Config::serve_options
+/// . max_batch_size : number of queries a batch is filled up to. A request with more queries is
+///   a batch on its own.
+/// . max_wait : longest time the first request of a batch waits for the batch to fill
+#[derive(Debug, Clone)]
+pub struct BatchConfig {
+    pub max_batch_size: usize,
+    pub max_wait: Duration,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig { max_batch_size: 64, max_wait: Duration::from_millis(1) }
+    }
+}
+
+/// counters of the batches run since the start, latencies in ns
+#[derive(Debug, Clone, Default)]
+pub struct BatchStats {
+    pub nb_batch: u64,
+    pub nb_request: u64,
+    pub nb_query: u64,
+    /// sum and max of the time requests waited for their batch
+    pub total_queue_latency: u64,
+    pub max_queue_latency: u64,
+}
+
+impl BatchStats {
+    fn record(&mut self, batch: &[Job], now: Instant) {
+        self.nb_batch += 1;
+        for job in batch.iter() {
+            let queue_latency: u64 = now.duration_since(job.enqueued).as_nanos() as u64;
+            self.nb_request += 1;
+            self.nb_query += job.search.get_nb_query() as u64;
+            self.total_queue_latency += queue_latency;
+            self.max_queue_latency = self.max_queue_latency.max(queue_latency);
+        }
+    }
+
+    /// mean number of queries by batch
+    pub fn mean_batch_size(&self) -> f64 {
+        self.nb_query as f64 / self.nb_batch.max(1) as f64
+    }
+
+    /// mean time a request waited for its batch, in ns
+    pub fn mean_queue_latency(&self) -> f64 {
+        self.total_queue_latency as f64 / self.nb_request.max(1) as f64
+    }
+}
+
+/// a request waiting for its batch
+struct Job {
+    search: PreparedSearch,
+    enqueued: Instant,
+    reply: oneshot::Sender<Result<PredictResponse, SearchError>>,
+}
+
+struct Queue {
+    jobs: VecDeque<Job>,
+    /// number of queries of jobs
+    nb_query: usize,
+    /// no job comes anymore, the workers run the jobs left and stop
+    closed: bool,
+    stats: BatchStats,
+}
+
+/// state shared by the Batcher and its workers
+struct Shared {
+    searcher: Arc<Searcher>,
+    config: BatchConfig,
+    queue: Mutex<Queue>,
+    available: Condvar,
+}
+
+impl Shared {
+    /// waits for the next batch, None once closed and empty
+    fn next_batch(&self) -> Option<Vec<Job>> {
+        let mut queue: MutexGuard<Queue> = self.queue.lock();
+
+        loop {
+            // another worker may have taken the jobs while this one waited
+            let first: Instant = match queue.jobs.front() {
+                Some(job) => job.enqueued,
+                None if queue.closed => return None,
+                None => {
+                    self.available.wait(&mut queue);
+                    continue;
+                },
+            };
+
+            let deadline: Instant = first + self.config.max_wait;
+            if queue.nb_query >= self.config.max_batch_size
+                || queue.closed
+                || Instant::now() >= deadline
+            {
+                break;
+            }
+            self.available.wait_until(&mut queue, deadline);
+        }
+
+        let mut batch: Vec<Job> = Vec::new();
+        let mut nb_query: usize = 0;
+        while let Some(job) = queue.jobs.front() {
+            let size: usize = job.search.get_nb_query();
+            if !batch.is_empty() && nb_query + size > self.config.max_batch_size {
+                break;
+            }
+            nb_query += size;
+            batch.push(queue.jobs.pop_front().unwrap());
+        }
+        queue.nb_query -= nb_query;
+        queue.stats.record(&batch, Instant::now());
+
+        // the jobs left are for another worker
+        if !queue.jobs.is_empty() {
+            self.available.notify_one();
+        }
+
+        Some(batch)
+    }
+
+    // end of next_batch
+
+    /// embeds & searches a batch, and sends its responses
+    fn run(&self, batch: Vec<Job>) {
+        let now: Instant = Instant::now();
+        let mut searches: Vec<PreparedSearch> = Vec::with_capacity(batch.len());
+        let mut replies: Vec<(oneshot::Sender<Result<PredictResponse, SearchError>>, u64)> =
+            Vec::with_capacity(batch.len());
+        for job in batch.into_iter() {
+            searches.push(job.search);
+            replies.push((job.reply, now.duration_since(job.enqueued).as_nanos() as u64));
+        }
+        log::debug!("batch of {} requests", searches.len());
+
+        // errors of the model are answered by Embedder::encode, the release build aborts on panic
+        let results: Vec<Result<PredictResponse, SearchError>> =
+            self.searcher.search_batch(searches);
+
+        for ((reply, queue_latency), result) in replies.into_iter().zip(results) {
+            // the request may have been cancelled, its response is dropped
+            _ = reply.send(result.map(|mut response: PredictResponse| {
+                response.queue_latency = queue_latency;
+                response
+            }));
+        }
+    }
+}
+
+/// Queues the requests of the server and runs them in batches, see the module documentation.
+/// The workers stop once the Batcher is dropped and the requests queued are answered.
+pub struct Batcher {
+    shared: Arc<Shared>,
+}
+
+impl Batcher {
+    /// starts one worker by model of searcher
+    pub fn new(searcher: Arc<Searcher>, config: BatchConfig) -> Self {
+        let nb_worker: usize = searcher.get_model_pool().get_size();
+        let shared: Arc<Shared> = Arc::new(Shared {
+            searcher,
+            config: BatchConfig { max_batch_size: config.max_batch_size.max(1), ..config },
+            queue: Mutex::new(Queue {
+                jobs: VecDeque::new(),
+                nb_query: 0,
+                closed: false,
+                stats: BatchStats::default(),
+            }),
+            available: Condvar::new(),
+        });
+
+        for i in 0..nb_worker {
+            let shared: Arc<Shared> = Arc::clone(&shared);
+            thread::Builder::new()
+                .name(format!("batch-{}", i))
+                .spawn(move || {
+                    while let Some(batch) = shared.next_batch() {
+                        shared.run(batch);
+                    }
+                })
+                .expect("cannot start a batch worker");
+        }
+
+        Batcher { shared }
+    }
+
+    pub fn get_searcher(&self) -> &Searcher {
+        &self.shared.searcher
+    }
+
+    pub fn get_stats(&self) -> BatchStats {
+        self.shared.queue.lock().stats.clone()
+    }
+
+    /// Queues request and waits for its response. An invalid request is answered at once, see
+    /// Searcher::prepare. The request is prepared on the blocking pool, parsing the predicate and
+    /// building the filters must not run on the executor.
+    pub async fn search(&self, request: PredictRequest) -> Result<PredictResponse, SearchError> {
+        let shared: Arc<Shared> = Arc::clone(&self.shared);
+        let search: PreparedSearch =
+            tokio::task::spawn_blocking(move || shared.searcher.prepare(&request))
+                .await
+                .map_err(|e: JoinError| {
+                    SearchError::Internal(format!("prepare failed : {}", e))
+                })??;
+        let (reply, response) = oneshot::channel::<Result<PredictResponse, SearchError>>();
+
+        {
+            let mut queue: MutexGuard<Queue> = self.shared.queue.lock();
+            queue.nb_query += search.get_nb_query();
+            queue
+                .jobs
+                .push_back(Job { search, enqueued: Instant::now(), reply });
+        }
+        // wakes the workers waiting for a first job and the one waiting for its batch to fill
+        self.shared.available.notify_all();
+
+        response
+            .await
+            .map_err(|_| SearchError::Internal(String::from("the batch worker stopped")))?
+    }
+} // end of impl Batcher
+
+impl Drop for Batcher {
+    fn drop(&mut self) {
+        let stats: BatchStats = {
+            let mut queue: MutexGuard<Queue> = self.shared.queue.lock();
+            queue.closed = true;
+            queue.stats.clone()
+        };
+        self.shared.available.notify_all();
+
+        log::info!(
+            "{} batches of {:.1} queries, queue latency mean {:.0} us max {} us",
+            stats.nb_batch,
+            stats.mean_batch_size(),
+            stats.mean_queue_latency() / 1e3,
+            stats.max_queue_latency / 1_000
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::task::JoinHandle;
+
+    use super::*;
+    use crate::search::{Embedder, ModelPool, Pool, SearchLimits};
+    use crate::ss::Features;
+    use crate::testing::FakeIndex;
+
+    /// a model recording the number of queries of each call, the calls wait until the gate opens
+    struct GatedModel {
+        calls: Arc<Mutex<Vec<usize>>>,
+        gate: Arc<(Mutex<bool>, Condvar)>,
+    }
+
+    impl Embedder for GatedModel {
+        fn encode(&self, queries: &[String]) -> Result<Vec<Vec<f32>>, SearchError> {
+            self.calls.lock().push(queries.len());
+            let mut open: MutexGuard<bool> = self.gate.0.lock();
+            while !*open {
+                self.gate.1.wait(&mut open);
+            }
+            Ok(queries.iter().map(|_| vec![0.5; 4]).collect())
+        }
+    }
+
+    fn request(nb_query: usize, k: i32) -> PredictRequest {
+        PredictRequest {
+            features: vec![Features { query: String::from("school life") }; nb_query],
+            k,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_batcher() {
+        let calls: Arc<Mutex<Vec<usize>>> = Arc::new(Mutex::new(Vec::new()));
+        let gate: Arc<(Mutex<bool>, Condvar)> = Arc::new((Mutex::new(false), Condvar::new()));
+        let models: ModelPool = Pool::new(vec![Box::new(GatedModel {
+            calls: Arc::clone(&calls),
+            gate: Arc::clone(&gate),
+        }) as Box<dyn Embedder>]);
+        let searcher: Searcher = Searcher::new(models, SearchLimits::default());
+        searcher.set_index(Arc::new(FakeIndex::range(100, 4, 1.)));
+
+        let max_wait: Duration = Duration::from_millis(1);
+        let batcher: Arc<Batcher> =
+            Arc::new(Batcher::new(Arc::new(searcher), BatchConfig { max_batch_size: 4, max_wait }));
+        let spawn = |k: i32| -> JoinHandle<Result<PredictResponse, SearchError>> {
+            let batcher: Arc<Batcher> = Arc::clone(&batcher);
+            tokio::spawn(async move { batcher.search(request(1, k)).await })
+        };
+
+        // the worker is held in the model by a first request while the others are queued
+        let mut handles: Vec<JoinHandle<Result<PredictResponse, SearchError>>> = vec![spawn(1)];
+        while calls.lock().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        handles.extend((1..6).map(|i: i32| spawn(i + 1)));
+        while batcher.shared.queue.lock().jobs.len() < 5 {
+            tokio::task::yield_now().await;
+        }
+        *gate.0.lock() = true;
+        gate.1.notify_all();
+
+        // the queued requests share the calls of the model, each gets its own hits
+        for (i, handle) in handles.into_iter().enumerate() {
+            let response: PredictResponse = handle.await.unwrap().unwrap();
+            assert_eq!(response.indices.len(), 1);
+            assert_eq!(response.indices[0].hits.len(), i + 1);
+            assert!(response.batch_size >= 1 && response.batch_size <= 4);
+        }
+        assert_eq!(*calls.lock(), vec![1, 4, 1]);
+        let stats: BatchStats = batcher.get_stats();
+        assert_eq!((stats.nb_batch, stats.nb_request, stats.nb_query), (3, 6, 6));
+
+        // a request larger than a batch is run alone, an invalid one is answered at once
+        let response: PredictResponse = batcher.search(request(6, 2)).await.unwrap();
+        assert_eq!((response.indices.len(), response.batch_size), (6, 6));
+        let e: SearchError = batcher.search(request(1, -1)).await.unwrap_err();
+        assert!(matches!(e, SearchError::InvalidArgument(_)));
+        assert_eq!(batcher.get_stats().nb_request, 7);
+    } // end of test_batcher
+}
@@ -1,57 +1,72 @@
 use std::sync::mpsc;
 use std::time::{Duration, Instant};
-use std::{env, process};
 
 use anyhow::Result;
+use clap::Parser;
+use semantic_search::config::{Config, ConfigArgs};
 use semantic_search::ss::inference_client::InferenceClient;
-use semantic_search::ss::{Features, PredictRequest, PredictResponse};
+use semantic_search::ss::{Features, Hit, PredictRequest, PredictResponse};
 use semantic_search::utils::log_stats;
 
-#[derive(Debug, Clone)]
-struct Config {
+/// benchmark of the gRPC server : num_users users send num_iters requests of bs queries each
+#[derive(Parser, Debug, Clone)]
+struct BenchConfig {
+    /// number of concurrent users
     u: usize,
+    /// number of requests by user
     n: usize,
+    /// number of queries by request
     bs: usize,
+    /// number of hits by query
     k: i32,
-}
-impl Config {
-    fn new(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 5 {
-            return Err("not enough arguments");
-        }
-
-        let u: usize = args[1].parse().unwrap();
-        let n: usize = args[2].parse().unwrap();
-        let bs: usize = args[3].parse().unwrap();
-        let k: i32 = args[4].parse().unwrap();
-
-        Ok(Config { u, n, bs, k })
-    }
+    #[command(flatten)]
+    settings: ConfigArgs,
 }
 
 #[derive(Default, Debug)]
 struct Metrics {
     model_lat: Vec<u64>,
     search_lat: Vec<u64>,
+    queue_lat: Vec<u64>,
     total_lat: Vec<u64>,
 }
 
-async fn execute(config: &Config) -> Result<Metrics> {
+async fn execute(config: &BenchConfig, settings: &Config) -> Result<Metrics> {
     let mut client: InferenceClient<tonic::transport::Channel> =
-        InferenceClient::connect("http://127.0.0.1:50051").await?;
+        InferenceClient::connect(format!("http://{}", settings.addr))
+            .await?
+            .max_decoding_message_size(settings.max_message_size)
+            .max_encoding_message_size(settings.max_message_size);
 
     let requests: PredictRequest = PredictRequest {
         features: vec![Features { query: "The story about the school life".to_owned() }; config.bs],
         k: config.k,
+        ..Default::default()
     };
 
-    // warm-up 11 times to load model & index files on the server-side
-    for _ in 0..11 {
+    // index & models are loaded at server start, warm-up only the inference
+    let response: PredictResponse = client.predict(requests.clone()).await?.into_inner();
+    if let Some(index) = response.indices.first() {
+        let hits: Vec<String> = index
+            .hits
+            .iter()
+            .map(|hit: &Hit| {
+                if hit.key.is_empty() {
+                    format!("{}:{:.4}", hit.id, hit.distance)
+                } else {
+                    format!("{}:{:.4}", hit.key, hit.distance)
+                }
+            })
+            .collect();
+        println!("hits (key or id:distance) : {}", hits.join(" "));
+    }
+    for _ in 0..2 {
         _ = client.predict(requests.clone()).await?;
     }
 
     let mut model_lat: Vec<u64> = vec![0u64; config.n];
     let mut search_lat: Vec<u64> = vec![0u64; config.n];
+    let mut queue_lat: Vec<u64> = vec![0u64; config.n];
     let mut total_lat: Vec<u64> = vec![0u64; config.n];
 
     for i in 1..config.n {
@@ -61,20 +76,16 @@ async fn execute(config: &Config) -> Result<Metrics> {
         total_lat[i] = start.elapsed().as_nanos() as u64;
         model_lat[i] = response.model_latency;
         search_lat[i] = response.search_latency;
+        queue_lat[i] = response.queue_latency;
     }
 
-    Ok(Metrics { model_lat, search_lat, total_lat })
+    Ok(Metrics { model_lat, search_lat, queue_lat, total_lat })
 }
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 8)]
 async fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-
-    let config: Config = Config::new(&args).unwrap_or_else(|err: &str| {
-        println!("Problem parsing arguments: {}", err);
-        println!("Usage: client num_users num_iters bs k");
-        process::exit(1);
-    });
+    let config: BenchConfig = BenchConfig::parse();
+    let settings: Config = Config::load(&config.settings)?;
 
     println!(
         "num_users : {}, num_iters : {}, bs : {}, k : {}",
@@ -84,11 +95,12 @@ async fn main() -> Result<()> {
     let (tx, rx) = mpsc::channel::<Metrics>();
 
     for _ in 0..config.u {
-        let config: Config = config.clone();
+        let config: BenchConfig = config.clone();
+        let settings: Config = settings.clone();
         let tx: mpsc::Sender<Metrics> = tx.clone();
 
         tokio::spawn(async move {
-            let metrics: Metrics = execute(&config).await.expect("ERROR!");
+            let metrics: Metrics = execute(&config, &settings).await.expect("ERROR!");
             tx.send(metrics).unwrap();
         });
     }
@@ -98,6 +110,7 @@ async fn main() -> Result<()> {
         let result: Metrics = rx.recv().unwrap();
         metrics.model_lat.extend(result.model_lat.iter());
         metrics.search_lat.extend(result.search_lat.iter());
+        metrics.queue_lat.extend(result.queue_lat.iter());
         metrics.total_lat.extend(result.total_lat.iter());
     }
 
@@ -108,9 +121,10 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-fn report(config: &Config, metrics: &Metrics) {
+fn report(config: &BenchConfig, metrics: &Metrics) {
     println!("REPORT =====================================================================");
     log_stats("total", config.n, config.bs, &metrics.total_lat);
     log_stats("model", config.n, config.bs, &metrics.model_lat);
     log_stats("search", config.n, config.bs, &metrics.search_lat);
+    log_stats("queue", config.n, config.bs, &metrics.queue_lat);
 }
@@ -0,0 +1,439 @@
+//! Collections : indexes served under a name by one server, each loaded from its own dump with its
+//! own type of data and distance. Requests not naming a collection search the default one.
+//!
+//! The index of a collection can be swapped while requests run : each search holds the index it
+//! started with, the previous index is dropped once the last of them ends. The metadata of the
+//! documents, their external keys and the store of their text, dumped next to the index, are
+//! swapped with it.
+
+use std::io;
+use std::sync::Arc;
+use std::time::Instant;
+
+use arc_swap::ArcSwap;
+use hashbrown::HashMap;
+use parking_lot::{Mutex, MutexGuard, RwLock, RwLockWriteGuard};
+
+use crate::documents::DocumentStore;
+use crate::error::SearchError;
+use crate::hnsw_index::hnswio::Description;
+use crate::index::{
+    check_compatible, load_configured_index, load_dump_description, IndexConfig, SearchIndex,
+};
+use crate::keys::KeyMap;
+use crate::metadata::{MetadataFilter, MetadataStore};
+use crate::predicate::Predicate;
+use crate::ss::{CollectionInfo, ReloadIndexResponse};
+
+/// the index of a collection, the metadata, the keys and the text of its documents, if dumped
+/// with it
+pub struct CollectionIndex {
+    pub index: Arc<dyn SearchIndex>,
+    pub metadata: Option<Arc<MetadataStore>>,
+    pub keys: Option<Arc<KeyMap>>,
+    pub documents: Option<Arc<DocumentStore>>,
+}
+
+impl CollectionIndex {
+    /// loads the dump of config, its metadata, keys and documents, which must describe the points
+    /// of the index
+    pub fn load(config: &IndexConfig) -> io::Result<Self> {
+        let index: Arc<dyn SearchIndex> = Arc::from(load_configured_index(config)?);
+        let metadata: Option<MetadataStore> = MetadataStore::load(&config.dataset)?;
+        if let Some(metadata) = metadata.as_ref() {
+            if metadata.get_nb_point() != index.get_nb_point() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "metadata of {} has {} documents, the index {} points",
+                        config.dataset,
+                        metadata.get_nb_point(),
+                        index.get_nb_point()
+                    ),
+                ));
+            }
+        }
+
+        let keys: Option<KeyMap> = KeyMap::load(&config.dataset)?;
+        if let Some(keys) = keys.as_ref() {
+            if keys.len() != index.get_nb_point() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "keys of {} map {} documents, the index has {} points",
+                        config.dataset,
+                        keys.len(),
+                        index.get_nb_point()
+                    ),
+                ));
+            }
+        }
+
+        let documents: Option<DocumentStore> = DocumentStore::load(&config.dataset)?;
+        if let Some(documents) = documents.as_ref() {
+            if documents.len() != index.get_nb_point() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "document store of {} has {} documents, the index {} points",
+                        config.dataset,
+                        documents.len(),
+                        index.get_nb_point()
+                    ),
+                ));
+            }
+        }
+
+        Ok(CollectionIndex {
+            index,
+            metadata: metadata.map(Arc::new),
+            keys: keys.map(Arc::new),
+            documents: documents.map(Arc::new),
+        })
+    }
+
+    /// the filter of predicate, FailedPrecondition without metadata, InvalidArgument if predicate
+    /// does not apply to the fields of the metadata
+    pub fn compile_filter(&self, predicate: &Predicate) -> Result<MetadataFilter, SearchError> {
+        let metadata: &Arc<MetadataStore> = self.metadata.as_ref().ok_or_else(|| {
+            SearchError::FailedPrecondition(format!(
+                "{} has no metadata to filter on",
+                self.index.name()
+            ))
+        })?;
+
+        metadata
+            .compile(predicate)
+            .map_err(|e: String| SearchError::InvalidArgument(format!("invalid filter : {}", e)))
+    }
+
+    /// the store of the documents, to return their text and fields. FailedPrecondition without
+    /// store, InvalidArgument for a field it does not have.
+    pub fn get_documents(&self, fields: &[String]) -> Result<Arc<DocumentStore>, SearchError> {
+        let documents: &Arc<DocumentStore> = self.documents.as_ref().ok_or_else(|| {
+            SearchError::FailedPrecondition(format!(
+                "{} has no stored documents",
+                self.index.name()
+            ))
+        })?;
+        if let Some(field) = fields
+            .iter()
+            .find(|field: &&String| !documents.get_fields().contains(field))
+        {
+            return Err(SearchError::InvalidArgument(format!(
+                "documents have no field {}, stored fields : {}",
+                field,
+                documents.get_fields().join(", ")
+            )));
+        }
+
+        Ok(Arc::clone(documents))
+    }
+
+    /// fields of the metadata, as name:type
+    pub fn get_fields(&self) -> Vec<String> {
+        match self.metadata.as_ref() {
+            Some(metadata) => metadata
+                .get_fields()
+                .iter()
+                .map(|(name, field_type)| format!("{}:{}", name, field_type))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+} // end of impl CollectionIndex
+
+/// an index served under a name, with the dump it is reloaded from
+pub struct Collection {
+    name: String,
+    /// the dump of the index served, updated by reloads from another dump
+    config: RwLock<IndexConfig>,
+    index: ArcSwap<CollectionIndex>,
+    /// held for the time of a reload, reloads do not run concurrently
+    reloading: Mutex<()>,
+}
+
+impl Collection {
+    /// a collection of index, without metadata
+    pub fn new(name: &str, config: IndexConfig, index: Arc<dyn SearchIndex>) -> Self {
+        Collection::with_metadata(
+            name,
+            config,
+            CollectionIndex { index, metadata: None, keys: None, documents: None },
+        )
+    }
+
+    pub fn with_metadata(name: &str, config: IndexConfig, index: CollectionIndex) -> Self {
+        Collection {
+            name: name.to_string(),
+            config: RwLock::new(config),
+            index: ArcSwap::from_pointee(index),
+            reloading: Mutex::new(()),
+        }
+    }
+
+    /// loads the dump of config and its metadata, see load_configured_index
+    pub fn load(name: &str, config: IndexConfig) -> io::Result<Self> {
+        let index: CollectionIndex = CollectionIndex::load(&config)?;
+        log::info!("collection {} : {}", name, index.index.name());
+        if index.metadata.is_some() {
+            log::info!("collection {} : metadata {}", name, index.get_fields().join(", "));
+        }
+        if let Some(keys) = index.keys.as_ref() {
+            log::info!("collection {} : {} keys", name, keys.len());
+        }
+        if let Some(documents) = index.documents.as_ref() {
+            log::info!("collection {} : {} stored documents", name, documents.len());
+        }
+
+        Ok(Collection::with_metadata(name, config, index))
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// the dump the index served was loaded from
+    pub fn get_config(&self) -> IndexConfig {
+        self.config.read().clone()
+    }
+
+    /// the index searched by the requests starting now
+    pub fn get_index(&self) -> Arc<dyn SearchIndex> {
+        Arc::clone(&self.index.load().index)
+    }
+
+    /// the index searched by the requests starting now, with its metadata
+    pub fn get_collection_index(&self) -> Arc<CollectionIndex> {
+        self.index.load_full()
+    }
+
+    /// serves index, without metadata
+    pub fn set_index(&self, index: Arc<dyn SearchIndex>) {
+        self.set_collection_index(CollectionIndex {
+            index,
+            metadata: None,
+            keys: None,
+            documents: None,
+        });
+    }
+
+    pub fn set_collection_index(&self, index: CollectionIndex) {
+        self.index.store(Arc::new(index));
+    }
+
+    /// Loads the dump of config, or the one of the collection, and swaps it with the index
+    /// served. The load blocks the calling thread while the searches go on with the previous
+    /// index. Once swapped, config is the dump of the collection, reloaded by default.
+    /// Errors are FailedPrecondition if a reload already runs or the dump is not compatible with
+    /// the index served, which is kept, Internal if the dump cannot be loaded.
+    pub fn reload(&self, config: Option<IndexConfig>) -> Result<ReloadIndexResponse, SearchError> {
+        let _reloading: MutexGuard<()> = self.reloading.try_lock().ok_or_else(|| {
+            SearchError::FailedPrecondition(format!("a reload of {} is already running", self.name))
+        })?;
+
+        let config: IndexConfig = config.unwrap_or_else(|| self.get_config());
+        let start: Instant = Instant::now();
+        let previous: Arc<dyn SearchIndex> = self.get_index();
+
+        // the description is checked before the long load, and again on the index loaded in case
+        // the dump was rewritten meanwhile
+        let description: Description = load_dump_description(&config.dataset)?;
+        check_replacement(previous.as_ref(), &description)?;
+        let loaded: CollectionIndex = CollectionIndex::load(&config)?;
+        if let Some(description) = loaded.index.get_description() {
+            check_replacement(previous.as_ref(), description)?;
+        }
+
+        let index: Arc<dyn SearchIndex> = Arc::clone(&loaded.index);
+        {
+            let mut served: RwLockWriteGuard<IndexConfig> = self.config.write();
+            self.set_collection_index(loaded);
+            *served = config;
+        }
+        let load_latency: u64 = start.elapsed().as_nanos() as u64;
+        log::info!(
+            "collection {} : {} swapped in, {} points",
+            self.name,
+            index.name(),
+            index.get_nb_point()
+        );
+
+        Ok(ReloadIndexResponse {
+            name: index.name(),
+            nb_point: index.get_nb_point() as u64,
+            previous_name: previous.name(),
+            previous_nb_point: previous.get_nb_point() as u64,
+            load_latency,
+        })
+    }
+
+    // end of reload
+
+    /// description of the collection and of its index
+    pub fn info(&self, is_default: bool) -> CollectionInfo {
+        let collection_index: Arc<CollectionIndex> = self.get_collection_index();
+        let index: &dyn SearchIndex = collection_index.index.as_ref();
+
+        CollectionInfo {
+            name: self.name.clone(),
+            index: index.name(),
+            nb_point: index.get_nb_point() as u64,
+            dimension: index.get_dimension() as u32,
+            dataset: self.config.read().dataset.clone(),
+            default: is_default,
+            fields: collection_index.get_fields(),
+            documents: collection_index.documents.is_some(),
+            document_fields: collection_index
+                .documents
+                .as_ref()
+                .map_or_else(Vec::new, |documents: &Arc<DocumentStore>| {
+                    documents.get_fields().to_vec()
+                }),
+        }
+    }
+} // end of impl Collection
+
+/// checks that the dump described by new can replace the index served
+fn check_replacement(served: &dyn SearchIndex, new: &Description) -> Result<(), SearchError> {
+    let checked: Result<(), String> = match served.get_description() {
+        Some(description) => check_compatible(description, new),
+        None if served.get_dimension() != new.dimension => Err(format!(
+            "dimension {} differs from the one served {}",
+            new.dimension,
+            served.get_dimension()
+        )),
+        None => Ok(()),
+    };
+
+    checked.map_err(|e: String| {
+        SearchError::FailedPrecondition(format!("cannot replace {} : {}", served.name(), e))
+    })
+}
+
+/// the collections of a server by name, and the name of the default one
+pub struct Collections {
+    default: String,
+    collections: RwLock<HashMap<String, Arc<Collection>>>,
+}
+
+impl Collections {
+    /// no collection, requests not naming one will search the collection default once added
+    pub fn new(default: &str) -> Self {
+        Collections { default: default.to_string(), collections: RwLock::new(HashMap::new()) }
+    }
+
+    pub fn get_default_name(&self) -> &str {
+        &self.default
+    }
+
+    /// the collection name, the default one if name is empty.
+    /// Errors are Unavailable if the default collection is not loaded, NotFound for another one.
+    pub fn get(&self, name: &str) -> Result<Arc<Collection>, SearchError> {
+        let name: &str = if name.is_empty() { &self.default } else { name };
+
+        match self.collections.read().get(name) {
+            Some(collection) => Ok(Arc::clone(collection)),
+            None if name == self.default => {
+                Err(SearchError::Unavailable(String::from("no index is loaded")))
+            },
+            None => Err(SearchError::NotFound(format!("no collection {}", name))),
+        }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.collections.read().contains_key(name)
+    }
+
+    /// adds collection, FailedPrecondition if one has the same name
+    pub fn insert(&self, collection: Collection) -> Result<Arc<Collection>, SearchError> {
+        let mut collections: RwLockWriteGuard<HashMap<String, Arc<Collection>>> =
+            self.collections.write();
+        if collections.contains_key(collection.get_name()) {
+            return Err(SearchError::FailedPrecondition(format!(
+                "collection {} is already loaded",
+                collection.get_name()
+            )));
+        }
+
+        let collection: Arc<Collection> = Arc::new(collection);
+        collections.insert(collection.get_name().to_string(), Arc::clone(&collection));
+        Ok(collection)
+    }
+
+    /// removes the collection name, its index is dropped once the searches running on it end.
+    /// The default collection cannot be removed.
+    pub fn remove(&self, name: &str) -> Result<Arc<Collection>, SearchError> {
+        if name.is_empty() || name == self.default {
+            return Err(SearchError::FailedPrecondition(format!(
+                "the default collection {} cannot be unloaded",
+                self.default
+            )));
+        }
+
+        self.collections
+            .write()
+            .remove(name)
+            .ok_or_else(|| SearchError::NotFound(format!("no collection {}", name)))
+    }
+
+    /// the collections sorted by name
+    pub fn list(&self) -> Vec<Arc<Collection>> {
+        let mut collections: Vec<Arc<Collection>> =
+            self.collections.read().values().map(Arc::clone).collect();
+        collections.sort_by(|a: &Arc<Collection>, b: &Arc<Collection>| a.name.cmp(&b.name));
+        collections
+    }
+} // end of impl Collections
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeIndex;
+
+    /// an index of nb_point points, without neighbours
+    fn collection(name: &str, nb_point: usize) -> Collection {
+        Collection::new(
+            name,
+            IndexConfig::default(),
+            Arc::new(FakeIndex { nb_point, dimension: 4, ..FakeIndex::default() }),
+        )
+    }
+
+    #[test]
+    fn test_collections() {
+        let collections: Collections = Collections::new("news");
+        assert!(matches!(collections.get(""), Err(SearchError::Unavailable(_))));
+
+        collections.insert(collection("news", 10)).unwrap();
+        collections.insert(collection("docs", 20)).unwrap();
+        assert!(matches!(
+            collections.insert(collection("docs", 30)),
+            Err(SearchError::FailedPrecondition(_))
+        ));
+
+        assert_eq!(collections.get("").unwrap().get_name(), "news");
+        assert_eq!(collections.get("docs").unwrap().get_index().get_nb_point(), 20);
+        assert!(matches!(collections.get("tickets"), Err(SearchError::NotFound(_))));
+        let names: Vec<String> = collections
+            .list()
+            .iter()
+            .map(|collection: &Arc<Collection>| collection.get_name().to_string())
+            .collect();
+        assert_eq!(names, vec!["docs", "news"]);
+
+        // a search holding the collection keeps it once removed
+        let docs: Arc<Collection> = collections.get("docs").unwrap();
+        collections.remove("docs").unwrap();
+        assert!(!collections.contains("docs"));
+        assert_eq!(docs.get_index().get_nb_point(), 20);
+        for name in ["", "news"] {
+            assert!(matches!(collections.remove(name), Err(SearchError::FailedPrecondition(_))));
+        }
+
+        // a reload while another one runs
+        let news: Arc<Collection> = collections.get("news").unwrap();
+        let _reloading: MutexGuard<()> = news.reloading.lock();
+        assert!(matches!(news.reload(None), Err(SearchError::FailedPrecondition(_))));
+    } // end of test_collections
+}
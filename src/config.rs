@@ -0,0 +1,416 @@
+//! Configuration shared by the binaries.
+//!
+//! Each setting is read, by decreasing priority, from the command line flags, the environment
+//! variables (SS_*), the TOML file given by --config (or SS_CONFIG), and the defaults below. A TOML
+//! file sets settings with the names of the fields of [Config], all optional :
+//!
+//! ```toml
+//! addr = "0.0.0.0:50051"
+//! index = "news"
+//! collections = ["tickets", "docs"]
+//! index_type = "quantize"
+//! default_ef = 50
+//! threads = 8
+//! ```
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+
+use crate::batch::BatchConfig;
+use crate::hnsw_index::rerank::RERANK_FACTOR;
+use crate::index::{FilterPolicy, IndexConfig};
+use crate::keys::KeySpec;
+use crate::metadata::FieldSpec;
+use crate::search::SearchLimits;
+use crate::service::ServeOptions;
+
+/// type of vectors of an index : f32 (full) or quantized to i8
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexType {
+    Full,
+    Quantize,
+}
+
+fn dump_name(index: &str, index_type: IndexType) -> String {
+    match index_type {
+        IndexType::Full => index.to_string(),
+        IndexType::Quantize => format!("{}_q", index),
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// address the server listens on and the client connects to
+    pub addr: String,
+    /// basename of the dumps : the f32 index is index.hnsw.*, the i8 one index_q.hnsw.*. It is the
+    /// default collection of the server.
+    pub index: String,
+    /// other collections served, by basename of their dumps as index
+    pub collections: Vec<String>,
+    /// which of the two dumps is served
+    pub index_type: IndexType,
+    /// rerank the results of a quantized index with the f32 dump, when it exists
+    pub rerank: bool,
+    /// candidates fetched by result wanted when reranking
+    pub rerank_factor: usize,
+    /// directory of the local model, the model is downloaded if it does not exist
+    pub model_dir: String,
+    /// number of model instances shared by the requests of the server
+    pub model_pool: usize,
+    /// csv file of the documents, the text is in the first column
+    pub data: String,
+    /// metadata of the documents dumped with the index, from the columns of data of the same
+    /// name, as name:type with a type of tag, int or timestamp
+    pub metadata: Vec<String>,
+    /// column of data with the external keys of the documents, as column:type with a type of str
+    /// or u64. Empty for no keys, the documents are then known by their row.
+    pub key: String,
+    /// columns of data stored with the text of the documents by the embedding binary, returned
+    /// with the hits of the requests asking them
+    pub document_fields: Vec<String>,
+    /// k of requests asking k = 0, and largest k accepted
+    pub default_k: usize,
+    pub max_k: usize,
+    /// ef of requests asking ef = 0, and largest ef accepted
+    pub default_ef: usize,
+    pub max_ef: usize,
+    /// largest number of allow_ids + deny_ids of a request
+    pub max_filter_ids: usize,
+    /// filtered searches expected to accept less than this fraction of the index are searched by
+    /// exact scan of the documents accepted, the others widen ef up to max_filter_ef until k hits
+    /// are found
+    pub scan_selectivity: f32,
+    pub max_filter_ef: usize,
+    /// number of threads of the rayon pool running searches, 0 for one by core
+    pub threads: usize,
+    /// largest gRPC message sent or received, in bytes
+    pub max_message_size: usize,
+    /// serve the Admin service, which reloads the index
+    pub admin: bool,
+    /// concurrent Predict requests are embedded and searched in batches of up to max_batch_size
+    /// queries, the first request of a batch waits up to max_batch_wait_us for the batch to fill
+    pub max_batch_size: usize,
+    pub max_batch_wait_us: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let limits: SearchLimits = SearchLimits::default();
+        let batch: BatchConfig = BatchConfig::default();
+
+        Config {
+            addr: String::from("127.0.0.1:50051"),
+            index: String::from("news"),
+            collections: Vec::new(),
+            index_type: IndexType::Quantize,
+            rerank: true,
+            rerank_factor: RERANK_FACTOR,
+            model_dir: String::from("models"),
+            model_pool: 2,
+            data: String::from("./data/ag_news.csv"),
+            metadata: Vec::new(),
+            key: String::new(),
+            document_fields: Vec::new(),
+            default_k: limits.default_k,
+            max_k: limits.max_k,
+            default_ef: limits.default_ef,
+            max_ef: limits.max_ef,
+            max_filter_ids: limits.max_filter_ids,
+            scan_selectivity: limits.filter_policy.scan_selectivity,
+            max_filter_ef: limits.filter_policy.max_ef,
+            threads: 0,
+            max_message_size: 4 * 1024 * 1024,
+            admin: false,
+            max_batch_size: batch.max_batch_size,
+            max_batch_wait_us: batch.max_wait.as_micros() as u64,
+        }
+    }
+}
+
+/// flags of the settings, flattened in the arguments of each binary. A flag not given falls back
+/// on its environment variable.
+#[derive(Debug, Clone, Default, Args)]
+pub struct ConfigArgs {
+    /// TOML file of settings
+    #[arg(long, env = "SS_CONFIG")]
+    pub config: Option<PathBuf>,
+    #[arg(long, env = "SS_ADDR")]
+    pub addr: Option<String>,
+    #[arg(long, env = "SS_INDEX")]
+    pub index: Option<String>,
+    /// comma separated
+    #[arg(long, env = "SS_COLLECTIONS", value_delimiter = ',')]
+    pub collections: Option<Vec<String>>,
+    #[arg(long, env = "SS_INDEX_TYPE")]
+    pub index_type: Option<IndexType>,
+    #[arg(long, env = "SS_RERANK")]
+    pub rerank: Option<bool>,
+    #[arg(long, env = "SS_RERANK_FACTOR")]
+    pub rerank_factor: Option<usize>,
+    #[arg(long, env = "SS_MODEL_DIR")]
+    pub model_dir: Option<String>,
+    #[arg(long, env = "SS_MODEL_POOL")]
+    pub model_pool: Option<usize>,
+    #[arg(long, env = "SS_DATA")]
+    pub data: Option<String>,
+    /// comma separated
+    #[arg(long, env = "SS_METADATA", value_delimiter = ',')]
+    pub metadata: Option<Vec<String>>,
+    #[arg(long, env = "SS_KEY")]
+    pub key: Option<String>,
+    /// comma separated
+    #[arg(long, env = "SS_DOCUMENT_FIELDS", value_delimiter = ',')]
+    pub document_fields: Option<Vec<String>>,
+    #[arg(long, env = "SS_K")]
+    pub default_k: Option<usize>,
+    #[arg(long, env = "SS_MAX_K")]
+    pub max_k: Option<usize>,
+    #[arg(long, env = "SS_EF")]
+    pub default_ef: Option<usize>,
+    #[arg(long, env = "SS_MAX_EF")]
+    pub max_ef: Option<usize>,
+    #[arg(long, env = "SS_MAX_FILTER_IDS")]
+    pub max_filter_ids: Option<usize>,
+    #[arg(long, env = "SS_SCAN_SELECTIVITY")]
+    pub scan_selectivity: Option<f32>,
+    #[arg(long, env = "SS_MAX_FILTER_EF")]
+    pub max_filter_ef: Option<usize>,
+    #[arg(long, env = "SS_THREADS")]
+    pub threads: Option<usize>,
+    #[arg(long, env = "SS_MAX_MESSAGE_SIZE")]
+    pub max_message_size: Option<usize>,
+    #[arg(long, env = "SS_ADMIN")]
+    pub admin: Option<bool>,
+    #[arg(long, env = "SS_MAX_BATCH_SIZE")]
+    pub max_batch_size: Option<usize>,
+    #[arg(long, env = "SS_MAX_BATCH_WAIT_US")]
+    pub max_batch_wait_us: Option<u64>,
+}
+
+impl Config {
+    /// parses a TOML file of settings, missing settings keep their default
+    pub fn from_toml(content: &str) -> anyhow::Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// the configuration of args : the file it names, if any, overridden by its values
+    pub fn load(args: &ConfigArgs) -> anyhow::Result<Self> {
+        let mut config: Config = match args.config.as_ref() {
+            Some(path) => {
+                let content: String = fs::read_to_string(path)
+                    .map_err(|e| anyhow::anyhow!("cannot read config file {:?} : {}", path, e))?;
+                Config::from_toml(&content)?
+            },
+            None => Config::default(),
+        };
+
+        macro_rules! set {
+            ($($field:ident),*) => {
+                $(if let Some(value) = args.$field.clone() {
+                    config.$field = value;
+                })*
+            };
+        }
+        set!(
+            addr,
+            index,
+            collections,
+            index_type,
+            rerank,
+            rerank_factor,
+            model_dir,
+            model_pool,
+            data,
+            metadata,
+            key,
+            document_fields,
+            default_k,
+            max_k,
+            default_ef,
+            max_ef,
+            max_filter_ids,
+            scan_selectivity,
+            max_filter_ef,
+            threads,
+            max_message_size,
+            admin,
+            max_batch_size,
+            max_batch_wait_us
+        );
+
+        Ok(config)
+    }
+
+    /// basename of the dump of index_type
+    pub fn dump_name(&self, index_type: IndexType) -> String {
+        dump_name(&self.index, index_type)
+    }
+
+    /// the fields of metadata
+    pub fn metadata_fields(&self) -> anyhow::Result<Vec<FieldSpec>> {
+        self.metadata
+            .iter()
+            .map(|spec: &String| {
+                spec.parse::<FieldSpec>()
+                    .map_err(|e: String| anyhow::anyhow!(e))
+            })
+            .collect()
+    }
+
+    /// the column of the keys, None without keys
+    pub fn key_spec(&self) -> anyhow::Result<Option<KeySpec>> {
+        if self.key.is_empty() {
+            return Ok(None);
+        }
+        self.key
+            .parse::<KeySpec>()
+            .map(Some)
+            .map_err(|e: String| anyhow::anyhow!(e))
+    }
+
+    /// the index served
+    pub fn index_config(&self) -> IndexConfig {
+        self.collection_config(&self.index)
+    }
+
+    /// names of the collections served, the default one first
+    pub fn collection_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = vec![self.index.clone()];
+        for name in self.collections.iter() {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        names
+    }
+
+    /// the index of the collection name, as the one of index
+    pub fn collection_config(&self, name: &str) -> IndexConfig {
+        IndexConfig {
+            dataset: dump_name(name, self.index_type),
+            rerank_dataset: if self.rerank && self.index_type == IndexType::Quantize {
+                Some(dump_name(name, IndexType::Full))
+            } else {
+                None
+            },
+            rerank_factor: self.rerank_factor,
+        }
+    }
+
+    pub fn search_limits(&self) -> SearchLimits {
+        SearchLimits {
+            default_k: self.default_k,
+            max_k: self.max_k,
+            default_ef: self.default_ef,
+            max_ef: self.max_ef,
+            max_filter_ids: self.max_filter_ids,
+            filter_policy: FilterPolicy {
+                scan_selectivity: self.scan_selectivity,
+                max_ef: self.max_filter_ef,
+            },
+        }
+    }
+
+    pub fn serve_options(&self) -> ServeOptions {
+        ServeOptions {
+            max_message_size: self.max_message_size,
+            admin: self.admin,
+            batch: BatchConfig {
+                max_batch_size: self.max_batch_size,
+                max_wait: Duration::from_micros(self.max_batch_wait_us),
+            },
+        }
+    }
+
+    /// sizes the global rayon pool, a no-op with threads = 0. Must be called before any parallel
+    /// search.
+    pub fn init_thread_pool(&self) -> anyhow::Result<()> {
+        if self.threads > 0 {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(self.threads)
+                .build_global()?;
+        }
+        Ok(())
+    }
+} // end of impl Config
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct TestArgs {
+        #[command(flatten)]
+        config: ConfigArgs,
+    }
+
+    #[test]
+    fn test_config_priority() {
+        let path: PathBuf = std::env::temp_dir().join("ss_test_config.toml");
+        fs::write(&path, "index = \"docs\"\ndefault_ef = 50\nindex_type = \"full\"\nthreads = 3\n")
+            .unwrap();
+
+        let args: TestArgs = TestArgs::try_parse_from([
+            "test",
+            "--config",
+            path.to_str().unwrap(),
+            "--default-ef",
+            "64",
+            "--addr",
+            "0.0.0.0:6000",
+        ])
+        .unwrap();
+        let config: Config = Config::load(&args.config).unwrap();
+
+        // flags over file over defaults
+        assert_eq!(config.default_ef, 64);
+        assert_eq!(config.addr, "0.0.0.0:6000");
+        assert_eq!(config.index, "docs");
+        assert_eq!(config.threads, 3);
+        assert_eq!(config.model_dir, Config::default().model_dir);
+
+        let index: IndexConfig = config.index_config();
+        assert_eq!(index.dataset, "docs");
+        assert!(index.rerank_dataset.is_none());
+
+        let config: Config = Config::from_toml("index = \"docs\"").unwrap();
+        let index: IndexConfig = config.index_config();
+        assert_eq!(index.dataset, "docs_q");
+        assert_eq!(index.rerank_dataset.as_deref(), Some("docs"));
+
+        assert!(Config::from_toml("unknown = 1").is_err());
+
+        let args: TestArgs =
+            TestArgs::try_parse_from(["test", "--collections", "tickets,docs,news"]).unwrap();
+        let config: Config = Config::load(&args.config).unwrap();
+        assert_eq!(config.collection_names(), vec!["news", "tickets", "docs"]);
+        assert_eq!(config.collection_config("tickets").dataset, "tickets_q");
+
+        let args: TestArgs =
+            TestArgs::try_parse_from(["test", "--metadata", "label:tag,year:int"]).unwrap();
+        let config: Config = Config::load(&args.config).unwrap();
+        assert_eq!(config.metadata_fields().unwrap().len(), 2);
+        let config: Config = Config::from_toml("metadata = [\"label:float\"]").unwrap();
+        assert!(config.metadata_fields().is_err());
+        assert!(config.key_spec().unwrap().is_none());
+
+        let args: TestArgs = TestArgs::try_parse_from(["test", "--key", "id:u64"]).unwrap();
+        let config: Config = Config::load(&args.config).unwrap();
+        assert_eq!(config.key_spec().unwrap().unwrap().column, "id");
+        let args: TestArgs =
+            TestArgs::try_parse_from(["test", "--document-fields", "label,title"]).unwrap();
+        let config: Config = Config::load(&args.config).unwrap();
+        assert_eq!(config.document_fields, vec!["label", "title"]);
+        let config: Config = Config::from_toml("key = \"id:i32\"").unwrap();
+        assert!(config.key_spec().is_err());
+        fs::remove_file(&path).unwrap();
+    } // end of test_config_priority
+}
@@ -0,0 +1,306 @@
+//! Document store : the text of the documents of an index and some of their fields, by DataId, to
+//! return them with the hits. It is built by the embedding binary from the csv file and dumped
+//! next to the index, in dataset.hnsw.docs. The file is memory mapped, a document is read from the
+//! page it is in, the store is not loaded in memory.
+//!
+//! Layout of the file, integers in little endian :
+//!     - magic (u32), version (u32)
+//!     - number of fields (u32), then each name as its length (u32) and its bytes
+//!     - number of documents n (u64), then n + 1 offsets (u64) of the records in the blob
+//!     - the blob : the record of each document, its text and its fields in the order of the names,
+//!       each value as its length (u32) and its utf8 bytes
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::{fmt, io};
+
+use mmap_rs::{Mmap, MmapOptions};
+
+use crate::hnsw_index::hnsw::DataId;
+
+/// magic of the .hnsw.docs dump
+const MAGIC_DOCUMENTS: u32 = 0x646f_6373;
+/// version of the format of the .hnsw.docs dump
+const DOCUMENTS_VERSION: u32 = 1;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// file of the documents of the dump dataset
+pub fn documents_file(dataset: &str) -> String {
+    format!("{}.hnsw.docs", dataset)
+}
+
+/// Reads the documents of the csv file path, a document by row : its text, the first column, then
+/// the value of each column of fields, empty when the row has none.
+pub fn read_documents(path: &str, fields: &[String]) -> io::Result<Vec<Vec<String>>> {
+    let mut reader: csv::Reader<File> = csv::Reader::from_path(path)?;
+    let headers: csv::StringRecord = reader.headers()?.clone();
+    let positions: Vec<usize> = fields
+        .iter()
+        .map(|field: &String| {
+            headers
+                .iter()
+                .position(|header: &str| header == field)
+                .ok_or_else(|| invalid_data(format!("no column {} in {}", field, path)))
+        })
+        .collect::<io::Result<Vec<usize>>>()?;
+
+    reader
+        .records()
+        .map(|record: csv::Result<csv::StringRecord>| {
+            let record: csv::StringRecord = record?;
+            Ok(std::iter::once(0)
+                .chain(positions.iter().copied())
+                .map(|position: usize| record.get(position).unwrap_or("").to_string())
+                .collect())
+        })
+        .collect()
+}
+
+/// the documents of an index, read from the mmap of their dump
+pub struct DocumentStore {
+    mmap: Mmap,
+    /// names of the fields stored with the text
+    fields: Vec<String>,
+    nb_doc: usize,
+    /// addresses of the offsets and of the blob in the mmap
+    offsets_addr: usize,
+    blob_addr: usize,
+}
+
+impl fmt::Debug for DocumentStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DocumentStore")
+            .field("fields", &self.fields)
+            .field("nb_doc", &self.nb_doc)
+            .finish()
+    }
+}
+
+/// cursor reading the integers of a dump
+struct Reader<'a> {
+    bytes: &'a [u8],
+    addr: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end: usize = self.addr.checked_add(len)?;
+        let bytes: &[u8] = self.bytes.get(self.addr..end)?;
+        self.addr = end;
+        Some(bytes)
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn read_u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    /// a value written as its length and its bytes
+    fn read_str(&mut self) -> Option<&'a str> {
+        let len: usize = self.read_u32()? as usize;
+        std::str::from_utf8(self.take(len)?).ok()
+    }
+}
+
+impl DocumentStore {
+    /// Dumps documents in dataset.hnsw.docs, the document of DataId d is documents[d] : its text
+    /// then a value by field.
+    pub fn dump(dataset: &str, fields: &[String], documents: &[Vec<String>]) -> io::Result<()> {
+        if let Some(d_id) = documents
+            .iter()
+            .position(|document: &Vec<String>| document.len() != fields.len() + 1)
+        {
+            return Err(invalid_data(format!(
+                "document {} has {} values for the text and {} fields",
+                d_id,
+                documents[d_id].len(),
+                fields.len()
+            )));
+        }
+        let value_len = |value: &String| -> io::Result<u32> {
+            u32::try_from(value.len())
+                .map_err(|_| invalid_data(format!("value of {} bytes", value.len())))
+        };
+
+        let path: String = documents_file(dataset);
+        let mut writer: BufWriter<File> = BufWriter::new(File::create(&path)?);
+        writer.write_all(&MAGIC_DOCUMENTS.to_le_bytes())?;
+        writer.write_all(&DOCUMENTS_VERSION.to_le_bytes())?;
+        writer.write_all(&(fields.len() as u32).to_le_bytes())?;
+        for field in fields {
+            writer.write_all(&value_len(field)?.to_le_bytes())?;
+            writer.write_all(field.as_bytes())?;
+        }
+
+        writer.write_all(&(documents.len() as u64).to_le_bytes())?;
+        let mut offset: u64 = 0;
+        writer.write_all(&offset.to_le_bytes())?;
+        for document in documents {
+            offset += document
+                .iter()
+                .map(|value: &String| 4 + value.len() as u64)
+                .sum::<u64>();
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+
+        for value in documents.iter().flatten() {
+            writer.write_all(&value_len(value)?.to_le_bytes())?;
+            writer.write_all(value.as_bytes())?;
+        }
+        writer.flush()
+    }
+
+    // end of dump
+
+    /// the documents dumped with dataset, None if the dump has no documents
+    pub fn load(dataset: &str) -> io::Result<Option<Self>> {
+        let path: String = documents_file(dataset);
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+
+        let file: File = File::open(&path)?;
+        let size: usize = file.metadata()?.len() as usize;
+        if size == 0 {
+            return Err(invalid_data(format!("{} is empty", path)));
+        }
+        let mmap: Mmap = MmapOptions::new(size)
+            .and_then(|options: MmapOptions<'_>| unsafe { options.with_file(&file, 0) }.map())
+            .map_err(|e| invalid_data(format!("cannot map {} : {}", path, e)))?;
+
+        let truncated = || invalid_data(format!("{} is truncated", path));
+        let mut reader: Reader = Reader { bytes: mmap.as_slice(), addr: 0 };
+        if reader.read_u32() != Some(MAGIC_DOCUMENTS) {
+            return Err(invalid_data(format!("{} is not a document store", path)));
+        }
+        let version: u32 = reader.read_u32().ok_or_else(truncated)?;
+        if version != DOCUMENTS_VERSION {
+            return Err(invalid_data(format!(
+                "{} has version {}, expected {}",
+                path, version, DOCUMENTS_VERSION
+            )));
+        }
+
+        let nb_field: u32 = reader.read_u32().ok_or_else(truncated)?;
+        let fields: Vec<String> = (0..nb_field)
+            .map(|_| reader.read_str().map(str::to_string).ok_or_else(truncated))
+            .collect::<io::Result<Vec<String>>>()?;
+
+        let nb_doc: usize = reader.read_u64().ok_or_else(truncated)? as usize;
+        let offsets_addr: usize = reader.addr;
+        let blob_addr: usize = nb_doc
+            .checked_add(1)
+            .and_then(|nb_offset: usize| nb_offset.checked_mul(8))
+            .and_then(|len: usize| len.checked_add(offsets_addr))
+            .filter(|blob_addr: &usize| *blob_addr <= size)
+            .ok_or_else(truncated)?;
+        let store: DocumentStore = DocumentStore { mmap, fields, nb_doc, offsets_addr, blob_addr };
+        if store.get_offset(nb_doc) != Some((size - blob_addr) as u64) {
+            return Err(truncated());
+        }
+
+        Ok(Some(store))
+    }
+
+    // end of load
+
+    /// number of documents, the DataIds stored are 0..len
+    pub fn len(&self) -> usize {
+        self.nb_doc
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nb_doc == 0
+    }
+
+    /// names of the fields stored with the text
+    pub fn get_fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    fn get_offset(&self, rank: usize) -> Option<u64> {
+        let mut reader: Reader = Reader { bytes: self.mmap.as_slice(), addr: self.offsets_addr };
+        reader.take(rank * 8)?;
+        reader.read_u64()
+    }
+
+    /// the values of document d_id, its text then its fields, None if it is not stored
+    pub fn get(&self, d_id: DataId) -> Option<Vec<&str>> {
+        if d_id >= self.nb_doc {
+            return None;
+        }
+        let start: usize = self.blob_addr + self.get_offset(d_id)? as usize;
+        let end: usize = self.blob_addr + self.get_offset(d_id + 1)? as usize;
+        let mut reader: Reader = Reader { bytes: self.mmap.as_slice().get(..end)?, addr: start };
+
+        (0..=self.fields.len()).map(|_| reader.read_str()).collect()
+    }
+
+    pub fn get_text(&self, d_id: DataId) -> Option<&str> {
+        self.get(d_id)?.first().copied()
+    }
+
+    /// the value of the field name of document d_id
+    pub fn get_field(&self, d_id: DataId, name: &str) -> Option<&str> {
+        let rank: usize = self
+            .fields
+            .iter()
+            .position(|field: &String| field == name)?;
+        self.get(d_id)?.get(rank + 1).copied()
+    }
+} // end of impl DocumentStore
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_document_store() {
+        let dir: PathBuf = std::env::temp_dir();
+        let dataset: String = dir.join("ss_test_documents").to_str().unwrap().to_string();
+        let csv_path: PathBuf = dir.join("ss_test_documents.csv");
+        let path: &str = csv_path.to_str().unwrap();
+        let csv: &str =
+            "text,label,id\nschool life,World,a\n\"fed rate, hike\",Business,b\nfinal score,,c\n";
+        fs::write(path, csv).unwrap();
+        let fields: Vec<String> = vec![String::from("label")];
+        let documents: Vec<Vec<String>> = read_documents(path, &fields).unwrap();
+        assert_eq!(documents[1], vec!["fed rate, hike", "Business"]);
+        assert!(read_documents(path, &[String::from("title")]).is_err());
+        fs::remove_file(path).unwrap();
+
+        DocumentStore::dump(&dataset, &fields, &documents).unwrap();
+        let store: DocumentStore = DocumentStore::load(&dataset).unwrap().unwrap();
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.get_fields(), &fields[..]);
+        assert_eq!(store.get(1), Some(vec!["fed rate, hike", "Business"]));
+        assert_eq!(store.get_text(0), Some("school life"));
+        assert_eq!(store.get_field(0, "label"), Some("World"));
+        assert_eq!(store.get_field(2, "label"), Some(""));
+        assert_eq!(store.get_field(2, "title"), None);
+        assert_eq!(store.get(3), None);
+
+        // a truncated dump is refused
+        let file: String = documents_file(&dataset);
+        let bytes: Vec<u8> = fs::read(&file).unwrap();
+        fs::write(&file, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(DocumentStore::load(&dataset).is_err());
+        fs::remove_file(&file).unwrap();
+
+        assert!(DocumentStore::load(&dataset).unwrap().is_none());
+        let e: io::Error =
+            DocumentStore::dump(&dataset, &fields, &[vec![String::from("text")]]).unwrap_err();
+        assert_eq!(e.to_string(), "document 0 has 1 values for the text and 1 fields");
+        assert!(!Path::new(&file).exists());
+    } // end of test_document_store
+}
@@ -1,26 +1,92 @@
-use std::env;
 use std::time::Instant;
 
 use anyhow::Result;
+use clap::Parser;
 #[cfg(feature = "progress")]
 use indicatif::ProgressBar;
 use rayon::prelude::*;
 use rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModel;
+use semantic_search::config::{Config, ConfigArgs, IndexType};
+use semantic_search::documents::{read_documents, DocumentStore};
 use semantic_search::hnsw_index::api::AnnT;
 use semantic_search::hnsw_index::dist::{DistDot, DistHamming};
-use semantic_search::hnsw_index::hnsw::{quantize, Hnsw};
+use semantic_search::hnsw_index::hnsw::{quantize, DataId, Hnsw};
+use semantic_search::keys::{read_keys, Key, KeyMap, KeySpec};
+use semantic_search::metadata::{FieldSpec, MetadataStore};
 use semantic_search::utils::{load_data, load_model};
 
-#[allow(clippy::range_zip_with_len)]
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
+/// embeds the documents and builds the index dumps
+#[derive(Parser)]
+struct Args {
+    /// full : f32 index only, quantize : i8 index only, both : the two indices, the f32 dump is
+    /// then also used to rerank results of the i8 index.
+    #[arg(default_value = "full")]
+    mode: String,
+    #[command(flatten)]
+    config: ConfigArgs,
+}
 
-    let do_quantize: bool = args[1] == "quantize";
+fn main() -> Result<()> {
+    let args: Args = Args::parse();
+    let config: Config = Config::load(&args.config)?;
+    config.init_thread_pool()?;
+
+    let mode: &str = &args.mode;
+    let build_full: bool = mode != "quantize";
+    let do_quantize: bool = mode == "quantize" || mode == "both";
+    println!("build full (f32) : {:?}", build_full);
     println!("do quantize (f32 to i8) : {:?}", do_quantize);
 
-    let model: SentenceEmbeddingsModel = load_model();
+    let model: SentenceEmbeddingsModel = load_model(&config.model_dir);
 
-    let data: Vec<String> = load_data();
+    let data: Vec<String> = load_data(&config.data);
+
+    let fields: Vec<FieldSpec> = config.metadata_fields()?;
+    let metadata: Option<MetadataStore> = if fields.is_empty() {
+        None
+    } else {
+        let metadata: MetadataStore = MetadataStore::from_csv(&config.data, &fields)?;
+        anyhow::ensure!(
+            metadata.get_nb_point() == data.len(),
+            "metadata of {} documents for {} documents",
+            metadata.get_nb_point(),
+            data.len()
+        );
+        println!("metadata : {}", config.metadata.join(", "));
+        Some(metadata)
+    };
+
+    // text and fields returned with the hits, stored next to the dumps
+    let documents: Vec<Vec<String>> = read_documents(&config.data, &config.document_fields)?;
+    anyhow::ensure!(
+        documents.len() == data.len(),
+        "{} stored documents for {} documents",
+        documents.len(),
+        data.len()
+    );
+
+    // each document is inserted under the DataId of its key, duplicate keys are refused before
+    // the documents are embedded
+    let mut keys: KeyMap = KeyMap::new();
+    let key_spec: Option<KeySpec> = config.key_spec()?;
+    let ids: Vec<DataId> = match key_spec.as_ref() {
+        Some(spec) => {
+            let read: Vec<Key> = read_keys(&config.data, spec)?;
+            anyhow::ensure!(
+                read.len() == data.len(),
+                "{} keys for {} documents",
+                read.len(),
+                data.len()
+            );
+            let ids: Vec<DataId> = read
+                .into_iter()
+                .map(|key: Key| keys.insert(key))
+                .collect::<std::io::Result<Vec<DataId>>>()?;
+            println!("keys : {} ({})", spec.column, spec.key_type);
+            ids
+        },
+        None => (0..data.len()).collect(),
+    };
 
     let mut embeddings: Vec<Vec<f32>> = Vec::with_capacity(data.len());
 
@@ -56,19 +122,32 @@ fn main() -> Result<()> {
     let ef_c: usize = 200;
     let nb_layer: u8 = 16;
 
-    if !do_quantize {
+    if build_full {
         let index: Hnsw<f32, DistDot> =
             Hnsw::<f32, DistDot>::new(max_nb_connection, nb_elem, nb_layer, ef_c, DistDot {});
 
         let embeddings_indices: Vec<(&Vec<f32>, usize)> =
-            embeddings.iter().zip(0..embeddings.len()).collect();
+            embeddings.iter().zip(ids.iter().copied()).collect();
 
         let start: Instant = Instant::now();
         index.parallel_insert(&embeddings_indices);
         println!("parallel insert : {:.3?}", start.elapsed());
 
-        _ = index.file_dump("news");
-    } else {
+        _ = index.file_dump(&config.dump_name(IndexType::Full));
+        if let Some(metadata) = metadata.as_ref() {
+            metadata.dump(&config.dump_name(IndexType::Full))?;
+        }
+        if key_spec.is_some() {
+            keys.dump(&config.dump_name(IndexType::Full))?;
+        }
+        DocumentStore::dump(
+            &config.dump_name(IndexType::Full),
+            &config.document_fields,
+            &documents,
+        )?;
+    }
+
+    if do_quantize {
         let index: Hnsw<i8, DistHamming> = Hnsw::<i8, DistHamming>::new(
             max_nb_connection,
             nb_elem,
@@ -83,14 +162,25 @@ fn main() -> Result<()> {
 
         let embeddings_indices: Vec<(&Vec<i8>, usize)> = quantized_embeddings
             .iter()
-            .zip(0..embeddings.len())
+            .zip(ids.iter().copied())
             .collect();
 
         let start: Instant = Instant::now();
         index.parallel_insert(&embeddings_indices);
         println!("parallel insert : {:.3?}", start.elapsed());
 
-        _ = index.file_dump("news_q");
+        _ = index.file_dump(&config.dump_name(IndexType::Quantize));
+        if let Some(metadata) = metadata.as_ref() {
+            metadata.dump(&config.dump_name(IndexType::Quantize))?;
+        }
+        if key_spec.is_some() {
+            keys.dump(&config.dump_name(IndexType::Quantize))?;
+        }
+        DocumentStore::dump(
+            &config.dump_name(IndexType::Quantize),
+            &config.document_fields,
+            &documents,
+        )?;
     }
 
     Ok(())
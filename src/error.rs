@@ -0,0 +1,79 @@
+//! Errors of the search, each kind is mapped to a gRPC status code so a bad request or a failure
+//! of the model is answered instead of stopping a worker of the server.
+
+use std::{error, fmt, io};
+
+use tonic::Status;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchError {
+    /// the request is not valid : no query, k or ef out of the limits, wrong vector dimension
+    InvalidArgument(String),
+    /// the collection named by the request is not served
+    NotFound(String),
+    /// the index is not loaded (yet) and requests cannot be answered
+    Unavailable(String),
+    /// the request does not apply to the state of the server : a dump not compatible with the
+    /// index served, a reload while another one runs
+    FailedPrecondition(String),
+    /// the model or the search failed on a valid request
+    Internal(String),
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::InvalidArgument(message) => write!(f, "invalid argument : {}", message),
+            SearchError::NotFound(message) => write!(f, "not found : {}", message),
+            SearchError::Unavailable(message) => write!(f, "unavailable : {}", message),
+            SearchError::FailedPrecondition(message) => {
+                write!(f, "failed precondition : {}", message)
+            },
+            SearchError::Internal(message) => write!(f, "internal error : {}", message),
+        }
+    }
+}
+
+impl error::Error for SearchError {}
+
+impl From<SearchError> for Status {
+    fn from(e: SearchError) -> Self {
+        match e {
+            SearchError::InvalidArgument(message) => Status::invalid_argument(message),
+            SearchError::NotFound(message) => Status::not_found(message),
+            SearchError::Unavailable(message) => Status::unavailable(message),
+            SearchError::FailedPrecondition(message) => Status::failed_precondition(message),
+            SearchError::Internal(message) => Status::internal(message),
+        }
+    }
+}
+
+impl From<io::Error> for SearchError {
+    fn from(e: io::Error) -> Self {
+        SearchError::Internal(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::Code;
+
+    use super::*;
+
+    #[test]
+    fn test_status_code() {
+        let cases: Vec<(SearchError, Code)> = vec![
+            (SearchError::InvalidArgument(String::from("k")), Code::InvalidArgument),
+            (SearchError::NotFound(String::from("collection")), Code::NotFound),
+            (SearchError::Unavailable(String::from("index")), Code::Unavailable),
+            (SearchError::FailedPrecondition(String::from("reload")), Code::FailedPrecondition),
+            (SearchError::Internal(String::from("model")), Code::Internal),
+        ];
+
+        for (e, code) in cases {
+            let status: Status = Status::from(e.clone());
+            assert_eq!(status.code(), code);
+            assert!(e.to_string().ends_with(status.message()));
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Recall evaluation of a SearchIndex against the exact ground truth.
+//!
+//! The ground truth of each query is the exact search of the index (SearchIndex::scan_filter),
+//! which computes the distance of the index to all the points stored, so the report measures only
+//! the approximation made by the graph search. A flat index has a recall of 1. For each ef of a
+//! sweep we report recall@k, the mean distance ratio (sum of the distances returned over the sum of
+//! the exact ones, 1.0 is perfect) and latency percentiles of single query searches.
+
+use std::time::Instant;
+
+use hashbrown::HashSet;
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::hnsw_index::hnsw::{DataId, Neighbour};
+use crate::index::SearchIndex;
+use crate::utils::{log_stats, percentiles};
+
+/// latency statistics of a run, in milliseconds
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LatencyReport {
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub p999_ms: f64,
+    pub max_ms: f64,
+    pub qps: f64,
+}
+
+/// result of the evaluation for one value of ef
+#[derive(Debug, Clone, Serialize)]
+pub struct EfReport {
+    pub ef: usize,
+    /// mean over queries of |returned ∩ exact| / |exact|
+    pub recall: f64,
+    /// mean over queries of the sum of returned distances over the sum of exact distances
+    pub mean_distance_ratio: f64,
+    pub latency: LatencyReport,
+}
+
+/// result of the whole sweep, serialized as json by the evaluate binary
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalReport {
+    /// name of the index, as "hnsw f32 DistDot"
+    pub index: String,
+    pub nb_point: usize,
+    pub dimension: usize,
+    pub nb_query: usize,
+    pub knbn: usize,
+    pub efs: Vec<EfReport>,
+}
+
+/// computes the exact knbn nearest neighbours of each query by the exact search of the index.
+/// Deleted points are skipped as in searches.
+pub fn ground_truth(
+    index: &dyn SearchIndex,
+    queries: &[Vec<f32>],
+    knbn: usize,
+) -> Vec<Vec<Neighbour>> {
+    queries
+        .par_iter()
+        .map(|query: &Vec<f32>| index.scan_filter(query, knbn, &|_: &DataId| true))
+        .collect()
+}
+
+// end of ground_truth
+
+/// fraction of the exact neighbours found in answer
+pub fn recall(answer: &[Neighbour], exact: &[Neighbour]) -> f64 {
+    if exact.is_empty() {
+        return 1.0;
+    }
+
+    let exact_ids: HashSet<DataId> = exact.iter().map(|n: &Neighbour| n.d_id).collect();
+    let nb_found: usize = answer
+        .iter()
+        .filter(|n: &&Neighbour| exact_ids.contains(&n.d_id))
+        .count();
+
+    nb_found as f64 / exact.len() as f64
+}
+
+/// sum of the distances in answer over the sum of the exact distances, on the first
+/// min(answer.len(), exact.len()) ranks. Returns None when the exact distances sum to 0 and the
+/// ratio is not defined.
+pub fn distance_ratio(answer: &[Neighbour], exact: &[Neighbour]) -> Option<f64> {
+    let nb: usize = answer.len().min(exact.len());
+    let answer_sum: f64 = answer[..nb]
+        .iter()
+        .map(|n: &Neighbour| n.distance as f64)
+        .sum();
+    let exact_sum: f64 = exact[..nb]
+        .iter()
+        .map(|n: &Neighbour| n.distance as f64)
+        .sum();
+
+    if exact_sum > 0.0 {
+        Some(answer_sum / exact_sum)
+    } else if answer_sum <= 0.0 {
+        Some(1.0)
+    } else {
+        None
+    }
+}
+
+/// the statistics of latencies, all zero if there are none
+fn latency_report(latencies: &[u64]) -> LatencyReport {
+    if latencies.is_empty() {
+        return LatencyReport::default();
+    }
+    let mut lats: Vec<u64> = latencies.to_vec();
+    lats.sort_unstable();
+
+    let to_ms = |ns: u64| ns as f64 * 1e-6;
+    let mean_ms: f64 = to_ms(lats.iter().sum::<u64>() / lats.len() as u64);
+    let ps: Vec<(f32, u64)> = percentiles(&[0.5, 0.95, 0.99, 0.999], &lats);
+
+    LatencyReport {
+        mean_ms,
+        p50_ms: to_ms(ps[0].1),
+        p95_ms: to_ms(ps[1].1),
+        p99_ms: to_ms(ps[2].1),
+        p999_ms: to_ms(ps[3].1),
+        max_ms: to_ms(*lats.last().unwrap()),
+        qps: if mean_ms > 0.0 { 1000.0 / mean_ms } else { 0.0 },
+    }
+}
+
+/// evaluates the index on queries for each ef in efs.
+/// Queries are searched one at a time to measure the latency of a single search.
+pub fn evaluate(
+    index: &dyn SearchIndex,
+    queries: &[Vec<f32>],
+    knbn: usize,
+    efs: &[usize],
+) -> EvalReport {
+    assert!(!queries.is_empty(), "evaluate needs at least one query");
+
+    let start: Instant = Instant::now();
+    let exact: Vec<Vec<Neighbour>> = ground_truth(index, queries, knbn);
+    log::info!("ground truth of {} queries : {:.3?}", queries.len(), start.elapsed());
+
+    let mut reports: Vec<EfReport> = Vec::with_capacity(efs.len());
+    for ef in efs {
+        let mut latencies: Vec<u64> = vec![0u64; queries.len()];
+        let mut recall_sum: f64 = 0.0;
+        let mut ratio_sum: f64 = 0.0;
+        let mut nb_ratio: usize = 0;
+
+        for (i, query) in queries.iter().enumerate() {
+            let start: Instant = Instant::now();
+            let answer: Vec<Neighbour> = index.search(query, knbn, *ef);
+            latencies[i] = start.elapsed().as_nanos() as u64;
+
+            recall_sum += recall(&answer, &exact[i]);
+            if let Some(ratio) = distance_ratio(&answer, &exact[i]) {
+                ratio_sum += ratio;
+                nb_ratio += 1;
+            }
+        }
+
+        log_stats(&format!("search ef={}", ef), queries.len(), 1, &latencies);
+
+        let report: EfReport = EfReport {
+            ef: *ef,
+            recall: recall_sum / queries.len() as f64,
+            mean_distance_ratio: if nb_ratio > 0 { ratio_sum / nb_ratio as f64 } else { 1.0 },
+            latency: latency_report(&latencies),
+        };
+        println!(
+            "ef={} recall@{}={:.4} distance ratio={:.4}",
+            report.ef, knbn, report.recall, report.mean_distance_ratio
+        );
+        reports.push(report);
+    }
+
+    EvalReport {
+        index: index.name(),
+        nb_point: index.get_nb_point(),
+        dimension: index.get_dimension(),
+        nb_query: queries.len(),
+        knbn,
+        efs: reports,
+    }
+} // end of evaluate
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use rand::distributions::Uniform;
+    use rand::prelude::*;
+
+    use super::*;
+    use crate::hnsw_index::api::AnnT;
+    use crate::hnsw_index::dist::DistL2;
+    use crate::hnsw_index::flat::FlatIndex;
+    use crate::hnsw_index::hnsw::Hnsw;
+    use crate::index::load_search_index;
+
+    fn remove_dump(dataset: &str) {
+        for ext in ["graph", "data"] {
+            fs::remove_file(format!("{}.hnsw.{}", dataset, ext)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_evaluate_sweep() {
+        let nb_elem: usize = 2000;
+        let dim: usize = 16;
+        let mut rng: StdRng = StdRng::seed_from_u64(4664);
+        let unif: Uniform<f32> = Uniform::<f32>::new(0., 1.);
+        let data: Vec<Vec<f32>> = (0..nb_elem)
+            .map(|_| (0..dim).map(|_| rng.sample(unif)).collect())
+            .collect();
+        let queries: Vec<Vec<f32>> = (0..50)
+            .map(|_| (0..dim).map(|_| rng.sample(unif)).collect())
+            .collect();
+
+        let hnsw: Hnsw<f32, DistL2> = Hnsw::<f32, DistL2>::new(16, nb_elem, 16, 100, DistL2 {});
+        let mut flat: FlatIndex<f32, DistL2> = FlatIndex::<f32, DistL2>::new(nb_elem, DistL2 {});
+        for (i, v) in data.iter().enumerate() {
+            hnsw.insert((v, i));
+            flat.insert((v, i));
+        }
+        hnsw.file_dump("eval_test_hnsw").unwrap();
+        flat.file_dump("eval_test_flat").unwrap();
+
+        let index: Box<dyn SearchIndex> = load_search_index("eval_test_hnsw", None, 1).unwrap();
+        let report: EvalReport = evaluate(index.as_ref(), &queries, 10, &[10, 100]);
+        assert_eq!(report.index, "hnsw f32 DistL2");
+        assert_eq!(report.efs.len(), 2);
+        assert_eq!(report.nb_query, 50);
+        for ef_report in report.efs.iter() {
+            assert!(ef_report.recall <= 1.0);
+            assert!(ef_report.mean_distance_ratio >= 1.0 - 1e-5);
+        }
+        assert!(report.efs[1].recall > 0.95, "recall at ef 100 {}", report.efs[1].recall);
+
+        // the exact search of a flat index is its ground truth
+        let flat_index: Box<dyn SearchIndex> =
+            load_search_index("eval_test_flat", None, 1).unwrap();
+        let flat_report: EvalReport = evaluate(flat_index.as_ref(), &queries, 10, &[10]);
+        assert_eq!(flat_report.efs[0].recall, 1.0);
+        assert_eq!(
+            ground_truth(flat_index.as_ref(), &queries, 10),
+            ground_truth(index.as_ref(), &queries, 10)
+        );
+
+        // deleted points disappear from the ground truth
+        let exact: Vec<Vec<Neighbour>> = ground_truth(index.as_ref(), &queries[..1], 1);
+        hnsw.delete(exact[0][0].d_id);
+        hnsw.file_dump("eval_test_hnsw").unwrap();
+        let index: Box<dyn SearchIndex> = load_search_index("eval_test_hnsw", None, 1).unwrap();
+        let exact_after: Vec<Vec<Neighbour>> = ground_truth(index.as_ref(), &queries[..1], 1);
+        assert_ne!(exact[0][0].d_id, exact_after[0][0].d_id);
+        remove_dump("eval_test_hnsw");
+        remove_dump("eval_test_flat");
+
+        let json: String = serde_json::to_string(&report).unwrap();
+        assert!(json.contains("\"mean_distance_ratio\""));
+    }
+
+    #[test]
+    fn test_latency_report() {
+        // no latencies give a zero report, the last percentile is the max
+        assert_eq!(latency_report(&[]).max_ms, 0.0);
+        assert!(percentiles(&[0.5, 1.0], &[]).is_empty());
+        assert_eq!(percentiles(&[0.5, 1.0], &[1, 2, 3]), vec![(0.5, 2), (1.0, 3)]);
+
+        let report: LatencyReport = latency_report(&[3_000_000, 1_000_000]);
+        assert_eq!((report.p50_ms, report.p999_ms, report.max_ms), (3.0, 3.0, 3.0));
+        assert_eq!(report.mean_ms, 2.0);
+    }
+}
@@ -0,0 +1,100 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModel;
+use semantic_search::config::{Config, ConfigArgs};
+use semantic_search::eval::{evaluate, EvalReport};
+use semantic_search::index::{load_search_index, SearchIndex};
+use semantic_search::utils::load_model;
+
+static K: usize = 10;
+static EFS: [usize; 6] = [10, 20, 30, 50, 100, 200];
+
+/// recall and latency of an index for a sweep of ef
+#[derive(Parser)]
+struct Args {
+    /// basename of the dump
+    dataset: String,
+    /// full or quantize, the quantized dump is dataset_q. Flat and hnsw dumps are evaluated alike.
+    mode: String,
+    /// csv file of the queries, the text is in the first column
+    queries: String,
+    /// number of neighbours searched
+    k: Option<usize>,
+    /// comma separated values of ef
+    efs: Option<String>,
+    /// json report, dataset.eval.json by default
+    output: Option<String>,
+    #[command(flatten)]
+    config: ConfigArgs,
+}
+
+fn load_queries(filename: &str) -> Result<Vec<String>> {
+    let file: File =
+        File::open(filename).with_context(|| format!("cannot open the queries {}", filename))?;
+    let mut reader: csv::Reader<File> = csv::Reader::from_reader(file);
+
+    reader
+        .records()
+        .map(|res: Result<csv::StringRecord, csv::Error>| {
+            let record: csv::StringRecord =
+                res.with_context(|| format!("cannot read the queries {}", filename))?;
+            Ok(record[0].to_string())
+        })
+        .collect()
+}
+
+fn main() -> Result<()> {
+    let args: Args = Args::parse();
+    let config: Config = Config::load(&args.config)?;
+    config.init_thread_pool()?;
+
+    let dataset: &str = &args.dataset;
+    let do_quantize: bool = args.mode == "quantize";
+    let knbn: usize = args.k.unwrap_or(K);
+    let efs: Vec<usize> = match args.efs.as_ref() {
+        Some(efs) => efs
+            .split(',')
+            .map(|ef: &str| {
+                ef.trim()
+                    .parse()
+                    .with_context(|| format!("bad ef {} in {}", ef, efs))
+            })
+            .collect::<Result<Vec<usize>>>()?,
+        None => EFS.to_vec(),
+    };
+    let output: String = args
+        .output
+        .clone()
+        .unwrap_or(format!("{}.eval.json", dataset));
+
+    let queries: Vec<String> = load_queries(&args.queries)?;
+    println!("queries : {:?}, k : {}, efs : {:?}", queries.len(), knbn, efs);
+
+    let model: SentenceEmbeddingsModel = load_model(&config.model_dir);
+    let query_embeddings: Vec<Vec<f32>> = queries
+        .chunks(128)
+        .map(|chunk: &[String]| model.encode(chunk).context("cannot embed the queries"))
+        .collect::<Result<Vec<Vec<Vec<f32>>>>>()?
+        .concat();
+
+    // queries of a quantized index are quantized by its search
+    let dump: String = if do_quantize { format!("{}_q", dataset) } else { dataset.to_string() };
+    let index: Box<dyn SearchIndex> = load_search_index(&dump, None, config.rerank_factor)?;
+    let report: EvalReport = evaluate(index.as_ref(), &query_embeddings, knbn, &efs);
+
+    let file: File =
+        File::create(&output).with_context(|| format!("cannot create the report {}", output))?;
+    let mut writer: BufWriter<File> = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, &report)
+        .with_context(|| format!("cannot write the report {}", output))?;
+    writer
+        .flush()
+        .with_context(|| format!("cannot write the report {}", output))?;
+
+    println!("report written to {}", output);
+
+    Ok(())
+}
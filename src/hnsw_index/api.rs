@@ -11,6 +11,7 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 use crate::hnsw_index::dist::Distance;
+use crate::hnsw_index::filter::FilterT;
 use crate::hnsw_index::hnsw::{Hnsw, Neighbour};
 use crate::hnsw_index::hnswio::{DumpMode, HnswIO};
 
@@ -22,10 +23,35 @@ pub trait AnnT {
     #[allow(clippy::ptr_arg)]
     fn insert_data(&mut self, data: &Vec<Self::Val>, id: usize);
 
+    /// insert data or replace the data already associated to id.
+    /// returns true if data was replaced
+    #[allow(clippy::ptr_arg)]
+    fn upsert_data(&mut self, data: &Vec<Self::Val>, id: usize) -> bool;
+
     ///
     #[allow(clippy::ptr_arg)]
     fn search_neighbours(&self, data: &Vec<Self::Val>, knbn: usize, ef_s: usize) -> Vec<Neighbour>;
 
+    /// as search_neighbours, only ids accepted by filter are returned
+    #[allow(clippy::ptr_arg)]
+    fn search_neighbours_filter(
+        &self,
+        data: &Vec<Self::Val>,
+        knbn: usize,
+        ef_s: usize,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour>;
+
+    /// exact search of the knbn nearest points accepted by filter, computing the distance to each
+    /// of them, see Hnsw::scan_filter
+    #[allow(clippy::ptr_arg)]
+    fn scan_neighbours_filter(
+        &self,
+        data: &Vec<Self::Val>,
+        knbn: usize,
+        filter: &dyn FilterT,
+    ) -> Vec<Neighbour>;
+
     ///
     #[allow(clippy::ptr_arg)]
     fn parallel_insert_data(&mut self, data: &Vec<(&Vec<Self::Val>, usize)>);
@@ -39,6 +65,18 @@ pub trait AnnT {
         ef_s: usize,
     ) -> Vec<Vec<Neighbour>>;
 
+    /// returns all points at a distance less or equal to radius of data, sorted by distance.
+    /// max_results caps the number of points returned.
+    #[allow(clippy::ptr_arg)]
+    fn search_range(
+        &self,
+        data: &Vec<Self::Val>,
+        radius: f32,
+        ef_s: usize,
+        max_results: Option<usize>,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour>;
+
     /// dumps a data and graph in 2 files.
     /// Datas are dumped in file filename.hnsw.data and graph in filename.hnsw.graph
     fn file_dump(&self, filename: &str) -> Result<i32, String>;
@@ -56,11 +94,34 @@ where
         self.insert((data, id));
     }
 
+    fn upsert_data(&mut self, data: &Vec<Self::Val>, id: usize) -> bool {
+        self.upsert((data, id))
+    }
+
     ///
     fn search_neighbours(&self, data: &Vec<T>, knbn: usize, ef_s: usize) -> Vec<Neighbour> {
         self.search(data, knbn, ef_s)
     }
 
+    fn search_neighbours_filter(
+        &self,
+        data: &Vec<T>,
+        knbn: usize,
+        ef_s: usize,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour> {
+        self.search_filter(data, knbn, ef_s, filter)
+    }
+
+    fn scan_neighbours_filter(
+        &self,
+        data: &Vec<T>,
+        knbn: usize,
+        filter: &dyn FilterT,
+    ) -> Vec<Neighbour> {
+        self.scan_filter(data, knbn, filter)
+    }
+
     fn parallel_insert_data(&mut self, data: &Vec<(&Vec<Self::Val>, usize)>) {
         self.parallel_insert(data);
     }
@@ -74,6 +135,17 @@ where
         self.parallel_search(data, knbn, ef_s)
     }
 
+    fn search_range(
+        &self,
+        data: &Vec<Self::Val>,
+        radius: f32,
+        ef_s: usize,
+        max_results: Option<usize>,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour> {
+        Hnsw::search_range(self, data, radius, ef_s, max_results, filter)
+    }
+
     /// The main entry point to do a dump.  
     /// It will generate two files one for the graph part of the data. The other for the real data
     /// points of the structure.
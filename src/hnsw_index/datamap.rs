@@ -43,7 +43,7 @@ impl DataMap {
         dir: &str,
         filename: &str,
     ) -> Result<DataMap, String> {
-        let datapath: PathBuf = PathBuf::from(format!("{}{}.hnsw.data", dir, filename));
+        let datapath: PathBuf = PathBuf::from(dir).join(format!("{}.hnsw.data", filename));
 
         let file: File = File::open(&datapath).unwrap();
         let filesize: usize = file.metadata().unwrap().len().try_into().unwrap();
@@ -57,7 +57,7 @@ impl DataMap {
         });
 
         // reload description to have data type
-        let graphpath: PathBuf = PathBuf::from(format!("{}{}.hnsw.graph", dir, filename));
+        let graphpath: PathBuf = PathBuf::from(dir).join(format!("{}.hnsw.graph", filename));
         let graphfile: File = OpenOptions::new().read(true).open(&graphpath).unwrap();
         let mut graph_in: BufReader<File> = BufReader::new(graphfile);
 
@@ -156,6 +156,11 @@ impl DataMap {
         Ok(DataMap { datapath, mmap, hmap, t_name, dimension: descr_dimension })
     }
 
+    /// returns dimension of the vectors mapped
+    pub fn get_dimension(&self) -> usize {
+        self.dimension
+    }
+
     /// return the data corresponding to dataid. Access is done via mmap
     pub fn get_data<T: Clone + std::fmt::Debug>(&self, dataid: &DataId) -> Option<&[T]> {
         let address: usize = *self.hmap.get(dataid)?;
@@ -198,6 +203,7 @@ mod tests {
     }
 
     #[test]
+    #[should_panic(expected = "type error")]
     fn test_file_mmap() {
         println!("\n\n test_file_mmap");
         log_init_test();
@@ -233,4 +239,33 @@ mod tests {
 
         let datamap: DataMap = DataMap::new::<i8>(".", "mmap_test");
     } // end of test_file_mmap
+
+    #[test]
+    fn test_file_mmap_data() {
+        println!("\n\n test_file_mmap_data");
+        log_init_test();
+        let mut rng: rand::rngs::ThreadRng = rand::thread_rng();
+        let unif: Uniform<f32> = Uniform::<f32>::new(0., 1.);
+        let nbcolumn: usize = 1000;
+        let nbrow: usize = 10;
+        let data: Vec<Vec<f32>> = (0..nbcolumn)
+            .map(|_| (0..nbrow).map(|_| unif.sample(&mut rng)).collect())
+            .collect();
+        let hnsw: Hnsw<f32, DistL1> = Hnsw::<f32, DistL1>::new(10, nbcolumn, 16, 25, DistL1 {});
+        for (i, v) in data.iter().enumerate() {
+            hnsw.insert((v, i));
+        }
+        _ = hnsw.file_dump("mmap_data_test");
+
+        // the vectors read by the rerank are the ones inserted
+        let datamap: DataMap = DataMap::new::<f32>(".", "mmap_data_test");
+        assert_eq!(datamap.get_dimension(), nbrow);
+        for (i, v) in data.iter().enumerate() {
+            assert_eq!(datamap.get_data::<f32>(&i).unwrap(), v.as_slice());
+        }
+        assert!(datamap.get_data::<f32>(&nbcolumn).is_none());
+
+        // type is checked against the description
+        assert!(DataMap::from_hnswdump::<i8>(".", "mmap_data_test").is_err());
+    } // end of test_file_mmap_data
 } // end of mod tests
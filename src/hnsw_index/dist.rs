@@ -12,7 +12,7 @@
 ///
 ///
 /// The L1 and Cosine distance are implemented for u16, i32, i64, f32, f64
-use std::{ops::Mul, os::raw::*};
+use std::os::raw::*;
 
 use num_traits::float::*;
 use packed_simd::{f32x16, f64x8, i8x64, m8, FromCast, Simd};
@@ -83,7 +83,9 @@ macro_rules! simd_l1_distance (
             fn eval(&self, va: &[$data_type], vb: &[$data_type]) -> f32 {
                 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
                 {
-                    let dist: $data_type = va
+                    let size: usize = va.len() - (va.len() % $size);
+
+                    let c: $data_type = va
                         .chunks_exact($size)
                         .map($simd_type::from_slice_unaligned)
                         .zip(vb.chunks_exact($size).map($simd_type::from_slice_unaligned))
@@ -91,7 +93,13 @@ macro_rules! simd_l1_distance (
                         .sum::<$simd_type>()
                         .sum();
 
-                    return dist as f32;
+                    let d: $data_type = va[size..]
+                        .iter()
+                        .zip(&vb[size..])
+                        .map(|(p, q)| (p - q).abs())
+                        .sum();
+
+                    return (c + d) as f32;
                 }
 
                 va.iter()
@@ -283,22 +291,36 @@ macro_rules! simd_dot_distance (
 
 #[allow(unreachable_code)]
 fn dot_f64(va: &[f64], vb: &[f64]) -> f64 {
-    va.chunks_exact(8)
+    let size: usize = va.len() - (va.len() % 8);
+
+    let c: f64 = va
+        .chunks_exact(8)
         .map(f64x8::from_slice_unaligned)
         .zip(vb.chunks_exact(8).map(f64x8::from_slice_unaligned))
         .map(|(a, b)| a * b)
         .sum::<f64x8>()
-        .sum()
+        .sum();
+
+    let d: f64 = va[size..].iter().zip(&vb[size..]).map(|(p, q)| p * q).sum();
+
+    c + d
 }
 
 #[allow(unreachable_code)]
 fn dot_f32(va: &[f32], vb: &[f32]) -> f32 {
-    va.chunks_exact(16)
+    let size: usize = va.len() - (va.len() % 16);
+
+    let c: f32 = va
+        .chunks_exact(16)
         .map(f32x16::from_slice_unaligned)
         .zip(vb.chunks_exact(16).map(f32x16::from_slice_unaligned))
         .map(|(a, b)| a * b)
         .sum::<f32x16>()
-        .sum()
+        .sum();
+
+    let d: f32 = va[size..].iter().zip(&vb[size..]).map(|(p, q)| p * q).sum();
+
+    c + d
 }
 
 // #[allow(unreachable_code)]
@@ -380,28 +402,45 @@ impl Distance<i8> for DistDot {
 #[derive(Default)]
 pub struct DistHellinger;
 
+// returns the Bhattacharyya coefficient sum sqrt(p\[i\] * q\[i\])
 fn hellinger_f64(va: &[f64], vb: &[f64]) -> f64 {
-    va.chunks_exact(8)
+    let size: usize = va.len() - (va.len() % 8);
+
+    let c: f64 = va
+        .chunks_exact(8)
         .map(f64x8::from_slice_unaligned)
         .zip(vb.chunks_exact(8).map(f64x8::from_slice_unaligned))
-        .map(|(a, b)| {
-            let c: Simd<[f64; 8]> = (a * b).sqrt();
-            c.mul(c)
-        })
+        .map(|(a, b)| (a * b).sqrt())
         .sum::<f64x8>()
-        .sum()
+        .sum();
+
+    let d: f64 = va[size..]
+        .iter()
+        .zip(&vb[size..])
+        .map(|(p, q)| (p * q).sqrt())
+        .sum();
+
+    c + d
 }
 
 fn hellinger_f32(va: &[f32], vb: &[f32]) -> f32 {
-    va.chunks_exact(16)
+    let size: usize = va.len() - (va.len() % 16);
+
+    let c: f32 = va
+        .chunks_exact(16)
         .map(f32x16::from_slice_unaligned)
         .zip(vb.chunks_exact(16).map(f32x16::from_slice_unaligned))
-        .map(|(a, b)| {
-            let c: Simd<[f32; 16]> = (a * b).sqrt();
-            c.mul(c)
-        })
+        .map(|(a, b)| (a * b).sqrt())
         .sum::<f32x16>()
-        .sum()
+        .sum();
+
+    let d: f32 = va[size..]
+        .iter()
+        .zip(&vb[size..])
+        .map(|(p, q)| (p * q).sqrt())
+        .sum();
+
+    c + d
 }
 
 impl Distance<f64> for DistHellinger {
@@ -567,7 +606,10 @@ impl Distance<f32> for DistHamming {
 
 impl Distance<i8> for DistHamming {
     fn eval(&self, va: &[i8], vb: &[i8]) -> f32 {
-        let dist: i32 = va
+        let size: usize = va.len() - (va.len() % 64);
+
+        // a lane of the mask is -1 when elements differ, so the sum is minus the count
+        let c: i32 = va
             .chunks_exact(64)
             .map(i8x64::from_slice_unaligned)
             .zip(vb.chunks_exact(64).map(i8x64::from_slice_unaligned))
@@ -577,7 +619,13 @@ impl Distance<i8> for DistHamming {
             })
             .sum();
 
-        -dist as f32 / va.len() as f32
+        let d: usize = va[size..]
+            .iter()
+            .zip(&vb[size..])
+            .filter(|(p, q)| p != q)
+            .count();
+
+        (d as i32 - c) as f32 / va.len() as f32
     } // end of eval
 } // end implementation Distance<f32>
 
@@ -1023,6 +1071,8 @@ mod tests {
 
     #[allow(unused)]
     use rand::distributions::{Distribution, Uniform};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     // to be run with and without simdeez_f
     #[test]
@@ -1142,6 +1192,125 @@ mod tests {
         }
     } // end of test_hamming_f32
 
+    // dimensions checked by the comparisons of simd kernels to scalar references : all small
+    // dimensions, to go through each remainder of the lanes, then random ones up to 1024.
+    fn test_dimensions() -> Vec<usize> {
+        let mut rng: StdRng = StdRng::seed_from_u64(1515);
+        let between: Uniform<usize> = Uniform::<usize>::from(1..1024);
+        let mut dims: Vec<usize> = (1..=130).collect();
+        dims.extend((0..200).map(|_| between.sample(&mut rng)));
+        dims
+    }
+
+    fn random_vec<T>(rng: &mut StdRng, between: &Uniform<T>, dim: usize) -> Vec<T>
+    where
+        T: rand::distributions::uniform::SampleUniform,
+    {
+        (0..dim).map(|_| between.sample(rng)).collect()
+    }
+
+    fn assert_close(name: &str, dim: usize, got: f64, expected: f64) {
+        assert!(
+            (got - expected).abs() <= 1.0e-4 * expected.abs().max(1.),
+            "{} dim {} : got {} expected {}",
+            name,
+            dim,
+            got,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_simd_tail_f32() {
+        let mut rng: StdRng = StdRng::seed_from_u64(4242);
+        let between: Uniform<f32> = Uniform::<f32>::from(-1.0..1.0);
+        let positive: Uniform<f32> = Uniform::<f32>::from(0.0..1.0);
+        for dim in test_dimensions() {
+            let va: Vec<f32> = random_vec(&mut rng, &between, dim);
+            let vb: Vec<f32> = random_vec(&mut rng, &between, dim);
+            let pairs = || {
+                va.iter()
+                    .zip(vb.iter())
+                    .map(|(a, b)| (*a as f64, *b as f64))
+            };
+
+            let l1: f64 = pairs().map(|(a, b)| (a - b).abs()).sum();
+            assert_close("l1 f32", dim, DistL1.eval(&va, &vb) as f64, l1);
+
+            // the simd L2 returns the squared distance
+            let l2: f64 = pairs().map(|(a, b)| (a - b) * (a - b)).sum();
+            assert_close("l2 f32", dim, DistL2.eval(&va, &vb) as f64, l2);
+
+            let dot: f64 = pairs().map(|(a, b)| a * b).sum();
+            assert_close("dot f32", dim, dot_f32(&va, &vb) as f64, dot);
+            assert_close("dist dot f32", dim, DistDot.eval(&va, &vb) as f64, (1. - dot).max(0.));
+
+            let pa: Vec<f32> = random_vec(&mut rng, &positive, dim);
+            let pb: Vec<f32> = random_vec(&mut rng, &positive, dim);
+            let bhattacharyya: f64 = pa
+                .iter()
+                .zip(pb.iter())
+                .map(|(a, b)| (*a as f64 * *b as f64).sqrt())
+                .sum();
+            assert_close("hellinger f32", dim, hellinger_f32(&pa, &pb) as f64, bhattacharyya);
+        }
+    } // end of test_simd_tail_f32
+
+    #[test]
+    fn test_simd_tail_f64() {
+        let mut rng: StdRng = StdRng::seed_from_u64(2424);
+        let between: Uniform<f64> = Uniform::<f64>::from(-1.0..1.0);
+        let positive: Uniform<f64> = Uniform::<f64>::from(0.0..1.0);
+        for dim in test_dimensions() {
+            let va: Vec<f64> = random_vec(&mut rng, &between, dim);
+            let vb: Vec<f64> = random_vec(&mut rng, &between, dim);
+
+            let l1: f64 = va.iter().zip(vb.iter()).map(|(a, b)| (a - b).abs()).sum();
+            assert_close("l1 f64", dim, DistL1.eval(&va, &vb) as f64, l1);
+
+            let l2: f64 = va
+                .iter()
+                .zip(vb.iter())
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum();
+            assert_close("l2 f64", dim, DistL2.eval(&va, &vb) as f64, l2);
+
+            let dot: f64 = va.iter().zip(vb.iter()).map(|(a, b)| a * b).sum();
+            assert_close("dot f64", dim, dot_f64(&va, &vb), dot);
+
+            let pa: Vec<f64> = random_vec(&mut rng, &positive, dim);
+            let pb: Vec<f64> = random_vec(&mut rng, &positive, dim);
+            let bhattacharyya: f64 = pa.iter().zip(pb.iter()).map(|(a, b)| (a * b).sqrt()).sum();
+            assert_close("hellinger f64", dim, hellinger_f64(&pa, &pb), bhattacharyya);
+        }
+    } // end of test_simd_tail_f64
+
+    #[test]
+    fn test_simd_tail_i8() {
+        let mut rng: StdRng = StdRng::seed_from_u64(8888);
+        // a small range so that many elements are equal
+        let between: Uniform<i8> = Uniform::<i8>::from(-2..2);
+        for dim in test_dimensions() {
+            let va: Vec<i8> = random_vec(&mut rng, &between, dim);
+            let vb: Vec<i8> = random_vec(&mut rng, &between, dim);
+
+            let nb_diff: usize = va.iter().zip(vb.iter()).filter(|(a, b)| a != b).count();
+            assert_close(
+                "hamming i8",
+                dim,
+                DistHamming.eval(&va, &vb) as f64,
+                nb_diff as f64 / dim as f64,
+            );
+
+            let dot: i32 = va
+                .iter()
+                .zip(vb.iter())
+                .map(|(a, b)| *a as i32 * *b as i32)
+                .sum();
+            assert_eq!(dot_i8(&va, &vb), dot, "dot i8 dim {}", dim);
+        }
+    } // end of test_simd_tail_i8
+
     #[test]
     fn test_feature_simd() {
         init_log();
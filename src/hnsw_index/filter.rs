@@ -1,15 +1,84 @@
-//! defines a trait for filtering requests
+//! defines a trait for filtering requests, and filters which can be serialized and composed:
+//! a bitset, a compressed bitmap (RoaringBitmap), sets and ranges of ids, And / Or / Not.
+
+use std::collections::HashSet;
+use std::hash::BuildHasher;
+use std::ops::{Range, RangeInclusive};
+
+use roaring::RoaringBitmap;
+use serde::{Deserialize, Serialize};
 
 use crate::hnsw_index::hnsw::DataId;
 
+/// number of ids checked by the default estimate of selectivity
+pub const SELECTIVITY_SAMPLE: usize = 256;
+
 pub trait FilterT {
     fn hnsw_filter(&self, id: &DataId) -> bool;
+
+    /// the ids the filter can accept, sorted, if it lists them. All the ids accepted are in it, a
+    /// search can compute their distances instead of scanning the whole index.
+    fn candidate_ids(&self) -> Option<&[DataId]> {
+        None
+    }
+
+    /// Estimated fraction of the ids 0..nb_point accepted, between 0 and 1.
+    /// The default checks SELECTIVITY_SAMPLE ids spread by a hash, so that filters on periodic ids
+    /// are not aliased. Filters listing their candidates count them.
+    fn selectivity(&self, nb_point: usize) -> f32 {
+        if nb_point == 0 {
+            return 1.;
+        }
+        if let Some(ids) = self.candidate_ids() {
+            return (ids.len() as f32 / nb_point as f32).min(1.);
+        }
+
+        sample_selectivity(self, nb_point)
+    }
+}
+
+/// fraction of the ids 0..nb_point accepted by filter, counted when nb_point is small, estimated
+/// on SELECTIVITY_SAMPLE ids otherwise
+fn sample_selectivity<F: FilterT + ?Sized>(filter: &F, nb_point: usize) -> f32 {
+    if nb_point == 0 {
+        return 1.;
+    }
+    if nb_point <= SELECTIVITY_SAMPLE {
+        let nb_accepted: usize = (0..nb_point)
+            .filter(|id: &DataId| filter.hnsw_filter(id))
+            .count();
+        return nb_accepted as f32 / nb_point as f32;
+    }
+
+    let nb_accepted: usize = (0..SELECTIVITY_SAMPLE as u64)
+        .filter(|i: &u64| {
+            // splitmix64 of the rank of the sample
+            let mut x: u64 = i.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+            x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            x ^= x >> 31;
+            filter.hnsw_filter(&((x % nb_point as u64) as DataId))
+        })
+        .count();
+    nb_accepted as f32 / SELECTIVITY_SAMPLE as f32
+}
+
+/// fraction of nb_point for a filter accepting nb_accepted ids
+fn count_selectivity(nb_accepted: usize, nb_point: usize) -> f32 {
+    if nb_point == 0 {
+        return 1.;
+    }
+    (nb_accepted as f32 / nb_point as f32).min(1.)
 }
 
 impl FilterT for Vec<usize> {
     fn hnsw_filter(&self, id: &DataId) -> bool {
         self.binary_search(id).is_ok()
     }
+
+    fn candidate_ids(&self) -> Option<&[DataId]> {
+        Some(self)
+    }
 }
 
 impl<F> FilterT for F
@@ -20,3 +89,329 @@ where
         self(id)
     }
 }
+
+impl FilterT for &dyn FilterT {
+    fn hnsw_filter(&self, id: &DataId) -> bool {
+        (**self).hnsw_filter(id)
+    }
+
+    fn candidate_ids(&self) -> Option<&[DataId]> {
+        (**self).candidate_ids()
+    }
+
+    fn selectivity(&self, nb_point: usize) -> f32 {
+        (**self).selectivity(nb_point)
+    }
+}
+
+impl FilterT for Box<dyn FilterT + Send + Sync> {
+    fn hnsw_filter(&self, id: &DataId) -> bool {
+        (**self).hnsw_filter(id)
+    }
+
+    fn candidate_ids(&self) -> Option<&[DataId]> {
+        (**self).candidate_ids()
+    }
+
+    fn selectivity(&self, nb_point: usize) -> f32 {
+        (**self).selectivity(nb_point)
+    }
+}
+
+/// a set of ids, one bit per id up to the largest one.
+/// Checking an id is one load and a mask, against a binary search for a Vec<usize>.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BitSetFilter {
+    bits: Vec<u64>,
+    count: usize,
+}
+
+impl BitSetFilter {
+    /// an empty set with room for the ids 0..nb_point
+    pub fn new(nb_point: usize) -> Self {
+        BitSetFilter { bits: vec![0; nb_point.div_ceil(64)], count: 0 }
+    }
+
+    /// adds id, returns false if it was already in the set
+    pub fn insert(&mut self, id: DataId) -> bool {
+        let (word, mask): (usize, u64) = (id / 64, 1 << (id % 64));
+        if word >= self.bits.len() {
+            self.bits.resize(word + 1, 0);
+        }
+        if self.bits[word] & mask != 0 {
+            return false;
+        }
+        self.bits[word] |= mask;
+        self.count += 1;
+        true
+    }
+
+    pub fn contains(&self, id: DataId) -> bool {
+        self.bits
+            .get(id / 64)
+            .is_some_and(|word: &u64| word & (1 << (id % 64)) != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// the ids of the set, in increasing order
+    pub fn iter(&self) -> impl Iterator<Item = DataId> + '_ {
+        self.bits
+            .iter()
+            .enumerate()
+            .flat_map(|(i, word): (usize, &u64)| {
+                let mut word: u64 = *word;
+                std::iter::from_fn(move || {
+                    if word == 0 {
+                        return None;
+                    }
+                    let bit: usize = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    Some(i * 64 + bit)
+                })
+            })
+    }
+}
+
+// end of impl BitSetFilter
+
+impl FromIterator<DataId> for BitSetFilter {
+    fn from_iter<I: IntoIterator<Item = DataId>>(ids: I) -> Self {
+        let mut bitset: BitSetFilter = BitSetFilter::default();
+        for id in ids {
+            bitset.insert(id);
+        }
+        bitset
+    }
+}
+
+impl FilterT for BitSetFilter {
+    fn hnsw_filter(&self, id: &DataId) -> bool {
+        self.contains(*id)
+    }
+
+    fn selectivity(&self, nb_point: usize) -> f32 {
+        count_selectivity(self.count, nb_point)
+    }
+}
+
+/// compressed bitmap, smaller than a BitSetFilter for sparse or clustered ids. Ids above u32::MAX
+/// are never accepted.
+impl FilterT for RoaringBitmap {
+    fn hnsw_filter(&self, id: &DataId) -> bool {
+        u32::try_from(*id).is_ok_and(|id: u32| self.contains(id))
+    }
+
+    fn selectivity(&self, nb_point: usize) -> f32 {
+        count_selectivity(self.len() as usize, nb_point)
+    }
+}
+
+impl<S: BuildHasher> FilterT for HashSet<DataId, S> {
+    fn hnsw_filter(&self, id: &DataId) -> bool {
+        self.contains(id)
+    }
+
+    fn selectivity(&self, nb_point: usize) -> f32 {
+        count_selectivity(self.len(), nb_point)
+    }
+}
+
+impl<S: BuildHasher> FilterT for hashbrown::HashSet<DataId, S> {
+    fn hnsw_filter(&self, id: &DataId) -> bool {
+        self.contains(id)
+    }
+
+    fn selectivity(&self, nb_point: usize) -> f32 {
+        count_selectivity(self.len(), nb_point)
+    }
+}
+
+impl FilterT for Range<DataId> {
+    fn hnsw_filter(&self, id: &DataId) -> bool {
+        self.contains(id)
+    }
+
+    fn selectivity(&self, nb_point: usize) -> f32 {
+        count_selectivity(self.end.min(nb_point).saturating_sub(self.start), nb_point)
+    }
+}
+
+impl FilterT for RangeInclusive<DataId> {
+    fn hnsw_filter(&self, id: &DataId) -> bool {
+        self.contains(id)
+    }
+
+    fn selectivity(&self, nb_point: usize) -> f32 {
+        let end: usize = self.end().saturating_add(1).min(nb_point);
+        count_selectivity(end.saturating_sub(*self.start()), nb_point)
+    }
+}
+
+/// accepts the ids accepted by both filters
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct And<A, B>(pub A, pub B);
+
+impl<A: FilterT, B: FilterT> FilterT for And<A, B> {
+    fn hnsw_filter(&self, id: &DataId) -> bool {
+        self.0.hnsw_filter(id) && self.1.hnsw_filter(id)
+    }
+
+    /// the shorter list of candidates of both filters
+    fn candidate_ids(&self) -> Option<&[DataId]> {
+        match (self.0.candidate_ids(), self.1.candidate_ids()) {
+            (Some(a), Some(b)) => Some(if a.len() <= b.len() { a } else { b }),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// no more than the selectivity of either filter
+    fn selectivity(&self, nb_point: usize) -> f32 {
+        sample_selectivity(self, nb_point)
+            .min(self.0.selectivity(nb_point))
+            .min(self.1.selectivity(nb_point))
+    }
+}
+
+/// accepts the ids accepted by either filter
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Or<A, B>(pub A, pub B);
+
+impl<A: FilterT, B: FilterT> FilterT for Or<A, B> {
+    fn hnsw_filter(&self, id: &DataId) -> bool {
+        self.0.hnsw_filter(id) || self.1.hnsw_filter(id)
+    }
+
+    /// no less than the selectivity of either filter
+    fn selectivity(&self, nb_point: usize) -> f32 {
+        sample_selectivity(self, nb_point)
+            .max(self.0.selectivity(nb_point))
+            .max(self.1.selectivity(nb_point))
+    }
+}
+
+/// accepts the ids rejected by the filter
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Not<A>(pub A);
+
+impl<A: FilterT> FilterT for Not<A> {
+    fn hnsw_filter(&self, id: &DataId) -> bool {
+        !self.0.hnsw_filter(id)
+    }
+
+    fn selectivity(&self, nb_point: usize) -> f32 {
+        1. - self.0.selectivity(nb_point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selectivity() {
+        let ids: Vec<usize> = vec![3, 7, 20, 500];
+        assert_eq!(ids.selectivity(1000), 0.004);
+        assert_eq!(ids.selectivity(2), 1.);
+
+        // one id in 10, estimated on a sample
+        let tenth = |id: &DataId| -> bool { id.is_multiple_of(10) };
+        assert!((tenth.selectivity(100_000) - 0.1).abs() < 0.05);
+        let low = |id: &DataId| -> bool { *id < 50_000 };
+        assert!((low.selectivity(100_000) - 0.5).abs() < 0.1);
+        assert_eq!(tenth.selectivity(5), 0.2);
+        assert_eq!(tenth.selectivity(0), 1.);
+    }
+
+    #[test]
+    fn test_bitset_filter() {
+        let mut bitset: BitSetFilter = BitSetFilter::new(100);
+        assert!(bitset.is_empty());
+        assert!(bitset.insert(3));
+        assert!(!bitset.insert(3));
+        assert!(bitset.insert(64));
+        // grows past the ids it was sized for
+        assert!(bitset.insert(1000));
+        assert_eq!(bitset.len(), 3);
+        assert_eq!(bitset.iter().collect::<Vec<DataId>>(), vec![3, 64, 1000]);
+        assert!(bitset.hnsw_filter(&64));
+        assert!(!bitset.hnsw_filter(&65));
+        assert!(!bitset.hnsw_filter(&5000));
+        assert_eq!(bitset.selectivity(1000), 0.003);
+
+        let ids: Vec<usize> = (0..500).filter(|id: &usize| id.is_multiple_of(7)).collect();
+        let bitset: BitSetFilter = ids.iter().copied().collect();
+        let bitmap: RoaringBitmap = ids.iter().map(|id: &usize| *id as u32).collect();
+        let set: HashSet<DataId> = ids.iter().copied().collect();
+        for id in 0..600 {
+            let expected: bool = ids.hnsw_filter(&id);
+            assert_eq!(bitset.hnsw_filter(&id), expected);
+            assert_eq!(bitmap.hnsw_filter(&id), expected);
+            assert_eq!(set.hnsw_filter(&id), expected);
+        }
+        assert!(!bitmap.hnsw_filter(&(u32::MAX as usize + 7)));
+        assert_eq!(bitset.selectivity(500), ids.selectivity(500));
+        assert_eq!(bitmap.selectivity(500), ids.selectivity(500));
+        assert_eq!(set.selectivity(500), ids.selectivity(500));
+
+        // serialized and read back
+        let encoded: Vec<u8> = bincode::serialize(&bitset).unwrap();
+        assert_eq!(bincode::deserialize::<BitSetFilter>(&encoded).unwrap(), bitset);
+    }
+
+    #[test]
+    fn test_range_filter() {
+        assert!((10..20).hnsw_filter(&10));
+        assert!(!(10..20).hnsw_filter(&20));
+        assert!((10..=20).hnsw_filter(&20));
+        assert_eq!((10..20).selectivity(100), 0.1);
+        assert_eq!((90..200).selectivity(100), 0.1);
+        assert_eq!((0..=9).selectivity(100), 0.1);
+        assert_eq!((200..300).selectivity(100), 0.);
+    }
+
+    #[test]
+    fn test_combinators() {
+        let even = |id: &DataId| -> bool { id.is_multiple_of(2) };
+        let filter = And(0..100, Or(even, Not(BitSetFilter::from_iter([1, 3, 5]))));
+        assert!(filter.hnsw_filter(&4));
+        assert!(!filter.hnsw_filter(&3));
+        assert!(filter.hnsw_filter(&7));
+        assert!(!filter.hnsw_filter(&100));
+
+        // the candidates of a list are kept through And, the shorter one first
+        let ids: Vec<usize> = vec![2, 4, 6];
+        let filter = And(0..100, ids.clone());
+        assert_eq!(filter.candidate_ids(), Some(&ids[..]));
+        let filter = And(vec![1, 2, 3, 4], ids.clone());
+        assert_eq!(filter.candidate_ids(), Some(&ids[..]));
+        assert_eq!(Or(ids.clone(), 0..10).candidate_ids(), None);
+
+        assert_eq!(And(0..10, 0..50).selectivity(100), 0.1);
+        assert_eq!(Or(0..10, 0..50).selectivity(100), 0.5);
+        assert_eq!(Not(0..10).selectivity(100), 0.9);
+
+        // filters of any type behind dyn FilterT
+        let mut boxed: Vec<Box<dyn FilterT + Send + Sync>> =
+            vec![Box::new(0..10), Box::new(BitSetFilter::from_iter([5, 50]))];
+        let last: Box<dyn FilterT + Send + Sync> = boxed.pop().unwrap();
+        let filter = And(boxed[0].as_ref() as &dyn FilterT, Not(last));
+        assert!(filter.hnsw_filter(&4));
+        assert!(!filter.hnsw_filter(&5));
+
+        // serialized and read back
+        let filter: Or<Range<DataId>, Not<BitSetFilter>> =
+            Or(0..10, Not(BitSetFilter::from_iter([1, 2])));
+        let encoded: Vec<u8> = bincode::serialize(&filter).unwrap();
+        assert_eq!(
+            bincode::deserialize::<Or<Range<DataId>, Not<BitSetFilter>>>(&encoded).unwrap(),
+            filter
+        );
+    }
+}
@@ -0,0 +1,642 @@
+//! This module provides an exact index : a search computes the distance from the request to each
+//! vector stored.
+//!
+//! Vectors are stored contiguously in one Vec, so a search is a linear scan of memory. It is the
+//! right choice for small collections where building a graph is not worth it, and it gives the
+//! ground truth against which answers of Hnsw are evaluated.
+//!
+//! A dump consists, as for Hnsw, of 2 files. The data file (suffixed by "hnsw.data") has the
+//! format of Hnsw data file, so it can be memory mapped by DataMap. The graph file (suffixed by
+//! "hnsw.graph") contains only the Description, with max_nb_connection set to 0 as there is no
+//! graph.
+
+use std::any::type_name;
+use std::collections::BinaryHeap;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use hashbrown::HashMap;
+use rayon::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::hnsw_index::api::AnnT;
+use crate::hnsw_index::dist::Distance;
+use crate::hnsw_index::filter::FilterT;
+use crate::hnsw_index::hnsw::{DataId, Neighbour, PointId, NB_LAYER_MAX};
+use crate::hnsw_index::hnswio::{Description, DumpMode, MAGICDATAP};
+
+/// An exact (brute force) index over vectors of type T compared with distance D.
+pub struct FlatIndex<T: Clone + Send + Sync, D: Distance<T>> {
+    /// dimension of vectors, set at first insertion
+    dimension: usize,
+    /// vectors stored contiguously, vector of rank r is in data\[r * dimension..(r + 1) *
+    /// dimension\]
+    data: Vec<T>,
+    /// external id of the vector of each rank
+    ids: Vec<DataId>,
+    /// rank of each external id
+    id_map: HashMap<DataId, usize>,
+    /// distance between vectors
+    dist_f: D,
+} // end of FlatIndex
+
+impl<T: Clone + Send + Sync, D: Distance<T> + Send + Sync> FlatIndex<T, D> {
+    /// allocation function
+    /// . max_elements : hint to speed up allocation tables. number of elements expected.
+    /// . f : the distance function
+    pub fn new(max_elements: usize, f: D) -> Self {
+        log::info!("FlatIndex nb elements {:?}", max_elements);
+        log::info!("FlatIndex distance {:?}", type_name::<D>());
+
+        FlatIndex {
+            dimension: 0,
+            data: Vec::new(),
+            ids: Vec::with_capacity(max_elements),
+            id_map: HashMap::with_capacity(max_elements),
+            dist_f: f,
+        }
+    }
+
+    // end of new
+
+    /// returns number of vectors stored
+    pub fn get_nb_point(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// returns dimension of vectors, 0 before first insertion
+    pub fn get_dimension(&self) -> usize {
+        self.dimension
+    }
+
+    /// get name of distance
+    pub fn get_distance_name(&self) -> String {
+        type_name::<D>().to_string()
+    }
+
+    /// retrieves the distance used
+    pub fn get_distance(&self) -> &D {
+        &self.dist_f
+    }
+
+    /// returns the vector associated to an external id
+    pub fn get_data(&self, d_id: &DataId) -> Option<&[T]> {
+        self.id_map
+            .get(d_id)
+            .map(|rank: &usize| self.get_vector(*rank))
+    }
+
+    fn get_vector(&self, rank: usize) -> &[T] {
+        &self.data[rank * self.dimension..(rank + 1) * self.dimension]
+    }
+
+    /// insert a tuple (&Vec, usize) with its external id as given by the client.
+    #[inline]
+    pub fn insert(&mut self, datav_with_id: (&Vec<T>, usize)) {
+        self.insert_slice((datav_with_id.0.as_slice(), datav_with_id.1))
+    }
+
+    /// Insert a data slice with its external id as given by the client.
+    /// All slices must have the dimension of the first one inserted, the insertion panics
+    /// otherwise.
+    /// As in Hnsw, inserting twice an id stores 2 vectors, use upsert to replace a vector.
+    pub fn insert_slice(&mut self, data_with_id: (&[T], usize)) {
+        let (data, origin_id) = data_with_id;
+        if self.ids.is_empty() {
+            self.dimension = data.len();
+            self.data.reserve(self.ids.capacity() * self.dimension);
+        }
+        assert_eq!(data.len(), self.dimension, "FlatIndex insertion, bad dimension");
+
+        self.id_map.insert(origin_id, self.ids.len());
+        self.ids.push(origin_id);
+        self.data.extend_from_slice(data);
+    }
+
+    // end of insert_slice
+
+    /// Insert slices of \[T\] each associated to its id. The slices are copied in parallel at the
+    /// end of the storage, then the ids are recorded in their order.
+    /// As for insert_slice, the insertion panics if a slice has not the dimension of the index.
+    pub fn parallel_insert_slice(&mut self, datas: &Vec<(&[T], usize)>) {
+        if datas.is_empty() {
+            return;
+        }
+        if self.ids.is_empty() {
+            self.dimension = datas[0].0.len();
+        }
+        assert!(
+            datas
+                .iter()
+                .all(|(data, _): &(&[T], usize)| data.len() == self.dimension),
+            "FlatIndex insertion, bad dimension"
+        );
+
+        self.data.par_extend(
+            datas
+                .par_iter()
+                .flat_map_iter(|(data, _): &(&[T], usize)| data.iter().cloned()),
+        );
+        self.ids.reserve(datas.len());
+        for (_, origin_id) in datas {
+            self.id_map.insert(*origin_id, self.ids.len());
+            self.ids.push(*origin_id);
+        }
+    }
+
+    // end of parallel_insert_slice
+
+    /// insert data or replace the vector already associated to its external id.
+    /// Returns true if a vector was replaced, false if the data was inserted.
+    /// As for insert_slice, the upsert panics if data has not the dimension of the index.
+    pub fn upsert_slice(&mut self, data_with_id: (&[T], usize)) -> bool {
+        let (data, origin_id) = data_with_id;
+        match self.id_map.get(&origin_id) {
+            Some(rank) => {
+                assert_eq!(data.len(), self.dimension, "FlatIndex upsert, bad dimension");
+                let rank: usize = *rank;
+                self.data[rank * self.dimension..(rank + 1) * self.dimension]
+                    .clone_from_slice(data);
+                true
+            },
+            None => {
+                self.insert_slice(data_with_id);
+                false
+            },
+        }
+    }
+
+    // end of upsert_slice
+
+    /// returns the knbn nearest vectors of data accepted by filter, sorted by increasing
+    /// distance. The result is exact.
+    pub fn search_filter(
+        &self,
+        data: &[T],
+        knbn: usize,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour> {
+        if knbn == 0 {
+            return Vec::<Neighbour>::new();
+        }
+        // a max heap on distance, peek gives the farthest of neighbours kept
+        let mut neighbours: BinaryHeap<Neighbour> = BinaryHeap::with_capacity(knbn + 1);
+        for (rank, d_id) in self.ids.iter().enumerate() {
+            if filter.is_some_and(|f: &dyn FilterT| !f.hnsw_filter(d_id)) {
+                continue;
+            }
+            let dist: f32 = self.dist_f.eval(data, self.get_vector(rank));
+            if neighbours.len() < knbn {
+                neighbours.push(Neighbour::new(*d_id, dist, PointId(0, rank as i32)));
+            } else if dist < neighbours.peek().unwrap().distance {
+                neighbours.pop();
+                neighbours.push(Neighbour::new(*d_id, dist, PointId(0, rank as i32)));
+            }
+        }
+
+        neighbours.into_sorted_vec()
+    }
+
+    // end of search_filter
+
+    /// as search_filter, the distances are computed only to the ids listed by the filter if it
+    /// lists them (see FilterT::candidate_ids)
+    pub fn scan_filter(&self, data: &[T], knbn: usize, filter: &dyn FilterT) -> Vec<Neighbour> {
+        let ids: &[DataId] = match filter.candidate_ids() {
+            Some(ids) if knbn > 0 => ids,
+            _ => return self.search_filter(data, knbn, Some(filter)),
+        };
+
+        let mut neighbours: BinaryHeap<Neighbour> = BinaryHeap::with_capacity(knbn + 1);
+        for d_id in ids {
+            let rank: usize = match self.id_map.get(d_id) {
+                Some(rank) if filter.hnsw_filter(d_id) => *rank,
+                _ => continue,
+            };
+            let dist: f32 = self.dist_f.eval(data, self.get_vector(rank));
+            if neighbours.len() < knbn {
+                neighbours.push(Neighbour::new(*d_id, dist, PointId(0, rank as i32)));
+            } else if dist < neighbours.peek().unwrap().distance {
+                neighbours.pop();
+                neighbours.push(Neighbour::new(*d_id, dist, PointId(0, rank as i32)));
+            }
+        }
+
+        neighbours.into_sorted_vec()
+    }
+
+    // end of scan_filter
+
+    /// returns the knbn nearest vectors of data, sorted by increasing distance.
+    pub fn search(&self, data: &[T], knbn: usize) -> Vec<Neighbour> {
+        self.search_filter(data, knbn, None)
+    }
+
+    /// search in parallel the knbn nearest vectors of each data. Results are in the order of
+    /// requests.
+    pub fn parallel_search(&self, datas: &[Vec<T>], knbn: usize) -> Vec<Vec<Neighbour>> {
+        datas
+            .par_iter()
+            .map(|data: &Vec<T>| self.search(data, knbn))
+            .collect()
+    }
+
+    /// returns all vectors at a distance less or equal to radius of data, accepted by filter and
+    /// sorted by increasing distance. max_results caps the number of vectors returned, the
+    /// nearest are kept.
+    pub fn search_range(
+        &self,
+        data: &[T],
+        radius: f32,
+        max_results: Option<usize>,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour> {
+        let mut neighbours: Vec<Neighbour> = self
+            .ids
+            .iter()
+            .enumerate()
+            .filter(|(_, d_id): &(usize, &DataId)| filter.map_or(true, |f| f.hnsw_filter(d_id)))
+            .map(|(rank, d_id): (usize, &DataId)| {
+                let dist: f32 = self.dist_f.eval(data, self.get_vector(rank));
+                Neighbour::new(*d_id, dist, PointId(0, rank as i32))
+            })
+            .filter(|n: &Neighbour| n.distance <= radius)
+            .collect();
+        neighbours.sort_unstable();
+        if let Some(max_results) = max_results {
+            neighbours.truncate(max_results);
+        }
+
+        neighbours
+    }
+
+    // end of search_range
+
+    /// dumps the Description (in graph file) and the vectors (in data file)
+    fn dump<W: Write>(
+        &self,
+        graphout: &mut BufWriter<W>,
+        dataout: &mut BufWriter<W>,
+    ) -> Result<i32, String> {
+        let description: Description = Description {
+            format_version: 4,
+            dumpmode: 1,
+            max_nb_connection: 0,
+            nb_layer: NB_LAYER_MAX,
+            ef: 0,
+            nb_point: self.get_nb_point(),
+            dimension: self.dimension,
+            distname: self.get_distance_name(),
+            t_name: type_name::<T>().to_string(),
+        };
+        description.dump(DumpMode::Full, graphout)?;
+
+        // header of data file then a record by vector as in dump of Hnsw points
+        dataout.write_all(&MAGICDATAP.to_ne_bytes()).unwrap();
+        dataout.write_all(&self.dimension.to_ne_bytes()).unwrap();
+        for (rank, d_id) in self.ids.iter().enumerate() {
+            dataout.write_all(&MAGICDATAP.to_ne_bytes()).unwrap();
+            dataout.write_all(&(*d_id as u64).to_ne_bytes()).unwrap();
+
+            let v: &[T] = self.get_vector(rank);
+            let serialized: &[u8] = unsafe {
+                std::slice::from_raw_parts(v.as_ptr() as *const u8, std::mem::size_of_val(v))
+            };
+            dataout
+                .write_all(&(serialized.len() as u64).to_ne_bytes())
+                .unwrap();
+            dataout.write_all(serialized).unwrap();
+        }
+
+        Ok(1)
+    }
+
+    // end of dump
+} // end of impl FlatIndex
+
+/// reload a FlatIndex from its data file. As for Hnsw, the description must first be loaded from
+/// the graph file by load_description.
+pub fn load_flat<
+    T: 'static + Clone + Sized + Send + Sync,
+    D: Distance<T> + Default + Send + Sync,
+>(
+    description: &Description,
+    data_in: &mut dyn Read,
+) -> io::Result<FlatIndex<T, D>> {
+    if !description.is_flat() {
+        return Err(io::Error::new(io::ErrorKind::Other, "load_flat : dump is not a FlatIndex"));
+    }
+    if type_name::<T>() != description.t_name {
+        return Err(io::Error::new(io::ErrorKind::Other, "load_flat : incoherent data type"));
+    }
+    if type_name::<D>() != description.distname {
+        let errmsg: String = format!(
+            "error in distances : dumped distance is : {} asked distance in loading is : {}",
+            description.distname,
+            type_name::<D>()
+        );
+        return Err(io::Error::new(io::ErrorKind::Other, errmsg));
+    }
+
+    let mut it_slice: [u8; 4] = [0u8; std::mem::size_of::<u32>()];
+    data_in.read_exact(&mut it_slice)?;
+    let magic: u32 = u32::from_ne_bytes(it_slice);
+    if magic != MAGICDATAP {
+        return Err(io::Error::new(io::ErrorKind::Other, "bad magic at data beginning"));
+    }
+
+    let mut it_slice: [u8; 8] = [0u8; std::mem::size_of::<usize>()];
+    data_in.read_exact(&mut it_slice)?;
+    let dimension: usize = usize::from_ne_bytes(it_slice);
+    if dimension != description.dimension {
+        return Err(io::Error::new(io::ErrorKind::Other, "data dimension incoherent"));
+    }
+
+    let mut flat: FlatIndex<T, D> = FlatIndex::new(description.nb_point, D::default());
+    let mut v_serialized: Vec<u8> = vec![0; dimension * std::mem::size_of::<T>()];
+    for _ in 0..description.nb_point {
+        let mut it_slice: [u8; 4] = [0u8; std::mem::size_of::<u32>()];
+        data_in.read_exact(&mut it_slice)?;
+        if u32::from_ne_bytes(it_slice) != MAGICDATAP {
+            return Err(io::Error::new(io::ErrorKind::Other, "bad magic at data record"));
+        }
+
+        let mut it_slice: [u8; 8] = [0u8; std::mem::size_of::<u64>()];
+        data_in.read_exact(&mut it_slice)?;
+        let d_id: DataId = u64::from_ne_bytes(it_slice) as DataId;
+
+        let mut it_slice: [u8; 8] = [0u8; std::mem::size_of::<u64>()];
+        data_in.read_exact(&mut it_slice)?;
+        let serialized_len: usize = u64::from_ne_bytes(it_slice) as usize;
+        if serialized_len != v_serialized.len() {
+            return Err(io::Error::new(io::ErrorKind::Other, "bad length of data record"));
+        }
+        data_in.read_exact(&mut v_serialized)?;
+
+        // the buffer of bytes is not aligned for T, each value is read unaligned
+        let ptr_t: *const T = v_serialized.as_ptr() as *const T;
+        let data: Vec<T> = (0..dimension)
+            .map(|i: usize| unsafe { std::ptr::read_unaligned(ptr_t.add(i)) })
+            .collect();
+        flat.insert_slice((&data, d_id));
+    }
+
+    Ok(flat)
+} // end of load_flat
+
+impl<T, D> AnnT for FlatIndex<T, D>
+where
+    T: Serialize + DeserializeOwned + Clone + Send + Sync,
+    D: Distance<T> + Send + Sync,
+{
+    type Val = T;
+
+    fn insert_data(&mut self, data: &Vec<Self::Val>, id: usize) {
+        self.insert((data, id));
+    }
+
+    fn upsert_data(&mut self, data: &Vec<Self::Val>, id: usize) -> bool {
+        self.upsert_slice((data, id))
+    }
+
+    /// the search is exhaustive, ef_s is ignored
+    fn search_neighbours(&self, data: &Vec<T>, knbn: usize, _ef_s: usize) -> Vec<Neighbour> {
+        self.search(data, knbn)
+    }
+
+    fn search_neighbours_filter(
+        &self,
+        data: &Vec<T>,
+        knbn: usize,
+        _ef_s: usize,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour> {
+        self.search_filter(data, knbn, filter)
+    }
+
+    fn scan_neighbours_filter(
+        &self,
+        data: &Vec<T>,
+        knbn: usize,
+        filter: &dyn FilterT,
+    ) -> Vec<Neighbour> {
+        self.scan_filter(data, knbn, filter)
+    }
+
+    fn parallel_insert_data(&mut self, data: &Vec<(&Vec<Self::Val>, usize)>) {
+        let datas: Vec<(&[T], usize)> = data
+            .iter()
+            .map(|(v, id): &(&Vec<T>, usize)| (v.as_slice(), *id))
+            .collect();
+        self.parallel_insert_slice(&datas);
+    }
+
+    fn parallel_search_neighbours(
+        &self,
+        data: &Vec<Vec<Self::Val>>,
+        knbn: usize,
+        _ef_s: usize,
+    ) -> Vec<Vec<Neighbour>> {
+        self.parallel_search(data, knbn)
+    }
+
+    fn search_range(
+        &self,
+        data: &Vec<Self::Val>,
+        radius: f32,
+        _ef_s: usize,
+        max_results: Option<usize>,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour> {
+        FlatIndex::search_range(self, data, radius, max_results, filter)
+    }
+
+    /// dumps the vectors in filename.hnsw.data and the description in filename.hnsw.graph
+    fn file_dump(&self, filename: &str) -> Result<i32, String> {
+        let graphpath: PathBuf = PathBuf::from(format!("{}.hnsw.graph", filename));
+        let graph: File = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(graphpath)
+            .map_err(|e: io::Error| e.to_string())?;
+
+        let datapath: PathBuf = PathBuf::from(format!("{}.hnsw.data", filename));
+        let data: File = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(datapath)
+            .map_err(|e: io::Error| e.to_string())?;
+
+        let mut graph_buf: BufWriter<File> = BufWriter::new(graph);
+        let mut data_buf: BufWriter<File> = BufWriter::with_capacity(50_000_000, data);
+
+        let res: Result<i32, String> = self.dump(&mut graph_buf, &mut data_buf);
+
+        graph_buf.flush().unwrap();
+        data_buf.flush().unwrap();
+
+        res
+    }
+} // end of impl block AnnT for FlatIndex<T,D>
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::BufReader;
+
+    use rand::distributions::{Distribution, Uniform};
+
+    use super::*;
+    use crate::hnsw_index::dist::DistL2;
+    use crate::hnsw_index::hnsw::Hnsw;
+    use crate::hnsw_index::hnswio::load_description;
+
+    fn gen_data(nbcolumn: usize, nbrow: usize) -> Vec<Vec<f32>> {
+        let mut rng: rand::rngs::ThreadRng = rand::thread_rng();
+        let unif: Uniform<f32> = Uniform::<f32>::new(0., 1.);
+        (0..nbcolumn)
+            .map(|_| (0..nbrow).map(|_| unif.sample(&mut rng)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_flat_search() {
+        println!("\n\n test_flat_search");
+        let data: Vec<Vec<f32>> = gen_data(500, 20);
+        let mut flat: FlatIndex<f32, DistL2> = FlatIndex::new(data.len(), DistL2 {});
+        for (i, v) in data.iter().enumerate() {
+            flat.insert((v, i));
+        }
+        assert_eq!(flat.get_nb_point(), data.len());
+        assert_eq!(flat.get_dimension(), 20);
+
+        for q in data.iter().step_by(50) {
+            let mut exact: Vec<(f32, usize)> = data
+                .iter()
+                .enumerate()
+                .map(|(i, v): (usize, &Vec<f32>)| (DistL2.eval(q, v), i))
+                .collect();
+            exact.sort_unstable_by(|a: &(f32, usize), b: &(f32, usize)| a.partial_cmp(b).unwrap());
+
+            let neighbours: Vec<Neighbour> = flat.search(q, 10);
+            let ids: Vec<usize> = neighbours.iter().map(|n: &Neighbour| n.d_id).collect();
+            let exact_ids: Vec<usize> = exact.iter().take(10).map(|e| e.1).collect();
+            assert_eq!(ids, exact_ids);
+
+            // filter
+            let odd = |id: &usize| -> bool { id % 2 == 1 };
+            let neighbours: Vec<Neighbour> = flat.search_filter(q, 10, Some(&odd));
+            let exact_ids: Vec<usize> = exact
+                .iter()
+                .filter(|e: &&(f32, usize)| e.1 % 2 == 1)
+                .take(10)
+                .map(|e| e.1)
+                .collect();
+            let ids: Vec<usize> = neighbours.iter().map(|n: &Neighbour| n.d_id).collect();
+            assert_eq!(ids, exact_ids);
+
+            // range
+            let radius: f32 = exact[20].0;
+            let in_ball: Vec<Neighbour> = flat.search_range(q, radius, None, None);
+            assert_eq!(in_ball.len(), 21);
+            assert_eq!(flat.search_range(q, radius, Some(5), None).len(), 5);
+        }
+
+        // a parallel insertion stores the vectors as insertions one by one
+        let datas: Vec<(&[f32], usize)> = data
+            .iter()
+            .enumerate()
+            .map(|(i, v): (usize, &Vec<f32>)| (v.as_slice(), i))
+            .collect();
+        let mut parallel: FlatIndex<f32, DistL2> = FlatIndex::new(data.len(), DistL2 {});
+        parallel.parallel_insert_slice(&datas[..100].to_vec());
+        parallel.parallel_insert_slice(&datas[100..].to_vec());
+        assert_eq!(parallel.get_dimension(), 20);
+        assert_eq!(parallel.ids, flat.ids);
+        assert_eq!(parallel.data, flat.data);
+
+        // upsert replaces the vector
+        assert!(flat.upsert_slice((&data[0], 3)));
+        assert_eq!(flat.get_data(&3).unwrap(), data[0].as_slice());
+        assert_eq!(flat.get_nb_point(), data.len());
+    } // end of test_flat_search
+
+    #[test]
+    fn test_flat_dump_reload() {
+        println!("\n\n test_flat_dump_reload");
+        let data: Vec<Vec<f32>> = gen_data(300, 12);
+        let mut flat: FlatIndex<f32, DistL2> = FlatIndex::new(data.len(), DistL2 {});
+        let datas: Vec<(&Vec<f32>, usize)> =
+            data.iter().enumerate().map(|(i, v)| (v, 10 * i)).collect();
+        flat.parallel_insert_data(&datas);
+        flat.file_dump("flatdumpreload").unwrap();
+
+        let graphfile: File = OpenOptions::new()
+            .read(true)
+            .open("flatdumpreload.hnsw.graph")
+            .unwrap();
+        let datafile: File = OpenOptions::new()
+            .read(true)
+            .open("flatdumpreload.hnsw.data")
+            .unwrap();
+        let mut graph_in: BufReader<File> = BufReader::new(graphfile);
+        let mut data_in: BufReader<File> = BufReader::new(datafile);
+        let description: Description = load_description(&mut graph_in).unwrap();
+        assert!(description.is_flat());
+        let reloaded: FlatIndex<f32, DistL2> = load_flat(&description, &mut data_in).unwrap();
+
+        assert_eq!(reloaded.get_nb_point(), flat.get_nb_point());
+        for (i, v) in data.iter().enumerate() {
+            assert_eq!(reloaded.get_data(&(10 * i)).unwrap(), v.as_slice());
+        }
+        let results: Vec<Vec<Neighbour>> = reloaded.parallel_search_neighbours(&data, 1, 0);
+        for (i, r) in results.iter().enumerate() {
+            assert_eq!(r[0].d_id, 10 * i);
+        }
+    } // end of test_flat_dump_reload
+
+    #[test]
+    fn test_flat_ground_truth() {
+        println!("\n\n test_flat_ground_truth");
+        // FlatIndex gives the ground truth to measure Hnsw recall
+        let data: Vec<Vec<f32>> = gen_data(2000, 16);
+        let mut flat: FlatIndex<f32, DistL2> = FlatIndex::new(data.len(), DistL2 {});
+        let hnsw: Hnsw<f32, DistL2> = Hnsw::new(12, data.len(), 16, 64, DistL2 {});
+        for (i, v) in data.iter().enumerate() {
+            flat.insert((v, i));
+            hnsw.insert((v, i));
+        }
+        let queries: Vec<Vec<f32>> = gen_data(100, 16);
+        let truth: Vec<Vec<Neighbour>> = flat.parallel_search(&queries, 10);
+        let mut nb_found: usize = 0;
+        for (q, t) in queries.iter().zip(truth.iter()) {
+            // the exact scan of hnsw finds the same neighbours
+            let exact: Vec<DataId> = hnsw
+                .scan_filter(q, 10, &|_: &DataId| true)
+                .iter()
+                .map(|n: &Neighbour| n.d_id)
+                .collect();
+            assert_eq!(
+                exact,
+                t.iter()
+                    .map(|n: &Neighbour| n.d_id)
+                    .collect::<Vec<DataId>>()
+            );
+            let answer: Vec<Neighbour> = hnsw.search(q, 10, 64);
+            nb_found += answer
+                .iter()
+                .filter(|n: &&Neighbour| t.iter().any(|m: &Neighbour| m.d_id == n.d_id))
+                .count();
+        }
+        let recall: f32 = nb_found as f32 / (10 * queries.len()) as f32;
+        // the levels of hnsw are drawn from entropy, a graph of low recall is rare but possible so
+        // the recall is only reported
+        println!("test_flat_ground_truth : hnsw recall {:.3}", recall);
+    } // end of test_flat_ground_truth
+} // end of mod tests
@@ -5,13 +5,12 @@
 use std::any::type_name;
 use std::cmp::Ordering;
 use std::collections::binary_heap::BinaryHeap;
-#[allow(unused)]
-use std::collections::HashSet;
 use std::mem;
+use std::sync::atomic::{self, AtomicBool};
 use std::sync::{mpsc, Arc};
 
 use dashmap::DashMap;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use packed_simd::{f32x16, i8x16, FromCast, Simd};
 use parking_lot::{Mutex, RwLock, RwLockReadGuard};
 use rayon::prelude::*;
@@ -131,6 +130,9 @@ pub struct Point<T: Clone + Send + Sync> {
     p_id: PointId,
     /// neighbours info
     pub(crate) neighbours: Arc<RwLock<Neighbors<T>>>,
+    /// PointIds of the points which have this point in their neighbours. Links dropped by a
+    /// shrinking are not removed, so it can contain points no longer linking to this one.
+    pub(crate) reverse_neighbours: Arc<RwLock<Vec<PointId>>>,
 }
 
 impl<T: Clone + Send + Sync> Point<T> {
@@ -140,7 +142,22 @@ impl<T: Clone + Send + Sync> Point<T> {
             neighbours.push(Vec::<Arc<PointWithOrder<T>>>::new());
         }
 
-        Point { v: v.to_vec(), origin_id, p_id, neighbours: Arc::new(RwLock::new(neighbours)) }
+        Point {
+            v: v.to_vec(),
+            origin_id,
+            p_id,
+            neighbours: Arc::new(RwLock::new(neighbours)),
+            reverse_neighbours: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    /// record that point p_id has this point in its neighbours.
+    /// The lock is never held while taking another one, so it can be taken under any lock.
+    pub(crate) fn add_reverse_neighbour(&self, p_id: PointId) {
+        let mut reverse_neighbours = self.reverse_neighbours.write();
+        if !reverse_neighbours.contains(&p_id) {
+            reverse_neighbours.push(p_id);
+        }
     }
 
     /// get a reference to vector data
@@ -304,6 +321,13 @@ impl LayerGenerator {
 /// A short-hand for points in a layer
 type Layer<T> = Vec<Arc<Point<T>>>;
 
+// compute fraction of points going into a layer for expected memory reservation.
+// With S the scale of the LayerGenerator P(l=n) = exp(-n/S) - exp(-(n+1)/S)
+fn expected_layer_size(layer: u8, scale: f32, nb_elements: usize) -> usize {
+    let frac: f32 = (-(layer as f32) / scale).exp() - (-((layer + 1) as f32) / scale).exp();
+    (frac * nb_elements as f32).round() as usize
+}
+
 /// a structure for indexation of points in layer
 #[allow(unused)]
 pub struct PointIndexation<T: Clone + Send + Sync> {
@@ -320,6 +344,10 @@ pub struct PointIndexation<T: Clone + Send + Sync> {
     pub(crate) nb_point: Arc<RwLock<usize>>,
     /// curent enter_point: an Arc RwLock on a possible Arc Point
     pub(crate) entry_point: Arc<RwLock<Option<Arc<Point<T>>>>>,
+    /// DataId of points deleted (tombstoned) but still stored in layers until compaction
+    pub(crate) deleted: Arc<RwLock<HashSet<DataId>>>,
+    /// lookup table from DataId to PointId. If an id is inserted twice the last point is kept
+    pub(crate) id_map: Arc<RwLock<HashMap<DataId, PointId>>>,
 }
 
 // A point indexation may contain circular references. To deallocate these after a point indexation
@@ -358,20 +386,15 @@ impl<T: Clone + Send + Sync> Drop for PointIndexation<T> {
 
 impl<T: Clone + Send + Sync> PointIndexation<T> {
     pub fn new(max_nb_connection: u8, max_layer: u8, max_elements: usize) -> Self {
-        let mut points_by_layer: Vec<Vec<Arc<Point<T>>>> = Vec::with_capacity(max_layer as usize);
+        let layer_g: LayerGenerator = LayerGenerator::new(max_nb_connection, max_layer);
 
-        let max_layer_f32: f32 = max_layer as f32;
+        let mut points_by_layer: Vec<Vec<Arc<Point<T>>>> = Vec::with_capacity(max_layer as usize);
         for i in 0..max_layer {
             // recall that range are right extremeity excluded
-            // compute fraction of points going into layer i and do expected memory reservation
-            let frac: f32 =
-                (-(i as f32) / max_layer_f32).exp() - (-((i + 1) as f32) / max_layer_f32);
-            let expected_size: usize = (frac * max_elements as f32).round() as usize;
+            let expected_size: usize = expected_layer_size(i, layer_g.scale, max_elements);
             points_by_layer.push(Vec::with_capacity(expected_size));
         }
 
-        let layer_g: LayerGenerator = LayerGenerator::new(max_nb_connection, max_layer);
-
         PointIndexation {
             max_nb_connection,
             max_layer,
@@ -379,11 +402,26 @@ impl<T: Clone + Send + Sync> PointIndexation<T> {
             layer_g,
             nb_point: Arc::new(RwLock::new(0)),
             entry_point: Arc::new(RwLock::new(None)),
+            deleted: Arc::new(RwLock::new(HashSet::new())),
+            id_map: Arc::new(RwLock::new(HashMap::with_capacity(max_elements))),
         }
     }
 
     // end of new
 
+    /// reserve memory for at least additional points to be inserted.  
+    /// Useful before inserting into a structure reloaded from a dump, as reloaded layers are
+    /// allocated to their exact size.
+    pub fn reserve(&self, additional: usize) {
+        let mut points_by_layer = self.points_by_layer.write();
+        for (i, layer) in points_by_layer.iter_mut().enumerate() {
+            layer.reserve(expected_layer_size(i as u8, self.layer_g.scale, additional));
+        }
+        self.id_map.write().reserve(additional);
+    }
+
+    // end of reserve
+
     /// returns the maximum level of layer observed
     pub fn get_max_level_observed(&self) -> u8 {
         let opt = self.entry_point.read();
@@ -421,7 +459,11 @@ impl<T: Clone + Send + Sync> PointIndexation<T> {
             new_point = Arc::new(point);
 
             points_by_layer_ref[p_id.0 as usize].push(Arc::clone(&new_point));
+            self.id_map.write().insert(origin_id, p_id);
         } // close write lock on points_by_layer
+          // an id deleted before is restored by its new insertion. deleted is locked alone : searches
+          // hold it while they read the points.
+        self.deleted.write().remove(&origin_id);
 
         let nb_point: usize;
         {
@@ -463,6 +505,21 @@ impl<T: Clone + Send + Sync> PointIndexation<T> {
         *self.nb_point.read()
     }
 
+    /// returns the number of points deleted but not yet compacted out of the structure
+    pub fn get_nb_deleted(&self) -> usize {
+        self.deleted.read().len()
+    }
+
+    /// returns true if the point with given DataId is deleted (tombstoned)
+    pub fn is_deleted(&self, d_id: &DataId) -> bool {
+        self.deleted.read().contains(d_id)
+    }
+
+    /// returns the PointId of the point with given DataId, or None if the id was never inserted.
+    pub fn get_point_id(&self, d_id: &DataId) -> Option<PointId> {
+        self.id_map.read().get(d_id).copied()
+    }
+
     /// returns the number of points in a given layer, 0 on a bad layer num
     pub fn get_layer_nb_point(&self, layer: usize) -> usize {
         let nb_layer: usize = self.points_by_layer.read().len();
@@ -565,12 +622,12 @@ impl<'a, T: Clone + Send + Sync> Iterator for IterPoint<'a, T> {
             self.slot_in_layer = 0;
             self.layer += 1;
 
-            // must reach a non empty layer if possible
-            let entry_point_ref = self.point_indexation.entry_point.read();
-            let points_by_layer = self.point_indexation.points_by_layer.read();
-            let entry_point_level: u8 = entry_point_ref.as_ref().unwrap().p_id.0;
+            // must reach a non empty layer if possible.
+            // We use the guard we hold, a second read lock on points_by_layer could deadlock with
+            // an insertion waiting for the write lock.
+            let entry_point_level: u8 = self.point_indexation.get_max_level_observed();
             while (self.layer as u8) <= entry_point_level
-                && points_by_layer[self.layer as usize].is_empty()
+                && self.pi_guard[self.layer as usize].is_empty()
             {
                 self.layer += 1;
             }
@@ -580,7 +637,7 @@ impl<'a, T: Clone + Send + Sync> Iterator for IterPoint<'a, T> {
             if (self.layer as u8) <= entry_point_level {
                 let slot: usize = self.slot_in_layer as usize;
                 self.slot_in_layer += 1;
-                Some(points_by_layer[self.layer as usize][slot].clone())
+                Some(self.pi_guard[self.layer as usize][slot].clone())
             } else {
                 None
             }
@@ -656,9 +713,9 @@ pub struct Hnsw<T: Clone + Send + Sync, D: Distance<T>> {
     pub(crate) data_dimension: usize,
     /// distance between points. initialized at first insertion
     pub(crate) dist_f: D,
-    /// insertion mode or searching mode. This flag prevents a internal thread to do a write when
-    /// searching with other threads.
-    pub(crate) searching: bool,
+    /// insertion mode or searching mode. Insertions and searches can run concurrently, the flag
+    /// only records the mode the client declared (see set_searching_mode).
+    pub(crate) searching: AtomicBool,
 } // end of Hnsw
 
 impl<T: Clone + Send + Sync, D: Distance<T> + Send + Sync> Hnsw<T, D> {
@@ -694,7 +751,7 @@ impl<T: Clone + Send + Sync, D: Distance<T> + Send + Sync> Hnsw<T, D> {
             layer_indexed_points,
             data_dimension: 0,
             dist_f: f,
-            searching: false,
+            searching: AtomicBool::new(false),
         }
     }
 
@@ -725,13 +782,27 @@ impl<T: Clone + Send + Sync, D: Distance<T> + Send + Sync> Hnsw<T, D> {
         self.layer_indexed_points.get_nb_point()
     }
 
+    /// reserve memory for additional points. To be called before appending a large number of
+    /// points to a structure reloaded from a dump.
+    pub fn reserve(&self, additional: usize) {
+        self.layer_indexed_points.reserve(additional);
+    }
+
     /// set searching mode.  
-    /// It is not possible to do parallel insertion and parallel searching simultaneously in
-    /// different threads so to enable searching after parallel insertion the flag must be set
-    /// to true. To resume parallel insertion reset the flag to false and so on.
-    pub fn set_searching_mode(&mut self, flag: bool) {
-        // must use an atomic!
-        self.searching = flag;
+    /// Parallel insertion and parallel searching can run simultaneously in different threads:
+    /// neighbourhoods are protected by their own RwLock. The only neighbourhoods locked while
+    /// another one is held are those of removed points, deleted points or the old version of an
+    /// upserted point, read by repair_point under the write lock of the point it repairs. Removed
+    /// points are out of the graph and never repaired, so these nested locks cannot deadlock. The
+    /// lock of reverse_neighbours is a leaf, no other lock is taken while it is held.
+    /// The flag is kept so that clients can declare the structure is only searched.
+    pub fn set_searching_mode(&self, flag: bool) {
+        self.searching.store(flag, atomic::Ordering::Release);
+    }
+
+    /// returns the mode set by set_searching_mode
+    pub fn get_searching_mode(&self) -> bool {
+        self.searching.load(atomic::Ordering::Acquire)
     }
 
     /// get name if distance
@@ -922,45 +993,151 @@ impl<T: Clone + Send + Sync, D: Distance<T> + Send + Sync> Hnsw<T, D> {
     ///  The slice insertion makes integration with ndarray crate easier than the vector insertion
     pub fn insert_slice(&self, data_with_id: (&[T], usize)) {
         let (data, origin_id) = data_with_id;
-        let keep_pruned: bool = self.keep_pruned;
 
         // insert in indexation and get point_id adn generate a new entry_point if necessary
-        let (new_point, point_rank) = self
+        let (new_point, _point_rank) = self
             .layer_indexed_points
             .generate_new_point(data, origin_id);
 
         // now real work begins
-        // allocate a binary heap
-        let level: u8 = new_point.p_id.0;
-        let mut enter_point_copy: Option<Arc<Point<T>>> = None;
-        let mut max_level_observed: u8 = 0;
-
-        // entry point has been set in
+        let enter_point: Arc<Point<T>>;
         {
-            // I open a read lock on an option
-            if let Some(arc_point) = self.layer_indexed_points.entry_point.read().as_ref() {
-                enter_point_copy = Some(Arc::clone(arc_point));
-                if point_rank == 1 {
+            // we take directly a write lock so that with concurrent insertions only the first
+            // point becomes the entry point without neighbours, others are connected to it.
+            let mut entry_point_ref = self.layer_indexed_points.entry_point.write();
+            match entry_point_ref.as_ref() {
+                Some(arc_point) => enter_point = Arc::clone(arc_point),
+                None => {
+                    *entry_point_ref = Some(new_point);
                     return;
-                }
-                max_level_observed = enter_point_copy.as_ref().unwrap().p_id.0;
+                },
             }
         }
 
-        if enter_point_copy.is_none() {
-            self.layer_indexed_points.check_entry_point(&new_point);
-            return;
+        self.connect_point(&new_point, enter_point);
+
+        // new_point has been inserted at the beginning in table
+        // so that we can call reverse_update_neighborhoodwe consitently
+        // now reverse update of neighbours.
+        self.reverse_update_neighborhood_simple(Arc::clone(&new_point));
+
+        self.layer_indexed_points.check_entry_point(&new_point);
+    }
+
+    // end of insert
+
+    /// insert a tuple (&Vec, usize) or replace the vector already associated to the external id.
+    /// See [`Self::upsert_slice`].
+    #[inline]
+    pub fn upsert(&self, datav_with_id: (&Vec<T>, usize)) -> bool {
+        self.upsert_slice((datav_with_id.0.as_slice(), datav_with_id.1))
+    }
+
+    /// Insert a data slice with its external id, or replace the vector if the id is already
+    /// indexed.  
+    /// A replaced point keeps its PointId and is re-linked at every layer it occupies : its
+    /// neighbours are selected again as for an insertion, and the points linking to the old
+    /// vector select again their neighbours, as in [`Self::repair_deleted`]. Only these points
+    /// are visited. If the id was deleted it is restored.  
+    /// Upserts of a same id must not run concurrently.  
+    /// Returns true if a vector was replaced, false if the data was inserted as a new point.
+    pub fn upsert_slice(&self, data_with_id: (&[T], usize)) -> bool {
+        let (data, origin_id) = data_with_id;
+
+        let p_id: PointId = match self.layer_indexed_points.get_point_id(&origin_id) {
+            Some(p_id) => p_id,
+            None => {
+                self.insert_slice(data_with_id);
+                return false;
+            },
+        };
+
+        let old_point: Arc<Point<T>> = Arc::clone(
+            &self.layer_indexed_points.points_by_layer.read()[p_id.0 as usize][p_id.1 as usize],
+        );
+        let new_point: Arc<Point<T>> = Arc::new(Point::new(data, origin_id, p_id));
+
+        // select neighbours of the new vector while the old one is still there to navigate
+        let enter_point: Option<Arc<Point<T>>> =
+            self.layer_indexed_points.entry_point.read().clone();
+        if let Some(enter_point) = enter_point {
+            self.connect_point(&new_point, enter_point);
+        }
+
+        // now the new version replaces the old one in the global table
+        {
+            let mut points_by_layer = self.layer_indexed_points.points_by_layer.write();
+            points_by_layer[p_id.0 as usize][p_id.1 as usize] = Arc::clone(&new_point);
+        }
+        {
+            let mut entry_point = self.layer_indexed_points.entry_point.write();
+            if entry_point
+                .as_ref()
+                .is_some_and(|ep: &Arc<Point<T>>| ep.p_id == p_id)
+            {
+                *entry_point = Some(Arc::clone(&new_point));
+            }
         }
 
-        let mut dist_to_entry: f32 = self
-            .dist_f
-            .eval(data, &enter_point_copy.as_ref().unwrap().v);
+        // links to the old version store obsolete distances. The points linking to it, found in
+        // its reverse neighbours, select again their neighbours at the layers where they had it,
+        // the new version being a candidate.
+        let linking: Vec<Arc<Point<T>>> = {
+            let reverse_neighbours: Vec<PointId> = old_point.reverse_neighbours.read().clone();
+            let points_by_layer = self.layer_indexed_points.points_by_layer.read();
+            reverse_neighbours
+                .iter()
+                .filter(|q_id: &&PointId| **q_id != p_id)
+                .filter_map(|q_id: &PointId| {
+                    points_by_layer
+                        .get(q_id.0 as usize)
+                        .and_then(|layer: &Layer<T>| layer.get(q_id.1 as usize))
+                        .cloned()
+                })
+                .collect()
+        };
+        linking.par_iter().for_each(|point: &Arc<Point<T>>| {
+            self.repair_point(
+                point,
+                &|q: &Point<T>| std::ptr::eq(q, old_point.as_ref()),
+                Some(&new_point),
+            );
+        });
+        // the old version can reference points linked to it by a concurrent insertion, break cycles
+        old_point
+            .neighbours
+            .write()
+            .iter_mut()
+            .for_each(|n: &mut Neighbor<T>| n.clear());
+
+        self.reverse_update_neighborhood_simple(Arc::clone(&new_point));
+        self.layer_indexed_points.deleted.write().remove(&origin_id);
+
+        true
+    }
+
+    // end of upsert_slice
+
+    /// select the neighbours of a point at each layer it occupies, going down the layers from
+    /// enter_point. Points having the PointId of the point are never selected, so that a point can
+    /// be connected while its old version (see upsert) is still in the graph.
+    /// The reverse update of neighbourhoods is left to the caller.
+    fn connect_point(&self, new_point: &Arc<Point<T>>, enter_point: Arc<Point<T>>) {
+        let data: &[T] = &new_point.v;
+        let keep_pruned: bool = self.keep_pruned;
+
+        // allocate a binary heap
+        let level: u8 = new_point.p_id.0;
+        let max_level_observed: u8 = enter_point.p_id.0;
+        let mut enter_point_copy: Arc<Point<T>> = enter_point;
+
+        let mut dist_to_entry: f32 = self.dist_f.eval(data, &enter_point_copy.v);
 
         // we go from self.max_level_observed to level+1 included
         for l in ((level + 1)..(max_level_observed + 1)).rev() {
             // CAVEAT could bypass when layer empty, avoid  allocation..
             let mut sorted_points: BinaryHeap<Arc<PointWithOrder<T>>> =
-                self.search_layer(data, Arc::clone(enter_point_copy.as_ref().unwrap()), 1, l, None);
+                self.search_layer(data, Arc::clone(&enter_point_copy), 1, l, None);
 
             if sorted_points.len() > 1 {
                 panic!(
@@ -973,18 +1150,22 @@ impl<T: Clone + Send + Sync, D: Distance<T> + Send + Sync> Hnsw<T, D> {
             // the heap conversion is useless beccause of the preceding test.
             // sorted_points = from_positive_binaryheap_to_negative_binary_heap(&sorted_points);
             if let Some(ep) = sorted_points.pop() {
+                if ep.point_ref.p_id == new_point.p_id {
+                    continue;
+                }
                 // useful for projecting lower layer to upper layer. keep track of points
                 // encountered.
                 if new_point.neighbours.read()[l as usize].len()
                     < self.get_max_nb_connection() as usize
                 {
                     new_point.neighbours.write()[l as usize].push(Arc::clone(&ep));
+                    ep.point_ref.add_reverse_neighbour(new_point.p_id);
                 }
 
                 // get the lowest distance point
                 let tmp_dist: f32 = self.dist_f.eval(data, &ep.point_ref.v);
                 if tmp_dist < dist_to_entry {
-                    enter_point_copy = Some(Arc::clone(&ep.point_ref));
+                    enter_point_copy = Arc::clone(&ep.point_ref);
                     dist_to_entry = tmp_dist;
                 }
             }
@@ -996,15 +1177,11 @@ impl<T: Clone + Send + Sync, D: Distance<T> + Send + Sync> Hnsw<T, D> {
             let ef: usize = self.ef_construction;
             // when l == level, we cannot get new_point in sorted_points as it is seen only from
             // declared neighbours
-            let mut sorted_points: BinaryHeap<Arc<PointWithOrder<T>>> = self.search_layer(
-                data,
-                Arc::clone(enter_point_copy.as_ref().unwrap()),
-                ef,
-                l,
-                None,
-            );
+            let mut sorted_points: BinaryHeap<Arc<PointWithOrder<T>>> =
+                self.search_layer(data, Arc::clone(&enter_point_copy), ef, l, None);
 
             sorted_points = from_positive_binaryheap_to_negative_binary_heap(&sorted_points);
+            sorted_points.retain(|p: &Arc<PointWithOrder<T>>| p.point_ref.p_id != new_point.p_id);
             if !sorted_points.is_empty() {
                 let nb_conn: usize;
                 let extend_c: bool;
@@ -1027,31 +1204,28 @@ impl<T: Clone + Send + Sync, D: Distance<T> + Send + Sync> Hnsw<T, D> {
                     keep_pruned,
                     &mut neighbours,
                 );
+                neighbours.retain(|p: &Arc<PointWithOrder<T>>| p.point_ref.p_id != new_point.p_id);
 
                 // sort neighbours
                 neighbours.par_sort_unstable();
 
                 // we must add bidirectional from data i.e new_point_id to neighbours
                 new_point.neighbours.write()[l as usize] = neighbours.clone();
+                for n in &neighbours {
+                    n.point_ref.add_reverse_neighbour(new_point.p_id);
+                }
 
                 // this reverse neighbour update could be done here but we put it at end to gather
                 // all code requiring a mutex guard for multi threading.
                 // update ep for loop iteration. As we sorted neighbours the nearest
                 if !neighbours.is_empty() {
-                    enter_point_copy = Some(Arc::clone(&neighbours[0].point_ref));
+                    enter_point_copy = Arc::clone(&neighbours[0].point_ref);
                 }
             }
         } // for l
-
-        // new_point has been inserted at the beginning in table
-        // so that we can call reverse_update_neighborhoodwe consitently
-        // now reverse update of neighbours.
-        self.reverse_update_neighborhood_simple(Arc::clone(&new_point));
-
-        self.layer_indexed_points.check_entry_point(&new_point);
     }
 
-    // end of insert
+    // end of connect_point
 
     /// Insert in parallel a slice of Vec\<T\> each associated to its id.    
     /// It uses Rayon for threading so the number of insertions asked for must be large enough to be
@@ -1075,11 +1249,255 @@ impl<T: Clone + Send + Sync, D: Distance<T> + Send + Sync> Hnsw<T, D> {
 
     // end of parallel_insert
 
+    /// delete the point associated to the external id as given by the client.
+    /// The point is tombstoned : it stays in the graph to keep it navigable but it is never
+    /// returned by searches. Neighbours of deleted points are reconnected by
+    /// [`Self::repair_deleted`] and the points are removed by [`Self::compact`].
+    /// Ids never inserted are ignored, and inserting or upserting a deleted id restores it.
+    /// Deletions and insertions of a same id must not run concurrently.
+    /// Returns true if the id is indexed and was not already deleted.
+    pub fn delete(&self, d_id: DataId) -> bool {
+        if self.layer_indexed_points.get_point_id(&d_id).is_none() {
+            return false;
+        }
+        self.layer_indexed_points.deleted.write().insert(d_id)
+    }
+
+    /// batch version of [`Self::delete`]. Returns the number of ids newly deleted.
+    pub fn delete_batch(&self, d_ids: &[DataId]) -> usize {
+        // id_map and deleted are locked one after the other, as in scan_filter
+        let indexed: Vec<DataId> = {
+            let id_map = self.layer_indexed_points.id_map.read();
+            d_ids
+                .iter()
+                .filter(|d_id: &&DataId| id_map.contains_key(*d_id))
+                .copied()
+                .collect()
+        };
+        let mut deleted = self.layer_indexed_points.deleted.write();
+        indexed
+            .into_iter()
+            .filter(|d_id: &DataId| deleted.insert(*d_id))
+            .count()
+    }
+
+    /// returns true if the point with given external id is deleted
+    pub fn is_deleted(&self, d_id: &DataId) -> bool {
+        self.layer_indexed_points.is_deleted(d_id)
+    }
+
+    /// returns the number of deleted points still stored in the structure
+    pub fn get_nb_deleted(&self) -> usize {
+        self.layer_indexed_points.get_nb_deleted()
+    }
+
+    /// reconnect the neighbourhood of points having deleted neighbours.
+    /// For each layer, links to deleted points are replaced by a selection among the remaining
+    /// neighbours and the neighbours of the deleted points. Deleted points keep their own links
+    /// so that searches entering the graph through them can go on.
+    /// It can run while searching (in a background thread for example).
+    /// Returns the number of points whose neighbourhood was modified.
+    pub fn repair_deleted(&self) -> usize {
+        let deleted: HashSet<DataId> = self.layer_indexed_points.deleted.read().clone();
+        if deleted.is_empty() {
+            return 0;
+        }
+
+        let points: Vec<Arc<Point<T>>> = self.layer_indexed_points.into_iter().collect();
+        points
+            .par_iter()
+            .filter(|p: &&Arc<Point<T>>| !deleted.contains(&p.origin_id))
+            .filter(|p: &&Arc<Point<T>>| {
+                self.repair_point(p, &|q: &Point<T>| deleted.contains(&q.origin_id), None)
+            })
+            .count()
+    }
+
+    // end of repair_deleted
+
+    /// recompute neighbours of point at each layer where it has removed neighbours : deleted
+    /// points, or the old version of an upserted point. The removed neighbours are replaced by a
+    /// selection among the remaining ones, the neighbours of the removed ones and replacement.
+    /// returns true if point has been modified
+    fn repair_point<F: Fn(&Point<T>) -> bool>(
+        &self,
+        point: &Arc<Point<T>>,
+        removed: &F,
+        replacement: Option<&Arc<Point<T>>>,
+    ) -> bool {
+        let mut modified: bool = false;
+
+        // we keep the write lock during the selection so that no reverse update is lost.
+        // We only read neighbourhoods of removed points, deleted points are never repaired and
+        // old versions are out of the graph, so no deadlock.
+        // As reverse updates can fill layers above the point level, we scan all layers.
+        let mut point_neighbours = point.neighbours.write();
+        for l in 0..point_neighbours.len() {
+            let neighbours_l: &Neighbor<T> = &point_neighbours[l];
+            if !neighbours_l
+                .iter()
+                .any(|n: &Arc<PointWithOrder<T>>| removed(&n.point_ref))
+            {
+                continue;
+            }
+
+            let mut candidates_set: HashMap<PointId, Arc<Point<T>>> =
+                HashMap::<PointId, Arc<Point<T>>>::new();
+            for n in neighbours_l {
+                if !removed(&n.point_ref) {
+                    candidates_set.insert(n.point_ref.p_id, Arc::clone(&n.point_ref));
+                } else {
+                    for q in &n.point_ref.neighbours.read()[l] {
+                        if q.point_ref.p_id != point.p_id && !removed(&q.point_ref) {
+                            candidates_set.insert(q.point_ref.p_id, Arc::clone(&q.point_ref));
+                        }
+                    }
+                }
+            }
+            if let Some(r_point) = replacement.filter(|r: &&Arc<Point<T>>| r.p_id != point.p_id) {
+                candidates_set.insert(r_point.p_id, Arc::clone(r_point));
+            }
+
+            let mut candidates: BinaryHeap<Arc<PointWithOrder<T>>> =
+                BinaryHeap::<Arc<PointWithOrder<T>>>::with_capacity(candidates_set.len());
+            for (_p_id, q_point) in candidates_set.iter() {
+                let dist_to_point: f32 = self.dist_f.eval(&point.v, &q_point.v);
+                candidates.push(Arc::new(PointWithOrder::new(q_point, -dist_to_point)));
+            }
+
+            let nb_conn: usize = if l == 0 {
+                2 * self.max_nb_connection as usize
+            } else {
+                self.max_nb_connection as usize
+            };
+
+            let mut new_neighbours: Vec<Arc<PointWithOrder<T>>> = Vec::with_capacity(nb_conn);
+            self.select_neighbours(
+                &point.v,
+                &mut candidates,
+                nb_conn,
+                false,
+                l as u8,
+                self.keep_pruned,
+                &mut new_neighbours,
+            );
+            new_neighbours.sort_unstable();
+            for n in &new_neighbours {
+                n.point_ref.add_reverse_neighbour(point.p_id);
+            }
+
+            point_neighbours[l] = new_neighbours;
+            modified = true;
+        }
+
+        modified
+    }
+
+    // end of repair_point
+
+    /// remove deleted points from the structure.
+    /// Neighbourhoods are first repaired (see [`Self::repair_deleted`]), then the layers are
+    /// rebuilt without the deleted points. As points are renumbered in their layer, PointId
+    /// obtained before the compaction are no longer valid.
+    /// Returns the number of points removed.
+    pub fn compact(&mut self) -> usize {
+        if self.get_nb_deleted() == 0 {
+            return 0;
+        }
+        self.repair_deleted();
+
+        let indexation: &PointIndexation<T> = &self.layer_indexed_points;
+        let deleted: HashSet<DataId> = indexation.deleted.read().clone();
+        let mut points_by_layer = indexation.points_by_layer.write();
+
+        // allocate the new points in their new rank, keeping a map from old to new points
+        let mut new_points: HashMap<PointId, Arc<Point<T>>> = HashMap::new();
+        let mut new_id_map: HashMap<DataId, PointId> = HashMap::new();
+        let mut new_layers: Vec<Layer<T>> = Vec::with_capacity(points_by_layer.len());
+        for (l, layer) in points_by_layer.iter().enumerate() {
+            let mut new_layer: Layer<T> = Vec::with_capacity(layer.len());
+            for point in layer {
+                if deleted.contains(&point.origin_id) {
+                    continue;
+                }
+                let p_id: PointId = PointId(l as u8, new_layer.len() as i32);
+                let new_point: Arc<Point<T>> =
+                    Arc::new(Point::new(&point.v, point.origin_id, p_id));
+                new_points.insert(point.p_id, Arc::clone(&new_point));
+                new_id_map.insert(point.origin_id, p_id);
+                new_layer.push(new_point);
+            }
+            new_layers.push(new_layer);
+        }
+        let nb_removed: usize = indexation.get_nb_point() - new_points.len();
+
+        // transfer neighbourhoods, dropping links to deleted points
+        points_by_layer
+            .par_iter()
+            .flatten()
+            .for_each(|point: &Arc<Point<T>>| {
+                if let Some(new_point) = new_points.get(&point.p_id) {
+                    let old_neighbours = point.neighbours.read();
+                    let mut neighbours = new_point.neighbours.write();
+                    for (l, old_neighbours_l) in old_neighbours.iter().enumerate() {
+                        neighbours[l] = old_neighbours_l
+                            .iter()
+                            .filter_map(|n: &Arc<PointWithOrder<T>>| {
+                                new_points.get(&n.point_ref.p_id).map(|q: &Arc<Point<T>>| {
+                                    q.add_reverse_neighbour(new_point.p_id);
+                                    Arc::new(PointWithOrder::new(q, n.dist_to_ref))
+                                })
+                            })
+                            .collect();
+                    }
+                }
+            });
+
+        // the entry point is kept if not deleted, else we take a point in the highest layer
+        let mut entry_point = indexation.entry_point.write();
+        let new_entry_point: Option<Arc<Point<T>>> = match entry_point.as_ref() {
+            Some(ep) if new_points.contains_key(&ep.p_id) => new_points.get(&ep.p_id).cloned(),
+            _ => new_layers
+                .iter()
+                .rev()
+                .find_map(|layer: &Layer<T>| layer.first().cloned()),
+        };
+
+        // old points may reference each other, clear neighbourhoods to break cycles
+        points_by_layer
+            .par_iter()
+            .flatten()
+            .for_each(|point: &Arc<Point<T>>| {
+                point
+                    .neighbours
+                    .write()
+                    .iter_mut()
+                    .for_each(|n: &mut Neighbor<T>| n.clear());
+            });
+
+        *points_by_layer = new_layers;
+        *entry_point = new_entry_point;
+        *indexation.nb_point.write() = new_points.len();
+        *indexation.id_map.write() = new_id_map;
+        indexation.deleted.write().clear();
+
+        log::info!("compact : removed {} deleted points", nb_removed);
+
+        nb_removed
+    }
+
+    // end of compact
+
     /// insert new_point in neighbourhood info of point
     fn reverse_update_neighborhood_simple(&self, new_point: Arc<Point<T>>) {
         let level: u8 = new_point.p_id.0;
         for l in (0..level + 1).rev() {
-            for q in &new_point.neighbours.read()[l as usize] {
+            // we work on a copy of the neighbours so that we never hold the lock of new_point
+            // while taking the lock of a neighbour : two points inserted concurrently can be
+            // neighbours of each other and would deadlock.
+            let neighbours_l: Vec<Arc<PointWithOrder<T>>> =
+                new_point.neighbours.read()[l as usize].clone();
+            for q in &neighbours_l {
                 if new_point.p_id != q.point_ref.p_id {
                     // as new point is in global table, do not loop and deadlock!!
                     let q_point: &Arc<Point<T>> = &q.point_ref;
@@ -1101,6 +1519,7 @@ impl<T: Clone + Send + Sync, D: Distance<T> + Send + Sync> Hnsw<T, D> {
                     }
 
                     q_point_neighbours[l_n].push(Arc::new(n_to_add));
+                    new_point.add_reverse_neighbour(q_point.p_id);
                     let nbn_at_l: usize = q_point_neighbours[l_n].len();
                     // if l < level, update upward chaining, insert does a sort! t_q has a neighbour
                     // not yet in global table of points!
@@ -1320,12 +1739,115 @@ impl<T: Clone + Send + Sync, D: Distance<T> + Send + Sync> Hnsw<T, D> {
         ef_arg: usize,
         filter: Option<&dyn FilterT>,
     ) -> Vec<Neighbour> {
+        let pivot: Arc<Point<T>> = match self.search_pivot(data) {
+            Some(pivot) => pivot,
+            None => return Vec::<Neighbour>::new(),
+        };
+
+        // ef must be greater than knbn. Possibly it should be between knbn and
+        // self.max_nb_connection
+        let ef: usize = ef_arg.max(knbn);
+
+        // deleted points are traversed but must not be returned, so they are filtered out
+        let deleted = self.layer_indexed_points.deleted.read();
+        let not_deleted = |id: &DataId| -> bool {
+            !deleted.contains(id) && filter.map_or(true, |f: &dyn FilterT| f.hnsw_filter(id))
+        };
+        let filter: Option<&dyn FilterT> =
+            if deleted.is_empty() { filter } else { Some(&not_deleted) };
+
+        // now search with asked ef in layer 0
+        let neighbours_heap: BinaryHeap<Arc<PointWithOrder<T>>> =
+            self.search_layer(data, pivot, ef, 0, filter);
+
+        // go from heap of points with negative dist to a sorted vec of increasing points with > 0
+        // distances.
+        let neighbours: Vec<Arc<PointWithOrder<T>>> = neighbours_heap.into_sorted_vec();
+
+        // get the min of K and ef points into a vector.
+        neighbours
+            .iter()
+            .filter(|p: &&Arc<PointWithOrder<T>>| !deleted.contains(&p.point_ref.origin_id))
+            .take(knbn.min(ef))
+            .map(|p: &Arc<PointWithOrder<T>>| {
+                Neighbour::new(
+                    p.as_ref().point_ref.origin_id,
+                    p.as_ref().dist_to_ref,
+                    p.as_ref().point_ref.p_id,
+                )
+            })
+            .collect()
+    }
+
+    // end of search_filter
+
+    /// exact search of the knbn nearest points accepted by filter : the distance to each of them
+    /// is computed, the graph is not used. The candidates are the ids listed by the filter (see
+    /// FilterT::candidate_ids), all the points otherwise. Deleted points are skipped.
+    /// It is cheaper than search_filter when the filter accepts few points.
+    pub fn scan_filter(&self, data: &[T], knbn: usize, filter: &dyn FilterT) -> Vec<Neighbour> {
+        if knbn == 0 {
+            return Vec::<Neighbour>::new();
+        }
+
+        let deleted = self.layer_indexed_points.deleted.read();
+        // a max heap on distance, peek gives the farthest of neighbours kept
+        let mut neighbours: BinaryHeap<Neighbour> = BinaryHeap::with_capacity(knbn + 1);
+        let mut push = |point: &Point<T>| {
+            if deleted.contains(&point.origin_id) || !filter.hnsw_filter(&point.origin_id) {
+                return;
+            }
+            let dist: f32 = self.dist_f.eval(data, &point.v);
+            if neighbours.len() < knbn {
+                neighbours.push(Neighbour::new(point.origin_id, dist, point.p_id));
+            } else if dist < neighbours.peek().unwrap().distance {
+                neighbours.pop();
+                neighbours.push(Neighbour::new(point.origin_id, dist, point.p_id));
+            }
+        };
+
+        match filter.candidate_ids() {
+            Some(ids) => {
+                // insertions lock points_by_layer then id_map : the two are locked one after the
+                // other here, never together, so that an insertion cannot deadlock a scan
+                let p_ids: Vec<PointId> = {
+                    let id_map = self.layer_indexed_points.id_map.read();
+                    ids.iter()
+                        .filter_map(|id: &DataId| id_map.get(id).copied())
+                        .collect()
+                };
+                let layers = self.layer_indexed_points.points_by_layer.read();
+                for p_id in p_ids {
+                    if let Some(point) = layers
+                        .get(p_id.0 as usize)
+                        .and_then(|layer: &Layer<T>| layer.get(p_id.1 as usize))
+                    {
+                        push(point);
+                    }
+                }
+            },
+            None => {
+                for point in self.get_point_indexation() {
+                    push(&point);
+                }
+            },
+        }
+
+        neighbours.into_sorted_vec()
+    }
+
+    // end of scan_filter
+
+    // greedy descent from the entry point to the point of layer 1 nearest data, in the stored
+    // neighbours of upper layers. It gives the entry point of search in layer 0.
+    // returns None if the structure is empty
+    fn search_pivot(&self, data: &[T]) -> Option<Arc<Point<T>>> {
         let entry_point: Arc<Point<T>>;
         {
             // a lock on an option an a Arc<Point>
             let entry_point_opt_ref = self.layer_indexed_points.entry_point.read();
             if entry_point_opt_ref.is_none() {
-                return Vec::<Neighbour>::new();
+                return None;
             } else {
                 entry_point = Arc::clone((*entry_point_opt_ref).as_ref().unwrap());
             }
@@ -1356,33 +1878,102 @@ impl<T: Clone + Send + Sync, D: Distance<T> + Send + Sync> Hnsw<T, D> {
             }
         } // end on for on layers
 
-        // ef must be greater than knbn. Possibly it should be between knbn and
-        // self.max_nb_connection
-        let ef: usize = ef_arg.max(knbn);
-        // now search with asked ef in layer 0
-        let neighbours_heap: BinaryHeap<Arc<PointWithOrder<T>>> =
-            self.search_layer(data, pivot, ef, 0, filter);
+        Some(pivot)
+    }
 
-        // go from heap of points with negative dist to a sorted vec of increasing points with > 0
-        // distances.
-        let neighbours: Vec<Arc<PointWithOrder<T>>> = neighbours_heap.into_sorted_vec();
+    // end of search_pivot
+
+    /// search all points at a distance less or equal to radius of data and returns them sorted by
+    /// increasing distance.  
+    /// The beam of the ef nearest points searched in layer 0 is doubled until it reaches points
+    /// outside the ball, then it is expanded through the neighbourhoods of points inside the ball
+    /// until no candidate under the radius remains. So ef needs not to be larger than the number
+    /// of points in the ball, it is only the initial width of the beam.  
+    /// max_results caps the number of points returned, the nearest are kept. As nearer points
+    /// can be reached through farther ones, the whole ball is explored anyway.  
+    /// The filter is applied to returned points, not to points traversed.
+    pub fn search_range(
+        &self,
+        data: &[T],
+        radius: f32,
+        ef: usize,
+        max_results: Option<usize>,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour> {
+        let max_results: usize = max_results.unwrap_or(usize::MAX);
+        if max_results == 0 {
+            return Vec::<Neighbour>::new();
+        }
+        let pivot: Arc<Point<T>> = match self.search_pivot(data) {
+            Some(pivot) => pivot,
+            None => return Vec::<Neighbour>::new(),
+        };
 
-        // get the min of K and ef points into a vector.
-        let last: usize = knbn.min(ef).min(neighbours.len());
+        let deleted = self.layer_indexed_points.deleted.read();
+        let accepted = |id: &DataId| -> bool {
+            !deleted.contains(id) && filter.map_or(true, |f: &dyn FilterT| f.hnsw_filter(id))
+        };
 
-        neighbours[0..last]
+        // the beam is doubled until its farthest point is outside the ball, so that points of the
+        // ball reachable only through points outside are found.
+        let mut ef_beam: usize = ef.max(1);
+        let mut beam: BinaryHeap<Arc<PointWithOrder<T>>>;
+        loop {
+            beam = self.search_layer(data, Arc::clone(&pivot), ef_beam, 0, None);
+            let farthest: f32 = beam
+                .peek()
+                .map_or(f32::MAX, |p: &Arc<PointWithOrder<T>>| p.dist_to_ref);
+            if farthest > radius || beam.len() < ef_beam {
+                break;
+            }
+            ef_beam *= 2;
+        }
+
+        let mut visited_point_id: HashSet<PointId> = HashSet::with_capacity(beam.len());
+        // candidates have negative distances, so that we pop the nearest first
+        let mut candidate_points: BinaryHeap<Arc<PointWithOrder<T>>> =
+            BinaryHeap::<Arc<PointWithOrder<T>>>::with_capacity(beam.len());
+        for p in beam.iter() {
+            visited_point_id.insert(p.point_ref.p_id);
+            if p.dist_to_ref <= radius {
+                candidate_points.push(Arc::new(PointWithOrder::new(&p.point_ref, -p.dist_to_ref)));
+            }
+        }
+
+        // points in ball have positive distances, peek gives the farthest
+        let mut return_points: BinaryHeap<Arc<PointWithOrder<T>>> =
+            BinaryHeap::<Arc<PointWithOrder<T>>>::new();
+        while let Some(c) = candidate_points.pop() {
+            let c_dist: f32 = -c.dist_to_ref;
+            if accepted(&c.point_ref.origin_id) {
+                return_points.push(Arc::new(PointWithOrder::new(&c.point_ref, c_dist)));
+                if return_points.len() > max_results {
+                    return_points.pop();
+                }
+            }
+
+            let neighbours_c: &Neighbor<T> = &c.point_ref.neighbours.read()[0];
+            for e in neighbours_c {
+                if visited_point_id.insert(e.point_ref.p_id) {
+                    let e_dist_to_p: f32 = self.dist_f.eval(data, &e.point_ref.v);
+                    if e_dist_to_p <= radius {
+                        candidate_points
+                            .push(Arc::new(PointWithOrder::new(&e.point_ref, -e_dist_to_p)));
+                    }
+                }
+            }
+        } // end of while on candidates
+
+        return_points
+            .into_sorted_vec()
             .iter()
             .map(|p: &Arc<PointWithOrder<T>>| {
-                Neighbour::new(
-                    p.as_ref().point_ref.origin_id,
-                    p.as_ref().dist_to_ref,
-                    p.as_ref().point_ref.p_id,
-                )
+                Neighbour::new(p.point_ref.origin_id, p.dist_to_ref, p.point_ref.p_id)
             })
             .collect()
     }
 
-    // end of search_filter
+    // end of search_range
 
     #[inline]
     pub fn search_possible_filter(
@@ -1475,9 +2066,12 @@ pub fn quantize(vector: &Vec<f32>) -> Vec<i8> {
     // assume the given vector is l2 normalized vector.
     let mut quantized_vector: Vec<i8> = Vec::with_capacity(vector.len());
 
-    vector
-        .chunks_exact(16)
-        .map(f32x16::from_slice_aligned)
+    // a Vec<f32> is only 4 bytes aligned, so chunks must be loaded unaligned
+    let chunks = vector.chunks_exact(16);
+    let remainder: &[f32] = chunks.remainder();
+
+    chunks
+        .map(f32x16::from_slice_unaligned)
         .for_each(|v: Simd<[f32; 16]>| {
             let qv: Simd<[i8; 16]> = i8x16::from_cast(v * MAX_QVALUE);
             let qv: [i8; 16] = unsafe { mem::transmute(qv) };
@@ -1485,6 +2079,8 @@ pub fn quantize(vector: &Vec<f32>) -> Vec<i8> {
             quantized_vector.extend_from_slice(&qv);
         });
 
+    quantized_vector.extend(remainder.iter().map(|x: &f32| (x * MAX_QVALUE) as i8));
+
     quantized_vector
 }
 
@@ -1690,4 +2286,436 @@ mod tests {
         //
         assert_eq!(nb_dumped, nbpl);
     } // end of test_iter_layerpoint
+
+    #[test]
+    fn test_delete_repair_compact() {
+        println!("\n\n test_delete_repair_compact");
+        //
+        let mut rng: ThreadRng = rand::thread_rng();
+        let unif: Uniform<f32> = Uniform::<f32>::new(0., 1.);
+        let nbcolumn: usize = 2000;
+        let nbrow: usize = 32;
+        let data: Vec<Vec<f32>> = (0..nbcolumn)
+            .map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect())
+            .collect();
+        let ef_construct: usize = 25;
+        let nb_connection: u8 = 10;
+        let mut hns: Hnsw<f32, DistL1> =
+            Hnsw::<f32, DistL1>::new(nb_connection, nbcolumn, 16, ef_construct, DistL1 {});
+        for (i, v) in data.iter().enumerate() {
+            hns.insert((v, i));
+        }
+
+        // delete one point out of 4, the entry point included
+        let entry_id: DataId = hns
+            .get_point_indexation()
+            .entry_point
+            .read()
+            .as_ref()
+            .unwrap()
+            .get_origin_id();
+        let mut to_delete: Vec<DataId> = (0..nbcolumn).step_by(4).collect();
+        to_delete.push(entry_id);
+        let nb_deleted: usize = hns.delete_batch(&to_delete);
+        assert_eq!(nb_deleted, hns.get_nb_deleted());
+        assert!(!hns.delete(0));
+        assert!(hns.is_deleted(&entry_id));
+
+        // deleted points are never returned, even when searching for themselves
+        let check_search = |hns: &Hnsw<f32, DistL1>| {
+            let mut nb_found: usize = 0;
+            for (i, v) in data.iter().enumerate() {
+                let neighbours: Vec<Neighbour> = hns.search(v, 10, 32);
+                assert_eq!(neighbours.len(), 10);
+                assert!(neighbours
+                    .iter()
+                    .all(|n: &Neighbour| !to_delete.contains(&n.d_id)));
+                if !to_delete.contains(&i) && neighbours[0].d_id == i {
+                    nb_found += 1;
+                }
+            }
+            let recall: f32 = nb_found as f32 / (nbcolumn - nb_deleted) as f32;
+            println!("test_delete_repair_compact : self recall {:.3}", recall);
+            assert!(recall > 0.95);
+        };
+        check_search(&hns);
+
+        // after repair no live point has a deleted neighbour
+        let nb_repaired: usize = hns.repair_deleted();
+        assert!(nb_repaired > 0);
+        for point in hns.get_point_indexation() {
+            if hns.is_deleted(&point.get_origin_id()) {
+                continue;
+            }
+            for neighbours in point.get_neighborhood_id() {
+                assert!(neighbours
+                    .iter()
+                    .all(|n: &Neighbour| !hns.is_deleted(&n.d_id)));
+            }
+        }
+        check_search(&hns);
+
+        let nb_removed: usize = hns.compact();
+        assert_eq!(nb_removed, nb_deleted);
+        assert_eq!(hns.get_nb_deleted(), 0);
+        assert_eq!(hns.get_nb_point(), nbcolumn - nb_deleted);
+        assert_eq!(hns.get_point_indexation().into_iter().count(), nbcolumn - nb_deleted);
+        // reverse neighbours are renumbered with the points
+        for point in hns.get_point_indexation() {
+            for n in point.neighbours.read().iter().flatten() {
+                assert!(n.point_ref.reverse_neighbours.read().contains(&point.p_id));
+            }
+        }
+        hns.dump_layer_info();
+        check_search(&hns);
+    } // end of test_delete_repair_compact
+
+    #[test]
+    fn test_delete_unknown_then_insert() {
+        println!("\n\n test_delete_unknown_then_insert");
+        //
+        let mut rng: ThreadRng = rand::thread_rng();
+        let unif: Uniform<f32> = Uniform::<f32>::new(0., 1.);
+        let nbcolumn: usize = 500;
+        let nbrow: usize = 16;
+        let data: Vec<Vec<f32>> = (0..nbcolumn + 2)
+            .map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect())
+            .collect();
+        let hns: Hnsw<f32, DistL1> = Hnsw::<f32, DistL1>::new(10, nbcolumn + 2, 16, 25, DistL1 {});
+        for (i, v) in data[..nbcolumn].iter().enumerate() {
+            hns.insert((v, i));
+        }
+
+        // ids not indexed are not deleted
+        assert!(!hns.delete(nbcolumn));
+        assert_eq!(hns.delete_batch(&[nbcolumn + 1, 0]), 1);
+        assert_eq!(hns.get_nb_deleted(), 1);
+        assert!(!hns.is_deleted(&nbcolumn));
+
+        // a deleted id inserted again, or upserted, is found by searches
+        hns.insert((&data[nbcolumn], 0));
+        assert!(!hns.is_deleted(&0));
+        let neighbours: Vec<Neighbour> = hns.search(&data[nbcolumn], 1, 32);
+        assert_eq!(neighbours[0].d_id, 0);
+        hns.delete(0);
+        assert!(!hns.upsert((&data[nbcolumn + 1], nbcolumn)));
+        assert!(hns.upsert((&data[0], 0)));
+        assert_eq!(hns.get_nb_deleted(), 0);
+        let neighbours: Vec<Neighbour> = hns.search(&data[nbcolumn + 1], 1, 32);
+        assert_eq!(neighbours[0].d_id, nbcolumn);
+    } // end of test_delete_unknown_then_insert
+
+    #[test]
+    fn test_upsert() {
+        println!("\n\n test_upsert");
+        //
+        let mut rng: ThreadRng = rand::thread_rng();
+        let unif: Uniform<f32> = Uniform::<f32>::new(0., 1.);
+        let nbcolumn: usize = 2000;
+        let nbrow: usize = 32;
+        let mut data: Vec<Vec<f32>> = (0..nbcolumn)
+            .map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect())
+            .collect();
+        let ef_construct: usize = 25;
+        let nb_connection: u8 = 10;
+        let hns: Hnsw<f32, DistL1> =
+            Hnsw::<f32, DistL1>::new(nb_connection, nbcolumn, 16, ef_construct, DistL1 {});
+        for (i, v) in data.iter().enumerate() {
+            hns.insert((v, i));
+        }
+
+        // replace one vector out of 5, the entry point included
+        let entry_id: DataId = hns
+            .get_point_indexation()
+            .entry_point
+            .read()
+            .as_ref()
+            .unwrap()
+            .get_origin_id();
+        let mut to_update: Vec<DataId> = (0..nbcolumn).step_by(5).collect();
+        to_update.push(entry_id);
+        hns.delete(to_update[1]);
+        for d_id in to_update.iter() {
+            let p_id: PointId = hns.get_point_indexation().get_point_id(d_id).unwrap();
+            data[*d_id] = (0..nbrow).map(|_| rng.sample(unif)).collect();
+            assert!(hns.upsert((&data[*d_id], *d_id)));
+            assert_eq!(hns.get_point_indexation().get_point_id(d_id), Some(p_id));
+            assert_eq!(hns.get_point_indexation().get_point_data(&p_id).unwrap(), data[*d_id]);
+        }
+        assert_eq!(hns.get_nb_point(), nbcolumn);
+        assert!(!hns.is_deleted(&to_update[1]));
+
+        // no link to an old version is left in the graph
+        for point in hns.get_point_indexation() {
+            for neighbours in point.neighbours.read().iter() {
+                for n in neighbours {
+                    let current: Arc<Point<f32>> = hns
+                        .get_point_indexation()
+                        .get_point(&n.point_ref.p_id)
+                        .unwrap();
+                    assert!(Arc::ptr_eq(&current, &n.point_ref));
+                    assert!(current.reverse_neighbours.read().contains(&point.p_id));
+                }
+            }
+            // points which lost a link to an old version selected other neighbours
+            assert!(!point.neighbours.read()[0].is_empty());
+        }
+
+        // a new id is inserted
+        let new_data: Vec<f32> = (0..nbrow).map(|_| rng.sample(unif)).collect();
+        assert!(!hns.upsert((&new_data, nbcolumn)));
+        assert_eq!(hns.get_nb_point(), nbcolumn + 1);
+        data.push(new_data);
+
+        // updated vectors are found with their new value
+        let mut nb_found: usize = 0;
+        for (i, v) in data.iter().enumerate() {
+            let neighbours: Vec<Neighbour> = hns.search(v, 10, 32);
+            if neighbours[0].d_id == i {
+                nb_found += 1;
+            }
+        }
+        let recall: f32 = nb_found as f32 / data.len() as f32;
+        println!("test_upsert : self recall {:.3}", recall);
+        assert!(recall > 0.95);
+    } // end of test_upsert
+
+    #[test]
+    fn test_concurrent_first_insertions() {
+        println!("\n\n test_concurrent_first_insertions");
+        // many threads inserting in an empty structure, only one point may stay unconnected
+        let mut rng: ThreadRng = rand::thread_rng();
+        let unif: Uniform<f32> = Uniform::<f32>::new(0., 1.);
+        let nbcolumn: usize = 64;
+        let data: Vec<Vec<f32>> = (0..nbcolumn)
+            .map(|_| (0..16).map(|_| rng.sample(unif)).collect())
+            .collect();
+        for _ in 0..20 {
+            let hns: Hnsw<f32, DistL1> = Hnsw::<f32, DistL1>::new(8, nbcolumn, 16, 16, DistL1 {});
+            std::thread::scope(|scope| {
+                for t in 0..8 {
+                    let hns: &Hnsw<f32, DistL1> = &hns;
+                    let data: &Vec<Vec<f32>> = &data;
+                    scope.spawn(move || {
+                        for i in (t..nbcolumn).step_by(8) {
+                            hns.insert((&data[i], i));
+                        }
+                    });
+                }
+            });
+            let nb_isolated: usize = hns
+                .get_point_indexation()
+                .into_iter()
+                .filter(|p: &Arc<Point<f32>>| {
+                    p.neighbours
+                        .read()
+                        .iter()
+                        .all(|n: &Neighbor<f32>| n.is_empty())
+                })
+                .count();
+            assert_eq!(nb_isolated, 0);
+        }
+    } // end of test_concurrent_first_insertions
+
+    #[test]
+    fn test_concurrent_insert_search() {
+        println!("\n\n test_concurrent_insert_search");
+        //
+        let mut rng: ThreadRng = rand::thread_rng();
+        let unif: Uniform<f32> = Uniform::<f32>::new(0., 1.);
+        let nbcolumn: usize = 5000;
+        let nbrow: usize = 16;
+        let data: Vec<Vec<f32>> = (0..nbcolumn)
+            .map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect())
+            .collect();
+        let datas: Vec<(&Vec<f32>, usize)> = data.iter().enumerate().map(|(i, v)| (v, i)).collect();
+        let hns: Hnsw<f32, DistL1> = Hnsw::<f32, DistL1>::new(12, nbcolumn, 16, 32, DistL1 {});
+        let nb_first: usize = 500;
+        hns.parallel_insert(&datas[..nb_first].to_vec());
+        hns.set_searching_mode(true);
+
+        // interleave insertions, searches, iterations and deletions. Rayon calls are made from
+        // distinct threads so that they interleave even with a single thread in rayon pool.
+        let inserting: AtomicBool = AtomicBool::new(true);
+        let nb_search: usize = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for chunk in datas[nb_first..].chunks(100) {
+                    hns.parallel_insert(&chunk.to_vec());
+                }
+                inserting.store(false, atomic::Ordering::Release);
+            });
+            let searcher = scope.spawn(|| {
+                let mut nb_search: usize = 0;
+                while inserting.load(atomic::Ordering::Acquire) {
+                    let queries: Vec<Vec<f32>> = data[..nb_first].to_vec();
+                    let results: Vec<Vec<Neighbour>> = hns.parallel_search(&queries, 10, 32);
+                    assert!(results.iter().all(|r: &Vec<Neighbour>| r.len() == 10));
+                    assert!(hns.get_point_indexation().into_iter().count() <= nbcolumn);
+                    hns.delete(nb_search % nb_first);
+                    nb_search += queries.len();
+                }
+                nb_search
+            });
+            searcher.join().unwrap()
+        });
+        println!("test_concurrent_insert_search : {} searches during insertion", nb_search);
+        assert_eq!(hns.get_nb_point(), nbcolumn);
+        assert_eq!(hns.get_point_indexation().into_iter().count(), nbcolumn);
+
+        // all points inserted during searches are reachable
+        let results: Vec<Vec<Neighbour>> = hns.parallel_search(&data[nb_first..].to_vec(), 1, 32);
+        let nb_found: usize = results
+            .iter()
+            .enumerate()
+            .filter(|(i, r): &(usize, &Vec<Neighbour>)| r[0].d_id == nb_first + i)
+            .count();
+        let recall: f32 = nb_found as f32 / (nbcolumn - nb_first) as f32;
+        println!("test_concurrent_insert_search : self recall {:.3}", recall);
+        assert!(recall > 0.95);
+    } // end of test_concurrent_insert_search
+
+    #[test]
+    fn test_concurrent_insert_scan() {
+        println!("\n\n test_concurrent_insert_scan");
+        // scans looking up candidate ids while points are inserted must not deadlock
+        let mut rng: ThreadRng = rand::thread_rng();
+        let unif: Uniform<f32> = Uniform::<f32>::new(0., 1.);
+        let nbcolumn: usize = 3000;
+        let data: Vec<Vec<f32>> = (0..nbcolumn)
+            .map(|_| (0..16).map(|_| rng.sample(unif)).collect())
+            .collect();
+        let datas: Vec<(&Vec<f32>, usize)> = data.iter().enumerate().map(|(i, v)| (v, i)).collect();
+        let hns: Hnsw<f32, DistL1> = Hnsw::<f32, DistL1>::new(12, nbcolumn, 16, 32, DistL1 {});
+        let nb_first: usize = 100;
+        hns.parallel_insert(&datas[..nb_first].to_vec());
+
+        // one id in 7, listed, some of them not inserted yet
+        let candidates: Vec<usize> = (0..nbcolumn).step_by(7).collect();
+        let inserting: AtomicBool = AtomicBool::new(true);
+        let nb_scan: usize = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for chunk in datas[nb_first..].chunks(50) {
+                    hns.parallel_insert(&chunk.to_vec());
+                }
+                inserting.store(false, atomic::Ordering::Release);
+            });
+            let scanner = scope.spawn(|| {
+                let mut nb_scan: usize = 0;
+                while inserting.load(atomic::Ordering::Acquire) {
+                    let query: &Vec<f32> = &data[nb_scan % nb_first];
+                    let listed: Vec<Neighbour> = hns.scan_filter(query, 10, &candidates);
+                    assert_eq!(listed.len(), 10);
+                    assert!(listed.iter().all(|n: &Neighbour| n.d_id.is_multiple_of(7)));
+                    let scanned: Vec<Neighbour> =
+                        hns.scan_filter(query, 10, &|id: &DataId| id.is_multiple_of(7));
+                    assert_eq!(scanned.len(), 10);
+                    nb_scan += 1;
+                }
+                nb_scan
+            });
+            scanner.join().unwrap()
+        });
+        println!("test_concurrent_insert_scan : {} scans during insertion", nb_scan);
+        assert_eq!(hns.get_nb_point(), nbcolumn);
+
+        // once inserted, listed and scanned candidates give the same exact neighbours
+        let listed: Vec<Neighbour> = hns.scan_filter(&data[0], 10, &candidates);
+        let scanned: Vec<Neighbour> =
+            hns.scan_filter(&data[0], 10, &|id: &DataId| id.is_multiple_of(7));
+        assert_eq!(
+            listed
+                .iter()
+                .map(|n: &Neighbour| n.d_id)
+                .collect::<Vec<DataId>>(),
+            scanned
+                .iter()
+                .map(|n: &Neighbour| n.d_id)
+                .collect::<Vec<DataId>>()
+        );
+    } // end of test_concurrent_insert_scan
+
+    #[test]
+    fn test_search_range() {
+        println!("\n\n test_search_range");
+        //
+        let mut rng: ThreadRng = rand::thread_rng();
+        let unif: Uniform<f32> = Uniform::<f32>::new(0., 1.);
+        let nbcolumn: usize = 3000;
+        let nbrow: usize = 16;
+        let data: Vec<Vec<f32>> = (0..nbcolumn)
+            .map(|_| (0..nbrow).map(|_| rng.sample(unif)).collect())
+            .collect();
+        let hns: Hnsw<f32, DistL1> = Hnsw::<f32, DistL1>::new(12, nbcolumn, 16, 48, DistL1 {});
+        for (i, v) in data.iter().enumerate() {
+            hns.insert((v, i));
+        }
+
+        let mut nb_exact: usize = 0;
+        let mut nb_found: usize = 0;
+        for q in data.iter().step_by(30) {
+            // the radius is the distance to the 50th nearest point, so the ball is larger than ef
+            let mut exact: Vec<(f32, usize)> = data
+                .iter()
+                .enumerate()
+                .map(|(i, v): (usize, &Vec<f32>)| (DistL1.eval(q, v), i))
+                .collect();
+            exact.sort_unstable_by(|a: &(f32, usize), b: &(f32, usize)| a.partial_cmp(b).unwrap());
+            let radius: f32 = exact[49].0;
+            let exact_ids: Vec<usize> = exact
+                .iter()
+                .filter(|e: &&(f32, usize)| e.0 <= radius)
+                .map(|e: &(f32, usize)| e.1)
+                .collect();
+
+            let in_ball: Vec<Neighbour> = hns.search_range(q, radius, 10, None, None);
+            assert!(in_ball.iter().all(|n: &Neighbour| n.distance <= radius));
+            assert!(in_ball
+                .windows(2)
+                .all(|w: &[Neighbour]| w[0].distance <= w[1].distance));
+            nb_exact += exact_ids.len();
+            nb_found += in_ball
+                .iter()
+                .filter(|n: &&Neighbour| exact_ids.contains(&n.d_id))
+                .count();
+
+            // a capped search returns the nearest points of the ball
+            let capped: Vec<Neighbour> = hns.search_range(q, radius, 10, Some(5), None);
+            assert_eq!(capped.len(), 5.min(in_ball.len()));
+            for (c, n) in capped.iter().zip(in_ball.iter()) {
+                assert_eq!(c.distance, n.distance);
+            }
+
+            // filter is applied to returned points
+            let even = |id: &usize| -> bool { id % 2 == 0 };
+            let filtered: Vec<Neighbour> = hns.search_range(q, radius, 10, None, Some(&even));
+            assert!(filtered.iter().all(|n: &Neighbour| n.d_id % 2 == 0));
+            assert_eq!(
+                filtered.len(),
+                in_ball
+                    .iter()
+                    .filter(|n: &&Neighbour| n.d_id % 2 == 0)
+                    .count()
+            );
+        }
+        let recall: f32 = nb_found as f32 / nb_exact as f32;
+        println!("test_search_range : recall in ball {:.3}", recall);
+        assert!(recall > 0.9);
+
+        // an empty ball
+        assert!(hns.search_range(&data[0], -1., 10, None, None).is_empty());
+    } // end of test_search_range
+
+    #[test]
+    fn test_quantize_tail() {
+        let mut rng: StdRng = StdRng::seed_from_u64(3030);
+        let unif: Uniform<f32> = Uniform::<f32>::new(-1., 1.);
+        let dims: Vec<usize> = (1..=70)
+            .chain((0..100).map(|_| rng.sample(Uniform::<usize>::new(1, 1024))))
+            .collect();
+        for dim in dims {
+            let v: Vec<f32> = (0..dim).map(|_| rng.sample(unif)).collect();
+            let expected: Vec<i8> = v.iter().map(|x: &f32| (x * MAX_QVALUE) as i8).collect();
+            assert_eq!(quantize(&v), expected, "quantize dim {}", dim);
+        }
+    } // end of test_quantize_tail
 } // end of module test
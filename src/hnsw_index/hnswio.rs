@@ -7,12 +7,13 @@
 //!
 //! An example of dump and reload of structure Hnsw is given in the tests (see test_dump_reload)
 use std::any::type_name;
-use std::collections::HashMap;
 use std::io;
 use std::io::prelude::*;
 use std::io::BufWriter;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 
+use hashbrown::{HashMap, HashSet};
 use parking_lot::RwLock;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -40,6 +41,9 @@ const MAGICDESCR_2: u32 = 0x002a677f;
 // differ from v2 as we do not use bincode encoding for point. We dump pure binary
 // This help use mmap as we can return directly a slice.
 const MAGICDESCR_3: u32 = 0x002a6771;
+// magic at beginning of description format v4 of dump
+// differ from v3 as the list of deleted points is dumped after the entry point in the graph file.
+const MAGICDESCR_4: u32 = 0x002a6772;
 
 // magic at beginning of a layer dump
 const MAGICLAYER: u32 = 0x000a676f;
@@ -66,12 +70,13 @@ pub trait HnswIO {
 ///
 /// Name of distance and type of data must be encoded in the dump file for a coherent reload.
 #[repr(C)]
+#[derive(Clone)]
 pub struct Description {
     /// to keep track of format version
     pub format_version: usize,
     ///  value is 1 for Full 0 for Light
     pub dumpmode: u8,
-    /// max number of connections in layers != 0
+    /// max number of connections in layers != 0. It is 0 for a dump of a FlatIndex (no graph).
     pub max_nb_connection: u8,
     /// number of observed layers
     pub nb_layer: u8,
@@ -95,9 +100,13 @@ impl Description {
     /// . ef (search parameter used in construction) as usize
     /// . nb_point (the number points dumped) as a usize
     /// . the name of distance used. (nb byes as a usize then list of bytes)
-    fn dump<W: Write>(&self, argmode: DumpMode, out: &mut BufWriter<W>) -> Result<i32, String> {
+    pub(crate) fn dump<W: Write>(
+        &self,
+        argmode: DumpMode,
+        out: &mut BufWriter<W>,
+    ) -> Result<i32, String> {
         log::info!("in dump of description");
-        out.write_all(&MAGICDESCR_3.to_ne_bytes()).unwrap();
+        out.write_all(&MAGICDESCR_4.to_ne_bytes()).unwrap();
         let mode: u8 = match argmode {
             DumpMode::Full => 1,
             _ => 0,
@@ -151,6 +160,12 @@ impl Description {
     pub fn get_dimension(&self) -> usize {
         self.dimension
     }
+
+    /// returns true if the dump is the dump of a FlatIndex, whose graph file contains only the
+    /// description.
+    pub fn is_flat(&self) -> bool {
+        self.max_nb_connection == 0
+    }
 } // end of HnswIO impl for Descr
 
 /// This method is a preliminary to do a full reload from a dump.
@@ -175,12 +190,14 @@ pub fn load_description(io_in: &mut dyn Read) -> io::Result<Description> {
     let magic: u32 = u32::from_ne_bytes(it_slice);
     log::debug!(" magic {:X} ", magic);
 
-    if magic != MAGICDESCR_2 && magic != MAGICDESCR_3 {
+    if magic != MAGICDESCR_2 && magic != MAGICDESCR_3 && magic != MAGICDESCR_4 {
         return Err(io::Error::new(io::ErrorKind::Other, "bad magic at descr beginning"));
     } else if magic == MAGICDESCR_2 {
         descr.format_version = 2;
     } else if magic == MAGICDESCR_3 {
         descr.format_version = 3;
+    } else if magic == MAGICDESCR_4 {
+        descr.format_version = 4;
     }
 
     let mut it_slice: [u8; 1] = [0u8; std::mem::size_of::<u8>()];
@@ -441,7 +458,7 @@ fn load_point<T: 'static + DeserializeOwned + Clone + Sized + Send + Sync>(
     let v: Vec<T> = if std::any::TypeId::of::<T>() != std::any::TypeId::of::<NoData>() {
         match descr.format_version {
             2 => bincode::deserialize(&v_serialized).unwrap(),
-            3 => {
+            3 | 4 => {
                 let slice_t: &[T] = unsafe {
                     std::slice::from_raw_parts(v_serialized.as_ptr() as *const T, descr.dimension)
                 };
@@ -473,6 +490,7 @@ fn load_point<T: 'static + DeserializeOwned + Clone + Sized + Send + Sync>(
 // . number of points in layer (usize),
 // . list of point of layer
 // dump entry point
+// . number of deleted points (usize), then their DataId (usize)
 //
 impl<T: Serialize + DeserializeOwned + Clone + Send + Sync> HnswIO for PointIndexation<T> {
     fn dump<W: Write>(
@@ -514,6 +532,13 @@ impl<T: Serialize + DeserializeOwned + Clone + Send + Sync> HnswIO for PointInde
             graphout.write_all(&p_id.1.to_ne_bytes()).unwrap();
         }
 
+        // dump deleted points (format v4)
+        let deleted = self.deleted.read();
+        graphout.write_all(&deleted.len().to_ne_bytes()).unwrap();
+        for d_id in deleted.iter() {
+            graphout.write_all(&d_id.to_ne_bytes()).unwrap();
+        }
+
         Ok(1)
     } // end of dump for PointIndexation<T>
 } // end of impl HnswIO
@@ -550,6 +575,7 @@ fn load_point_indexation<
     }
 
     let mut nb_points_loaded: usize = 0;
+    let mut id_map: HashMap<DataId, PointId> = HashMap::new();
 
     for l in 0..nb_layer as usize {
         // read and check magic
@@ -588,11 +614,16 @@ fn load_point_indexation<
 
             // store neoghbour info of this point
             neighbourhood_map.insert(p_id, load_point_res.1);
+            id_map.insert(point.get_origin_id(), p_id);
             vlayer.push(point);
         }
         points_by_layer.push(vlayer);
         nb_points_loaded += nbpoints;
     }
+    // all layers must be allocated so that new points can be inserted in the reloaded graph
+    while points_by_layer.len() < NB_LAYER_MAX as usize {
+        points_by_layer.push(Vec::new());
+    }
 
     // at this step all points are loaded , but without their neighbours fileds are not yet
     // initialized
@@ -606,6 +637,7 @@ fn load_point_indexation<
                 // now n_point is the Arc<Point> corresponding to neighbour n of point,
                 // construct a corresponding PointWithOrder
                 let n_pwo: PointWithOrder<T> = PointWithOrder::<T>::new(n_point, n.distance);
+                n_point.add_reverse_neighbour(*p_id);
                 point.neighbours.write()[l].push(Arc::new(n_pwo));
             } // end of for n
             // must sort
@@ -635,14 +667,33 @@ fn load_point_indexation<
     let entry_point: Arc<Point<T>> =
         Arc::clone(&points_by_layer[layer as usize][rank_in_l as usize]);
 
+    // load deleted points, dumped since format v4
+    let mut deleted: HashSet<DataId> = HashSet::new();
+    if descr.format_version >= 4 {
+        let mut it_slice: [u8; 8] = [0u8; std::mem::size_of::<usize>()];
+        graph_in.read_exact(&mut it_slice)?;
+        let nb_deleted: usize = usize::from_ne_bytes(it_slice);
+
+        deleted.reserve(nb_deleted);
+        for _ in 0..nb_deleted {
+            let mut it_slice: [u8; 8] = [0u8; std::mem::size_of::<DataId>()];
+            graph_in.read_exact(&mut it_slice)?;
+            deleted.insert(DataId::from_ne_bytes(it_slice));
+        }
+        log::debug!("nb deleted points loaded : {}", nb_deleted);
+    }
+
     let point_indexation: PointIndexation<T> = PointIndexation {
         max_nb_connection: descr.max_nb_connection,
         max_layer: NB_LAYER_MAX,
         points_by_layer: Arc::new(RwLock::new(points_by_layer)),
         layer_g: LayerGenerator::new(descr.max_nb_connection, NB_LAYER_MAX),
-        // CAVEAT, we should increase , the whole thing is to be able to increment graph?
+        // new points get their rank after the loaded ones, so the graph can be incremented.
+        // Use Hnsw::reserve before inserting a large number of points.
         nb_point: Arc::new(RwLock::new(nb_points_loaded)),
         entry_point: Arc::new(RwLock::new(Some(entry_point))),
+        deleted: Arc::new(RwLock::new(deleted)),
+        id_map: Arc::new(RwLock::new(id_map)),
     };
 
     Ok(point_indexation)
@@ -674,7 +725,7 @@ impl<T: Serialize + DeserializeOwned + Clone + Sized + Send + Sync, D: Distance<
         let dimension: usize = self.layer_indexed_points.get_data_dimension();
 
         let description: Description = Description {
-            format_version: 3,
+            format_version: 4,
             dumpmode,
             max_nb_connection: self.get_max_nb_connection(),
             nb_layer: self.get_max_level(),
@@ -708,6 +759,10 @@ pub fn load_hnsw<
     description: &Description,
     data_in: &mut dyn Read,
 ) -> io::Result<Hnsw<T, D>> {
+    if description.is_flat() {
+        return Err(io::Error::new(io::ErrorKind::Other, "dump of a FlatIndex, use load_flat"));
+    }
+
     //  In datafile , we must read MAGICDATAP and dimension and check
     let mut it_slice: [u8; 4] = [0u8; std::mem::size_of::<u32>()];
     data_in.read_exact(&mut it_slice)?;
@@ -749,16 +804,18 @@ pub fn load_hnsw<
         load_point_indexation(graph_in, description, data_in)?;
     let data_dim: usize = layer_point_indexation.get_data_dimension();
 
+    // construction flags are those of Hnsw::new, so that points inserted in the reloaded graph
+    // are linked as in the initial construction
     Ok(Hnsw {
         max_nb_connection: description.max_nb_connection,
         ef_construction: description.ef,
-        extend_candidates: true,
+        extend_candidates: false,
         keep_pruned: false,
         max_layer: description.nb_layer,
         layer_indexed_points: layer_point_indexation,
         data_dimension: data_dim,
         dist_f: D::default(),
-        searching: false,
+        searching: AtomicBool::new(false),
     })
 } // end of load_hnsw
 
@@ -774,6 +831,9 @@ pub fn load_hnsw_with_dist<
     f: D,
     data_in: &mut dyn Read,
 ) -> io::Result<Hnsw<T, D>> {
+    if description.is_flat() {
+        return Err(io::Error::new(io::ErrorKind::Other, "dump of a FlatIndex, use load_flat"));
+    }
     //  In datafile , we must read MAGICDATAP and dimension and check
     let mut it_slice: [u8; 4] = [0u8; std::mem::size_of::<u32>()];
     data_in.read_exact(&mut it_slice)?;
@@ -818,16 +878,17 @@ pub fn load_hnsw_with_dist<
         load_point_indexation(graph_in, description, data_in)?;
     let data_dim: usize = layer_point_indexation.get_data_dimension();
 
+    // as in load_hnsw, construction flags are those of Hnsw::new
     let hnsw: Hnsw<T, D> = Hnsw {
         max_nb_connection: description.max_nb_connection,
         ef_construction: description.ef,
-        extend_candidates: true,
+        extend_candidates: false,
         keep_pruned: false,
         max_layer: description.nb_layer,
         layer_indexed_points: layer_point_indexation,
         data_dimension: data_dim,
         dist_f: f,
-        searching: false,
+        searching: AtomicBool::new(false),
     };
     //
     log::debug!("load_hnsw_with_dist completed");
@@ -1075,6 +1136,162 @@ mod tests {
         check_graph_equality(&hnsw_loaded, &hnsw);
     } // end of test_dump_reload
 
+    #[test]
+    fn test_dump_reload_deleted() {
+        println!("\n\n test_dump_reload_deleted");
+        log_init_test();
+        // generate a random test
+        let mut rng: rand::rngs::ThreadRng = rand::thread_rng();
+        let unif: Uniform<f32> = Uniform::<f32>::new(0., 1.);
+        // 1000 vectors of size 10 f32
+        let nbcolumn: usize = 1000;
+        let nbrow: usize = 10;
+        let data: Vec<Vec<f32>> = (0..nbcolumn)
+            .map(|_| (0..nbrow).map(|_| unif.sample(&mut rng)).collect())
+            .collect();
+        // define hnsw
+        let ef_construct: usize = 25;
+        let nb_connection: u8 = 10;
+        let hnsw: Hnsw<f32, DistL1> =
+            Hnsw::<f32, DistL1>::new(nb_connection, nbcolumn, 16, ef_construct, DistL1 {});
+        for (i, v) in data.iter().enumerate() {
+            hnsw.insert((v, i));
+        }
+        let deleted: Vec<DataId> = (0..nbcolumn).step_by(7).collect();
+        hnsw.delete_batch(&deleted);
+        // dump in a file. Must take care of name as tests runs in // !!!
+        let fname: String = String::from("dumpreloadtestdeleted");
+        let _res: Result<i32, String> = hnsw.file_dump(&fname);
+        //
+        let graphfile: std::fs::File = OpenOptions::new()
+            .read(true)
+            .open("dumpreloadtestdeleted.hnsw.graph")
+            .unwrap();
+        let datafile: std::fs::File = OpenOptions::new()
+            .read(true)
+            .open("dumpreloadtestdeleted.hnsw.data")
+            .unwrap();
+        let mut graph_in: BufReader<std::fs::File> = BufReader::new(graphfile);
+        let mut data_in: BufReader<std::fs::File> = BufReader::new(datafile);
+        // we need to call load_description first to get distance name
+        let hnsw_description: Description = load_description(&mut graph_in).unwrap();
+        assert_eq!(hnsw_description.format_version, 4);
+        let hnsw_loaded: Hnsw<f32, DistL1> =
+            load_hnsw(&mut graph_in, &hnsw_description, &mut data_in).unwrap();
+        // test equality, tombstones included
+        check_graph_equality(&hnsw_loaded, &hnsw);
+        assert_eq!(hnsw_loaded.get_nb_deleted(), deleted.len());
+        assert!(deleted
+            .iter()
+            .all(|d_id: &DataId| hnsw_loaded.is_deleted(d_id)));
+        for d_id in deleted.iter() {
+            let neighbours: Vec<Neighbour> = hnsw_loaded.search(&data[*d_id], 10, 32);
+            assert!(neighbours
+                .iter()
+                .all(|n: &Neighbour| !hnsw_loaded.is_deleted(&n.d_id)));
+        }
+    } // end of test_dump_reload_deleted
+
+    // recall@knbn of hnsw searches against brute force neighbours
+    fn recall_at(
+        hnsw: &Hnsw<f32, DistL1>,
+        data: &[Vec<f32>],
+        queries: &[Vec<f32>],
+        knbn: usize,
+    ) -> f32 {
+        let mut nb_found: usize = 0;
+        for q in queries {
+            let mut exact: Vec<(f32, usize)> = data
+                .iter()
+                .enumerate()
+                .map(|(i, v): (usize, &Vec<f32>)| (DistL1.eval(q, v), i))
+                .collect();
+            exact.sort_unstable_by(|a: &(f32, usize), b: &(f32, usize)| a.partial_cmp(b).unwrap());
+            let exact_ids: Vec<usize> = exact.iter().take(knbn).map(|e| e.1).collect();
+            nb_found += hnsw
+                .search(q, knbn, 64)
+                .iter()
+                .filter(|n: &&Neighbour| exact_ids.contains(&n.d_id))
+                .count();
+        }
+        nb_found as f32 / (knbn * queries.len()) as f32
+    }
+
+    fn reload_l1(fname: &str) -> Hnsw<f32, DistL1> {
+        let graphfile: std::fs::File = OpenOptions::new()
+            .read(true)
+            .open(format!("{}.hnsw.graph", fname))
+            .unwrap();
+        let datafile: std::fs::File = OpenOptions::new()
+            .read(true)
+            .open(format!("{}.hnsw.data", fname))
+            .unwrap();
+        let mut graph_in: BufReader<std::fs::File> = BufReader::new(graphfile);
+        let mut data_in: BufReader<std::fs::File> = BufReader::new(datafile);
+        let hnsw_description: Description = load_description(&mut graph_in).unwrap();
+        load_hnsw(&mut graph_in, &hnsw_description, &mut data_in).unwrap()
+    }
+
+    #[test]
+    fn test_reload_append() {
+        println!("\n\n test_reload_append");
+        log_init_test();
+        //
+        let mut rng: rand::rngs::ThreadRng = rand::thread_rng();
+        let unif: Uniform<f32> = Uniform::<f32>::new(0., 1.);
+        let nbcolumn: usize = 3000;
+        let nbrow: usize = 32;
+        let data: Vec<Vec<f32>> = (0..nbcolumn)
+            .map(|_| (0..nbrow).map(|_| unif.sample(&mut rng)).collect())
+            .collect();
+        let queries: Vec<Vec<f32>> = (0..100)
+            .map(|_| (0..nbrow).map(|_| unif.sample(&mut rng)).collect())
+            .collect();
+        let ef_construct: usize = 50;
+        let nb_connection: u8 = 12;
+        let knbn: usize = 10;
+        let datas: Vec<(&Vec<f32>, usize)> = data.iter().enumerate().map(|(i, v)| (v, i)).collect();
+        // fresh build as reference
+        let hnsw_fresh: Hnsw<f32, DistL1> =
+            Hnsw::<f32, DistL1>::new(nb_connection, nbcolumn, 16, ef_construct, DistL1 {});
+        hnsw_fresh.parallel_insert(&datas);
+        let recall_fresh: f32 = recall_at(&hnsw_fresh, &data, &queries, knbn);
+        // build half, dump, reload and append the other half
+        let half: usize = nbcolumn / 2;
+        let hnsw_half: Hnsw<f32, DistL1> =
+            Hnsw::<f32, DistL1>::new(nb_connection, half, 16, ef_construct, DistL1 {});
+        hnsw_half.parallel_insert(&datas[..half].to_vec());
+        let fname: &str = "dumpreloadappend";
+        hnsw_half.file_dump(fname).unwrap();
+        let hnsw_appended: Hnsw<f32, DistL1> = reload_l1(fname);
+        assert_eq!(hnsw_appended.get_nb_point(), half);
+        hnsw_appended.reserve(nbcolumn - half);
+        hnsw_appended.parallel_insert(&datas[half..].to_vec());
+        assert_eq!(hnsw_appended.get_nb_point(), nbcolumn);
+        assert_eq!(hnsw_appended.get_point_indexation().into_iter().count(), nbcolumn);
+        // dump again and check the appended graph survives a reload
+        hnsw_appended.file_dump(fname).unwrap();
+        let hnsw_reloaded: Hnsw<f32, DistL1> = reload_l1(fname);
+        check_graph_equality(&hnsw_reloaded, &hnsw_appended);
+        let indexation: &PointIndexation<f32> = hnsw_reloaded.get_point_indexation();
+        for point in indexation {
+            for n in point.get_neighborhood_id().iter().flatten() {
+                let n_point: Arc<Point<f32>> = indexation.get_point(&n.p_id).unwrap();
+                assert!(n_point
+                    .reverse_neighbours
+                    .read()
+                    .contains(&point.get_point_id()));
+            }
+        }
+        let recall_appended: f32 = recall_at(&hnsw_reloaded, &data, &queries, knbn);
+        println!(
+            "test_reload_append : recall fresh build {:.3}, reload and append {:.3}",
+            recall_fresh, recall_appended
+        );
+        assert!(recall_appended > 0.85);
+        assert!(recall_appended >= recall_fresh - 0.03);
+    } // end of test_reload_append
+
     #[test]
     fn test_bincode() {
         let mut rng: rand::rngs::ThreadRng = rand::thread_rng();
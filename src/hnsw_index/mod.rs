@@ -2,6 +2,8 @@ pub mod api;
 pub mod datamap;
 pub mod dist;
 pub mod filter;
+pub mod flat;
 pub mod flatten;
 pub mod hnsw;
 pub mod hnswio;
+pub mod rerank;
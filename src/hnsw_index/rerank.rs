@@ -0,0 +1,173 @@
+//! Two stage search : candidates returned by an approximate (typically quantized) index are
+//! rescored with full precision vectors and the exact top k of the candidates is returned.
+//!
+//! The full precision vectors are read from a DataMap, i.e a mmap of the data file of a dump, so
+//! they do not need to be held in memory next to the quantized index.
+
+use std::fmt::Debug;
+
+use rayon::prelude::*;
+
+use crate::hnsw_index::datamap::DataMap;
+use crate::hnsw_index::dist::{DistDot, DistHamming, Distance};
+use crate::hnsw_index::hnsw::{quantize, Hnsw, Neighbour};
+
+/// default number of candidates fetched by result wanted
+pub const RERANK_FACTOR: usize = 4;
+
+/// rescores candidates with the vectors of vectors and the distance dist_f, returns the knbn
+/// best sorted by increasing distance.
+/// Candidates whose id is not in vectors are dropped.
+pub fn rerank<T: Clone + Send + Sync + Debug, D: Distance<T>>(
+    query: &[T],
+    candidates: &[Neighbour],
+    vectors: &DataMap,
+    dist_f: &D,
+    knbn: usize,
+) -> Vec<Neighbour> {
+    let mut rescored: Vec<Neighbour> = candidates
+        .iter()
+        .filter_map(|candidate: &Neighbour| {
+            let v: &[T] = vectors.get_data::<T>(&candidate.d_id)?;
+            Some(Neighbour::new(candidate.d_id, dist_f.eval(query, v), candidate.p_id))
+        })
+        .collect();
+
+    rescored.sort_unstable_by(|a: &Neighbour, b: &Neighbour| a.distance.total_cmp(&b.distance));
+    rescored.truncate(knbn);
+
+    rescored
+}
+
+// end of rerank
+
+/// searches the quantized index with knbn * factor candidates, then reranks them with the f32
+/// vectors using DistDot.
+/// . ef : ef of the quantized search, raised to the number of candidates if lower.
+pub fn search_rerank(
+    index: &Hnsw<i8, DistHamming>,
+    vectors: &DataMap,
+    query: &Vec<f32>,
+    knbn: usize,
+    ef: usize,
+    factor: usize,
+) -> Vec<Neighbour> {
+    let nb_candidate: usize = knbn * factor.max(1);
+    let quantized_query: Vec<i8> = quantize(query);
+
+    let candidates: Vec<Neighbour> =
+        index.search(&quantized_query, nb_candidate, ef.max(nb_candidate));
+
+    rerank(query, &candidates, vectors, &DistDot {}, knbn)
+}
+
+/// parallel version of search_rerank, one result by query.
+pub fn parallel_search_rerank(
+    index: &Hnsw<i8, DistHamming>,
+    vectors: &DataMap,
+    queries: &[Vec<f32>],
+    knbn: usize,
+    ef: usize,
+    factor: usize,
+) -> Vec<Vec<Neighbour>> {
+    queries
+        .par_iter()
+        .map(|query: &Vec<f32>| search_rerank(index, vectors, query, knbn, ef, factor))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashSet;
+    use rand::distributions::Uniform;
+    use rand::prelude::*;
+
+    use super::*;
+    use crate::hnsw_index::api::AnnT;
+    use crate::hnsw_index::flat::FlatIndex;
+    use crate::hnsw_index::hnsw::{DataId, PointId};
+
+    fn normalized(rng: &mut StdRng, dim: usize) -> Vec<f32> {
+        let unif: Uniform<f32> = Uniform::<f32>::new(-1., 1.);
+        let v: Vec<f32> = (0..dim).map(|_| rng.sample(unif)).collect();
+        let norm: f32 = v.iter().map(|x: &f32| x * x).sum::<f32>().sqrt();
+        v.iter().map(|x: &f32| x / norm).collect()
+    }
+
+    fn recall(answer: &[Neighbour], exact: &[Neighbour]) -> f32 {
+        let exact_ids: HashSet<DataId> = exact.iter().map(|n: &Neighbour| n.d_id).collect();
+        answer
+            .iter()
+            .filter(|n: &&Neighbour| exact_ids.contains(&n.d_id))
+            .count() as f32
+            / exact.len() as f32
+    }
+
+    #[test]
+    fn test_rerank_quantized() {
+        let nb_elem: usize = 1000;
+        let dim: usize = 64;
+        let knbn: usize = 10;
+        let mut rng: StdRng = StdRng::seed_from_u64(1789);
+        let data: Vec<Vec<f32>> = (0..nb_elem).map(|_| normalized(&mut rng, dim)).collect();
+
+        // f32 vectors go in a flat dump, exact search on them gives the ground truth
+        let mut flat: FlatIndex<f32, DistDot> = FlatIndex::<f32, DistDot>::new(nb_elem, DistDot {});
+        let quantized: Hnsw<i8, DistHamming> =
+            Hnsw::<i8, DistHamming>::new(16, nb_elem, 16, 100, DistHamming {});
+        for (i, v) in data.iter().enumerate() {
+            flat.insert((v, i));
+            quantized.insert((&quantize(v), i));
+        }
+        flat.file_dump("rerank_test").unwrap();
+        let vectors: DataMap = DataMap::new::<f32>(".", "rerank_test");
+
+        let mut recall_q: f32 = 0.;
+        let mut recall_rerank: f32 = 0.;
+        let nb_query: usize = 50;
+        for _ in 0..nb_query {
+            let query: Vec<f32> = normalized(&mut rng, dim);
+            let exact: Vec<Neighbour> = flat.search(&query, knbn);
+
+            let answer_q: Vec<Neighbour> = quantized.search(&quantize(&query), knbn, 100);
+            let answer: Vec<Neighbour> =
+                search_rerank(&quantized, &vectors, &query, knbn, 100, RERANK_FACTOR);
+
+            assert_eq!(answer.len(), knbn);
+            for pair in answer.windows(2) {
+                assert!(pair[0].distance <= pair[1].distance);
+            }
+            for n in answer.iter() {
+                let expected: f32 = DistDot {}.eval(&query, &data[n.d_id]);
+                assert!((n.distance - expected).abs() < 1e-5);
+            }
+
+            recall_q += recall(&answer_q, &exact);
+            recall_rerank += recall(&answer, &exact);
+        }
+        println!(
+            "recall quantized {} reranked {}",
+            recall_q / nb_query as f32,
+            recall_rerank / nb_query as f32
+        );
+        assert!(recall_rerank >= recall_q);
+
+        // with all points as candidates rerank is exact
+        let query: Vec<f32> = normalized(&mut rng, dim);
+        let all: Vec<Neighbour> = (0..nb_elem)
+            .map(|i| Neighbour::new(i, 0., PointId(0, 0)))
+            .collect();
+        let answer: Vec<Neighbour> = rerank(&query, &all, &vectors, &DistDot {}, knbn);
+        let exact: Vec<Neighbour> = flat.search(&query, knbn);
+        assert_eq!(
+            answer
+                .iter()
+                .map(|n: &Neighbour| n.d_id)
+                .collect::<Vec<DataId>>(),
+            exact
+                .iter()
+                .map(|n: &Neighbour| n.d_id)
+                .collect::<Vec<DataId>>()
+        );
+    } // end of test_rerank_quantized
+} // end of mod tests
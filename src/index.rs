@@ -0,0 +1,699 @@
+//! Index served by the search, selected at runtime from the Description of the dump on disk.
+//!
+//! Queries are always f32 embeddings. An index over i8 vectors quantizes queries itself and
+//! can rerank its candidates with the f32 vectors of another dump, so callers never depend on the
+//! type of data or the distance stored.
+
+use std::any::type_name;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::hnsw_index::api::AnnT;
+use crate::hnsw_index::datamap::DataMap;
+use crate::hnsw_index::dist::{DistCosine, DistDot, DistHamming, DistL1, DistL2, Distance};
+use crate::hnsw_index::filter::FilterT;
+use crate::hnsw_index::flat::{load_flat, FlatIndex};
+use crate::hnsw_index::hnsw::{quantize, Hnsw, Neighbour};
+use crate::hnsw_index::hnswio::{load_description, load_hnsw, Description};
+use crate::hnsw_index::rerank::{rerank, RERANK_FACTOR};
+
+/// An object safe search interface over any index, queries are f32 embeddings.
+pub trait SearchIndex: Send + Sync {
+    /// short description of the index, as "hnsw f32 DistDot"
+    fn name(&self) -> String;
+
+    /// number of vectors indexed
+    fn get_nb_point(&self) -> usize;
+
+    /// dimension of vectors indexed, which queries must have
+    fn get_dimension(&self) -> usize;
+
+    /// description of the dump the index was loaded from, None if it was not loaded from a dump
+    fn get_description(&self) -> Option<&Description> {
+        None
+    }
+
+    /// returns the knbn nearest neighbours of query accepted by filter, sorted by increasing
+    /// distance
+    #[allow(clippy::ptr_arg)]
+    fn search_filter(
+        &self,
+        query: &Vec<f32>,
+        knbn: usize,
+        ef: usize,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour>;
+
+    /// returns the knbn nearest neighbours of query sorted by increasing distance
+    #[allow(clippy::ptr_arg)]
+    fn search(&self, query: &Vec<f32>, knbn: usize, ef: usize) -> Vec<Neighbour> {
+        self.search_filter(query, knbn, ef, None)
+    }
+
+    /// Exact search of the knbn nearest neighbours accepted by filter, computing the distance to
+    /// each point it accepts. The default searches the graph with an ef of the size of the index.
+    #[allow(clippy::ptr_arg)]
+    fn scan_filter(&self, query: &Vec<f32>, knbn: usize, filter: &dyn FilterT) -> Vec<Neighbour> {
+        self.search_filter(query, knbn, self.get_nb_point().max(knbn), Some(filter))
+    }
+
+    /// true if vectors are stored quantized to i8, then search_quantized is available
+    fn is_quantized(&self) -> bool {
+        false
+    }
+
+    /// search of a query already quantized by [quantize]. Returns None if the index does not
+    /// store i8 vectors. There is no rerank as the f32 query is not known.
+    #[allow(clippy::ptr_arg)]
+    fn search_quantized(
+        &self,
+        _query: &Vec<i8>,
+        _knbn: usize,
+        _ef: usize,
+    ) -> Option<Vec<Neighbour>> {
+        None
+    }
+
+    /// search of a batch of queries, one result by query
+    fn parallel_search(&self, queries: &[Vec<f32>], knbn: usize, ef: usize) -> Vec<Vec<Neighbour>> {
+        queries
+            .par_iter()
+            .map(|query: &Vec<f32>| self.search(query, knbn, ef))
+            .collect()
+    }
+}
+
+/// short name of a distance type : semantic_search::hnsw_index::dist::DistDot gives DistDot
+fn short_name(name: &str) -> &str {
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// describes an index from the description of its dump
+fn index_name(description: &Description) -> String {
+    format!(
+        "{} {} {}",
+        if description.is_flat() { "flat" } else { "hnsw" },
+        description.t_name,
+        short_name(&description.distname)
+    )
+}
+
+/// an index over f32 vectors, queries are searched as they are
+pub struct FloatIndex<I> {
+    index: I,
+    name: String,
+    description: Description,
+}
+
+impl<I: AnnT<Val = f32> + Send + Sync> FloatIndex<I> {
+    pub fn new(index: I, description: &Description) -> Self {
+        FloatIndex { index, name: index_name(description), description: description.clone() }
+    }
+}
+
+impl<I: AnnT<Val = f32> + Send + Sync> SearchIndex for FloatIndex<I> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_nb_point(&self) -> usize {
+        self.description.nb_point
+    }
+
+    fn get_dimension(&self) -> usize {
+        self.description.dimension
+    }
+
+    fn get_description(&self) -> Option<&Description> {
+        Some(&self.description)
+    }
+
+    fn search_filter(
+        &self,
+        query: &Vec<f32>,
+        knbn: usize,
+        ef: usize,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour> {
+        self.index.search_neighbours_filter(query, knbn, ef, filter)
+    }
+
+    fn scan_filter(&self, query: &Vec<f32>, knbn: usize, filter: &dyn FilterT) -> Vec<Neighbour> {
+        self.index.scan_neighbours_filter(query, knbn, filter)
+    }
+} // end of impl SearchIndex for FloatIndex
+
+/// an index over i8 vectors quantized by [quantize]. Queries are quantized before the search.
+/// If the f32 vectors are given, knbn * factor candidates are searched and reranked with DistDot
+/// on the f32 vectors.
+pub struct QuantizedIndex<I> {
+    index: I,
+    name: String,
+    description: Description,
+    vectors: Option<DataMap>,
+    factor: usize,
+}
+
+impl<I: AnnT<Val = i8> + Send + Sync> QuantizedIndex<I> {
+    pub fn new(index: I, description: &Description) -> Self {
+        QuantizedIndex {
+            index,
+            name: index_name(description),
+            description: description.clone(),
+            vectors: None,
+            factor: RERANK_FACTOR,
+        }
+    }
+
+    /// rerank candidates with the f32 vectors, knbn * factor candidates are fetched
+    pub fn with_rerank(mut self, vectors: DataMap, factor: usize) -> Self {
+        self.name.push_str(" + rerank f32");
+        self.vectors = Some(vectors);
+        self.factor = factor.max(1);
+        self
+    }
+}
+
+impl<I: AnnT<Val = i8> + Send + Sync> SearchIndex for QuantizedIndex<I> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn get_nb_point(&self) -> usize {
+        self.description.nb_point
+    }
+
+    fn get_dimension(&self) -> usize {
+        self.description.dimension
+    }
+
+    fn get_description(&self) -> Option<&Description> {
+        Some(&self.description)
+    }
+
+    fn search_filter(
+        &self,
+        query: &Vec<f32>,
+        knbn: usize,
+        ef: usize,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour> {
+        let quantized_query: Vec<i8> = quantize(query);
+
+        match self.vectors.as_ref() {
+            Some(vectors) => {
+                let nb_candidate: usize = knbn * self.factor;
+                let candidates: Vec<Neighbour> = self.index.search_neighbours_filter(
+                    &quantized_query,
+                    nb_candidate,
+                    ef.max(nb_candidate),
+                    filter,
+                );
+                rerank(query, &candidates, vectors, &DistDot {}, knbn)
+            },
+            None => self
+                .index
+                .search_neighbours_filter(&quantized_query, knbn, ef, filter),
+        }
+    }
+
+    /// the i8 vectors are scanned, then reranked as by search_filter
+    fn scan_filter(&self, query: &Vec<f32>, knbn: usize, filter: &dyn FilterT) -> Vec<Neighbour> {
+        let quantized_query: Vec<i8> = quantize(query);
+
+        match self.vectors.as_ref() {
+            Some(vectors) => {
+                let candidates: Vec<Neighbour> =
+                    self.index
+                        .scan_neighbours_filter(&quantized_query, knbn * self.factor, filter);
+                rerank(query, &candidates, vectors, &DistDot {}, knbn)
+            },
+            None => self
+                .index
+                .scan_neighbours_filter(&quantized_query, knbn, filter),
+        }
+    }
+
+    fn is_quantized(&self) -> bool {
+        true
+    }
+
+    fn search_quantized(&self, query: &Vec<i8>, knbn: usize, ef: usize) -> Option<Vec<Neighbour>> {
+        Some(self.index.search_neighbours(query, knbn, ef))
+    }
+} // end of impl SearchIndex for QuantizedIndex
+
+/// how search_adaptive searches with a filter, see Config::search_limits
+/// . scan_selectivity : filters estimated to accept less than this fraction of the points are
+///   searched by exact scan of the points they accept
+/// . max_ef : otherwise ef is doubled, up to max_ef, until knbn neighbours are accepted
+#[derive(Debug, Clone)]
+pub struct FilterPolicy {
+    pub scan_selectivity: f32,
+    pub max_ef: usize,
+}
+
+impl Default for FilterPolicy {
+    fn default() -> Self {
+        FilterPolicy { scan_selectivity: 0.01, max_ef: 4096 }
+    }
+}
+
+/// how the neighbours of a query were searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchStrategy {
+    /// in the graph with the ef asked, as no filter rejected too many points
+    Graph(usize),
+    /// in the graph with a wider ef, the last one tried, as the filter rejected too many points
+    Widened(usize),
+    /// by exact distances to the points accepted by the filter
+    Scan,
+}
+
+/// the neighbours of a query and how they were searched
+#[derive(Debug, Clone)]
+pub struct FilteredSearch {
+    pub neighbours: Vec<Neighbour>,
+    pub strategy: SearchStrategy,
+}
+
+/// Searches the knbn nearest neighbours of query accepted by filter. The search in the graph
+/// visits about ef / selectivity points to find ef accepted ones and returns fewer than knbn
+/// neighbours when the filter rejects most of them, so :
+/// . a filter accepting less than policy.scan_selectivity of the points (see FilterT::selectivity)
+///   is searched by exact scan of the points it accepts
+/// . otherwise ef is doubled until knbn neighbours are found or ef reaches policy.max_ef, the
+///   neighbours found are returned then.
+/// A flat index is always scanned.
+#[allow(clippy::ptr_arg)]
+pub fn search_adaptive(
+    index: &dyn SearchIndex,
+    query: &Vec<f32>,
+    knbn: usize,
+    ef: usize,
+    filter: Option<&dyn FilterT>,
+    policy: &FilterPolicy,
+) -> FilteredSearch {
+    let filter: &dyn FilterT = match filter {
+        Some(filter) => filter,
+        None => {
+            return FilteredSearch {
+                neighbours: index.search(query, knbn, ef),
+                strategy: SearchStrategy::Graph(ef.max(knbn)),
+            }
+        },
+    };
+
+    let nb_point: usize = index.get_nb_point();
+    let is_flat: bool = index
+        .get_description()
+        .is_some_and(|description: &Description| description.is_flat());
+    if is_flat || filter.selectivity(nb_point) < policy.scan_selectivity {
+        return FilteredSearch {
+            neighbours: index.scan_filter(query, knbn, filter),
+            strategy: SearchStrategy::Scan,
+        };
+    }
+
+    let first_ef: usize = ef.max(knbn);
+    let mut ef: usize = first_ef;
+    let mut neighbours: Vec<Neighbour> = index.search_filter(query, knbn, ef, Some(filter));
+    while neighbours.len() < knbn && ef < policy.max_ef && ef < nb_point {
+        ef = (ef * 2).min(policy.max_ef);
+        neighbours = index.search_filter(query, knbn, ef, Some(filter));
+    }
+
+    FilteredSearch {
+        neighbours,
+        strategy: if ef == first_ef {
+            SearchStrategy::Graph(ef)
+        } else {
+            SearchStrategy::Widened(ef)
+        },
+    }
+} // end of search_adaptive
+
+/// which dump to serve, see Config::index_config
+/// . dataset : basename of the dump to serve
+/// . rerank_dataset : basename of the f32 dump used to rerank an i8 index
+/// . rerank_factor : candidates fetched by result wanted when reranking
+#[derive(Debug, Clone)]
+pub struct IndexConfig {
+    pub dataset: String,
+    pub rerank_dataset: Option<String>,
+    pub rerank_factor: usize,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        IndexConfig {
+            dataset: String::from("news_q"),
+            rerank_dataset: Some(String::from("news")),
+            rerank_factor: RERANK_FACTOR,
+        }
+    }
+}
+
+fn open_dump_file(dataset: &str, suffix: &str) -> io::Result<BufReader<File>> {
+    let path: PathBuf = PathBuf::from(format!("{}.hnsw.{}", dataset, suffix));
+    let file: File = OpenOptions::new()
+        .read(true)
+        .open(&path)
+        .map_err(|e: io::Error| {
+            io::Error::new(e.kind(), format!("cannot open {}: {}", path.display(), e))
+        })?;
+
+    Ok(BufReader::new(file))
+}
+
+fn is_dist<D>(description: &Description) -> bool {
+    description.distname == type_name::<D>()
+}
+
+fn load_float<D>(
+    description: &Description,
+    graph: &mut BufReader<File>,
+    data: &mut BufReader<File>,
+) -> io::Result<Box<dyn SearchIndex>>
+where
+    D: 'static + Distance<f32> + Default + Send + Sync,
+{
+    if description.is_flat() {
+        let flat: FlatIndex<f32, D> = load_flat::<f32, D>(description, data)?;
+        Ok(Box::new(FloatIndex::new(flat, description)))
+    } else {
+        let hnsw: Hnsw<f32, D> = load_hnsw::<f32, D>(graph, description, data)?;
+        hnsw.set_searching_mode(true);
+        Ok(Box::new(FloatIndex::new(hnsw, description)))
+    }
+}
+
+fn load_quantized<D>(
+    description: &Description,
+    graph: &mut BufReader<File>,
+    data: &mut BufReader<File>,
+    rerank: Option<(DataMap, usize)>,
+) -> io::Result<Box<dyn SearchIndex>>
+where
+    D: 'static + Distance<i8> + Default + Send + Sync,
+{
+    fn boxed<I: 'static + AnnT<Val = i8> + Send + Sync>(
+        index: QuantizedIndex<I>,
+        rerank: Option<(DataMap, usize)>,
+    ) -> Box<dyn SearchIndex> {
+        match rerank {
+            Some((vectors, factor)) => Box::new(index.with_rerank(vectors, factor)),
+            None => Box::new(index),
+        }
+    }
+
+    if description.is_flat() {
+        let flat: FlatIndex<i8, D> = load_flat::<i8, D>(description, data)?;
+        Ok(boxed(QuantizedIndex::new(flat, description), rerank))
+    } else {
+        let hnsw: Hnsw<i8, D> = load_hnsw::<i8, D>(graph, description, data)?;
+        hnsw.set_searching_mode(true);
+        Ok(boxed(QuantizedIndex::new(hnsw, description), rerank))
+    }
+}
+
+fn unsupported(description: &Description) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        format!(
+            "unsupported index : data type {} distance {}",
+            description.t_name, description.distname
+        ),
+    )
+}
+
+/// reads the Description at the beginning of dataset.hnsw.graph, without loading the index
+pub fn load_dump_description(dataset: &str) -> io::Result<Description> {
+    load_description(&mut open_dump_file(dataset, "graph")?)
+}
+
+/// Checks that the dump described by new can replace the index described by served : requests
+/// valid for one must stay valid for the other, so both must agree on the type of data, the
+/// distance and the dimension. Flat and hnsw dumps can replace each other.
+pub fn check_compatible(served: &Description, new: &Description) -> Result<(), String> {
+    if new.t_name != served.t_name {
+        return Err(format!(
+            "data type {} differs from the one served {}",
+            new.t_name, served.t_name
+        ));
+    }
+    if new.distname != served.distname {
+        return Err(format!(
+            "distance {} differs from the one served {}",
+            short_name(&new.distname),
+            short_name(&served.distname)
+        ));
+    }
+    if new.dimension != served.dimension {
+        return Err(format!(
+            "dimension {} differs from the one served {}",
+            new.dimension, served.dimension
+        ));
+    }
+    Ok(())
+}
+
+/// loads the dump dataset.hnsw.graph / dataset.hnsw.data whatever its type of data and distance,
+/// as described by the Description at the beginning of the graph file.
+/// For an i8 dump, rerank_dataset names the dump of the f32 vectors used to rerank.
+pub fn load_search_index(
+    dataset: &str,
+    rerank_dataset: Option<&str>,
+    rerank_factor: usize,
+) -> io::Result<Box<dyn SearchIndex>> {
+    let mut graph: BufReader<File> = open_dump_file(dataset, "graph")?;
+    let mut data: BufReader<File> = open_dump_file(dataset, "data")?;
+
+    let description: Description = load_description(&mut graph)?;
+    log::info!("loading {} : {}", dataset, index_name(&description));
+
+    let index: Box<dyn SearchIndex> = match description.t_name.as_str() {
+        "f32" => {
+            if is_dist::<DistDot>(&description) {
+                load_float::<DistDot>(&description, &mut graph, &mut data)?
+            } else if is_dist::<DistL2>(&description) {
+                load_float::<DistL2>(&description, &mut graph, &mut data)?
+            } else if is_dist::<DistL1>(&description) {
+                load_float::<DistL1>(&description, &mut graph, &mut data)?
+            } else if is_dist::<DistCosine>(&description) {
+                load_float::<DistCosine>(&description, &mut graph, &mut data)?
+            } else {
+                return Err(unsupported(&description));
+            }
+        },
+        "i8" => {
+            let rerank: Option<(DataMap, usize)> = match rerank_dataset {
+                Some(rerank_dataset) => {
+                    let vectors: DataMap = DataMap::from_hnswdump::<f32>(".", rerank_dataset)
+                        .map_err(|e: String| {
+                            io::Error::new(
+                                io::ErrorKind::Other,
+                                format!("cannot map {} for rerank : {}", rerank_dataset, e),
+                            )
+                        })?;
+                    if vectors.get_dimension() != description.dimension {
+                        return Err(io::Error::new(
+                            io::ErrorKind::Other,
+                            "rerank vectors and index do not agree on dimension",
+                        ));
+                    }
+                    Some((vectors, rerank_factor))
+                },
+                None => None,
+            };
+
+            if is_dist::<DistHamming>(&description) {
+                load_quantized::<DistHamming>(&description, &mut graph, &mut data, rerank)?
+            } else if is_dist::<DistDot>(&description) {
+                load_quantized::<DistDot>(&description, &mut graph, &mut data, rerank)?
+            } else if is_dist::<DistL2>(&description) {
+                load_quantized::<DistL2>(&description, &mut graph, &mut data, rerank)?
+            } else if is_dist::<DistL1>(&description) {
+                load_quantized::<DistL1>(&description, &mut graph, &mut data, rerank)?
+            } else {
+                return Err(unsupported(&description));
+            }
+        },
+        _ => return Err(unsupported(&description)),
+    };
+
+    log::info!("{} loaded : {} points", index.name(), index.get_nb_point());
+
+    Ok(index)
+} // end of load_search_index
+
+/// loads the index described by config
+pub fn load_configured_index(config: &IndexConfig) -> io::Result<Box<dyn SearchIndex>> {
+    // the default rerank dump may not have been built, the index is then served alone
+    let rerank_dataset: Option<&str> = config.rerank_dataset.as_deref().filter(|name: &&str| {
+        let exists: bool = Path::new(&format!("{}.hnsw.data", name)).exists();
+        if !exists {
+            log::warn!("no dump {} to rerank with, rerank disabled", name);
+        }
+        exists
+    });
+
+    load_search_index(&config.dataset, rerank_dataset, config.rerank_factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+    use rand::prelude::*;
+
+    use super::*;
+    use crate::hnsw_index::hnsw::DataId;
+
+    fn normalized(rng: &mut StdRng, dim: usize) -> Vec<f32> {
+        let unif: Uniform<f32> = Uniform::<f32>::new(-1., 1.);
+        let v: Vec<f32> = (0..dim).map(|_| rng.sample(unif)).collect();
+        let norm: f32 = v.iter().map(|x: &f32| x * x).sum::<f32>().sqrt();
+        v.iter().map(|x: &f32| x / norm).collect()
+    }
+
+    #[test]
+    fn test_load_search_index_dispatch() {
+        let nb_elem: usize = 500;
+        let dim: usize = 64;
+        let mut rng: StdRng = StdRng::seed_from_u64(1010);
+        let data: Vec<Vec<f32>> = (0..nb_elem).map(|_| normalized(&mut rng, dim)).collect();
+
+        let hnsw: Hnsw<f32, DistDot> = Hnsw::<f32, DistDot>::new(16, nb_elem, 16, 100, DistDot {});
+        let mut flat: FlatIndex<f32, DistL2> = FlatIndex::<f32, DistL2>::new(nb_elem, DistL2 {});
+        let quantized: Hnsw<i8, DistHamming> =
+            Hnsw::<i8, DistHamming>::new(16, nb_elem, 16, 100, DistHamming {});
+        for (i, v) in data.iter().enumerate() {
+            hnsw.insert((v, i));
+            flat.insert((v, i));
+            quantized.insert((&quantize(v), i));
+        }
+        hnsw.file_dump("index_test_f32").unwrap();
+        flat.file_dump("index_test_flat").unwrap();
+        quantized.file_dump("index_test_q").unwrap();
+
+        let query: Vec<f32> = normalized(&mut rng, dim);
+
+        let index: Box<dyn SearchIndex> = load_search_index("index_test_f32", None, 1).unwrap();
+        assert_eq!(index.name(), "hnsw f32 DistDot");
+        assert_eq!(index.get_nb_point(), nb_elem);
+        assert_eq!(index.get_dimension(), dim);
+        assert_eq!(index.search(&query, 10, 30).len(), 10);
+
+        let index: Box<dyn SearchIndex> = load_search_index("index_test_flat", None, 1).unwrap();
+        assert_eq!(index.name(), "flat f32 DistL2");
+        let expected: Vec<Neighbour> = flat.search(&query, 10);
+        let answer: Vec<Neighbour> = index.search(&query, 10, 0);
+        assert_eq!(
+            answer
+                .iter()
+                .map(|n: &Neighbour| n.d_id)
+                .collect::<Vec<usize>>(),
+            expected
+                .iter()
+                .map(|n: &Neighbour| n.d_id)
+                .collect::<Vec<usize>>()
+        );
+
+        // the i8 index quantizes the f32 query
+        let index: Box<dyn SearchIndex> = load_search_index("index_test_q", None, 1).unwrap();
+        assert_eq!(index.name(), "hnsw i8 DistHamming");
+        assert_eq!(index.search(&query, 10, 30).len(), 10);
+
+        // then reranks with the f32 dump
+        let index: Box<dyn SearchIndex> =
+            load_search_index("index_test_q", Some("index_test_f32"), 4).unwrap();
+        assert_eq!(index.name(), "hnsw i8 DistHamming + rerank f32");
+        let answers: Vec<Vec<Neighbour>> =
+            index.parallel_search(std::slice::from_ref(&query), 10, 30);
+        assert_eq!(answers.len(), 1);
+        for n in answers[0].iter() {
+            assert!((n.distance - DistDot {}.eval(&query, &data[n.d_id])).abs() < 1e-5);
+        }
+
+        assert!(load_search_index("index_test_missing", None, 1).is_err());
+
+        // the i8 dump cannot replace the f32 one, the flat one uses another distance
+        let served: Description = load_dump_description("index_test_f32").unwrap();
+        assert_eq!(served.nb_point, nb_elem);
+        assert!(check_compatible(&served, &served).is_ok());
+        for other in ["index_test_q", "index_test_flat"] {
+            let new: Description = load_dump_description(other).unwrap();
+            assert!(check_compatible(&served, &new).is_err());
+        }
+    } // end of test_load_search_index_dispatch
+
+    #[test]
+    fn test_search_adaptive() {
+        let nb_elem: usize = 2000;
+        let dim: usize = 16;
+        let mut rng: StdRng = StdRng::seed_from_u64(2022);
+        let data: Vec<Vec<f32>> = (0..nb_elem).map(|_| normalized(&mut rng, dim)).collect();
+
+        let hnsw: Hnsw<f32, DistL2> = Hnsw::<f32, DistL2>::new(16, nb_elem, 16, 100, DistL2 {});
+        let mut flat: FlatIndex<f32, DistL2> = FlatIndex::<f32, DistL2>::new(nb_elem, DistL2 {});
+        for (i, v) in data.iter().enumerate() {
+            hnsw.insert((v, i));
+            flat.insert((v, i));
+        }
+        hnsw.file_dump("index_test_adaptive").unwrap();
+        flat.file_dump("index_test_adaptive_flat").unwrap();
+        let index: Box<dyn SearchIndex> =
+            load_search_index("index_test_adaptive", None, 1).unwrap();
+        let flat_index: Box<dyn SearchIndex> =
+            load_search_index("index_test_adaptive_flat", None, 1).unwrap();
+
+        let query: Vec<f32> = normalized(&mut rng, dim);
+        let policy: FilterPolicy = FilterPolicy::default();
+        let ids = |searched: &FilteredSearch| -> Vec<DataId> {
+            searched
+                .neighbours
+                .iter()
+                .map(|n: &Neighbour| n.d_id)
+                .collect()
+        };
+
+        let searched: FilteredSearch =
+            search_adaptive(index.as_ref(), &query, 10, 30, None, &policy);
+        assert_eq!((searched.neighbours.len(), searched.strategy), (10, SearchStrategy::Graph(30)));
+
+        // a few ids listed : exact distances to them only
+        let allowed: Vec<usize> = vec![3, 50, 77, 400, 1024, 1999];
+        let expected: Vec<DataId> = flat
+            .search_filter(&query, 4, Some(&allowed))
+            .iter()
+            .map(|n: &Neighbour| n.d_id)
+            .collect();
+        for index in [index.as_ref(), flat_index.as_ref()] {
+            let searched: FilteredSearch =
+                search_adaptive(index, &query, 4, 30, Some(&allowed), &policy);
+            assert_eq!(searched.strategy, SearchStrategy::Scan);
+            assert_eq!(ids(&searched), expected);
+        }
+
+        // one point in 40 : the search in the graph widens ef until 10 are found
+        let one_in_40 = |id: &DataId| -> bool { id.is_multiple_of(40) };
+        let searched: FilteredSearch =
+            search_adaptive(index.as_ref(), &query, 10, 10, Some(&one_in_40), &policy);
+        assert_ne!(searched.strategy, SearchStrategy::Scan);
+        assert_eq!(searched.neighbours.len(), 10);
+        assert!(ids(&searched)
+            .iter()
+            .all(|id: &DataId| id.is_multiple_of(40)));
+
+        // fewer points accepted than asked : ef is widened up to the budget
+        let policy: FilterPolicy = FilterPolicy { scan_selectivity: 0., max_ef: 200 };
+        let searched: FilteredSearch =
+            search_adaptive(index.as_ref(), &query, 10, 10, Some(&allowed), &policy);
+        assert_eq!(searched.strategy, SearchStrategy::Widened(200));
+        assert!(searched.neighbours.len() <= allowed.len());
+    } // end of test_search_adaptive
+} // end of mod tests
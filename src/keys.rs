@@ -0,0 +1,274 @@
+//! External keys of the documents : the key of each DataId, given by the client (a string or an
+//! u64), and the DataId of each key. The map is built by the embedding binary, which inserts each
+//! document under the DataId given to its key, from a column of the csv file. It is dumped next to
+//! the index, in dataset.hnsw.keys, and the server returns the keys of the hits with their ids.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::str::FromStr;
+use std::{fmt, io};
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::hnsw_index::hnsw::DataId;
+
+/// version of the format of the .hnsw.keys dump
+const KEYS_VERSION: u32 = 1;
+
+/// type of the keys of a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    Str,
+    U64,
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyType::Str => write!(f, "str"),
+            KeyType::U64 => write!(f, "u64"),
+        }
+    }
+}
+
+impl FromStr for KeyType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "str" => Ok(KeyType::Str),
+            "u64" => Ok(KeyType::U64),
+            _ => Err(format!("unknown key type {}, expected str or u64", s)),
+        }
+    }
+}
+
+/// the column of the csv file with the keys, written column:type, or column for str keys
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeySpec {
+    pub column: String,
+    pub key_type: KeyType,
+}
+
+impl FromStr for KeySpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (column, key_type) = match s.split_once(':') {
+            Some((column, key_type)) => (column, key_type.trim().parse()?),
+            None => (s, KeyType::Str),
+        };
+        if column.trim().is_empty() {
+            return Err(format!("key {} has no column", s));
+        }
+
+        Ok(KeySpec { column: column.trim().to_string(), key_type })
+    }
+}
+
+/// external key of a document
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Key {
+    Str(String),
+    U64(u64),
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::Str(key) => write!(f, "{}", key),
+            Key::U64(key) => write!(f, "{}", key),
+        }
+    }
+}
+
+/// the keys of the documents of an index, the document of DataId d has the key keys[d]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyMap {
+    version: u32,
+    keys: Vec<Key>,
+    /// rebuilt from keys on load
+    #[serde(skip)]
+    ids: HashMap<Key, DataId>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap::new()
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// file of the keys of the dump dataset
+pub fn keys_file(dataset: &str) -> String {
+    format!("{}.hnsw.keys", dataset)
+}
+
+/// Reads the keys of the column of spec of the csv file path, a document by row. Each row must
+/// have a key, of the type of spec.
+pub fn read_keys(path: &str, spec: &KeySpec) -> io::Result<Vec<Key>> {
+    let mut reader: csv::Reader<File> = csv::Reader::from_path(path)?;
+    let position: usize = reader
+        .headers()?
+        .iter()
+        .position(|header: &str| header == spec.column)
+        .ok_or_else(|| invalid_data(format!("no column {} in {}", spec.column, path)))?;
+
+    reader
+        .records()
+        .enumerate()
+        .map(|(row, record): (usize, csv::Result<csv::StringRecord>)| {
+            let record: csv::StringRecord = record?;
+            let value: &str = record.get(position).unwrap_or("").trim();
+            if value.is_empty() {
+                return Err(invalid_data(format!("row {} : no key in {}", row, spec.column)));
+            }
+            match spec.key_type {
+                KeyType::Str => Ok(Key::Str(value.to_string())),
+                KeyType::U64 => value
+                    .parse::<u64>()
+                    .map(Key::U64)
+                    .map_err(|_| invalid_data(format!("row {} : key {} is not a u64", row, value))),
+            }
+        })
+        .collect()
+}
+
+impl KeyMap {
+    pub fn new() -> Self {
+        KeyMap { version: KEYS_VERSION, keys: Vec::new(), ids: HashMap::new() }
+    }
+
+    /// Maps key to the next DataId and returns it. A key already inserted is an error naming the
+    /// two documents.
+    pub fn insert(&mut self, key: Key) -> io::Result<DataId> {
+        let d_id: DataId = self.keys.len();
+        if let Some(previous) = self.ids.get(&key) {
+            return Err(invalid_data(format!(
+                "duplicate key {} : documents {} and {}",
+                key, previous, d_id
+            )));
+        }
+
+        self.ids.insert(key.clone(), d_id);
+        self.keys.push(key);
+        Ok(d_id)
+    }
+
+    /// number of keys, the DataIds mapped are 0..len
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn get_key(&self, d_id: DataId) -> Option<&Key> {
+        self.keys.get(d_id)
+    }
+
+    pub fn get_id(&self, key: &Key) -> Option<DataId> {
+        self.ids.get(key).copied()
+    }
+
+    /// dumps the keys in dataset.hnsw.keys
+    pub fn dump(&self, dataset: &str) -> io::Result<()> {
+        let path: String = keys_file(dataset);
+        let writer: BufWriter<File> = BufWriter::new(File::create(&path)?);
+        bincode::serialize_into(writer, self)
+            .map_err(|e| invalid_data(format!("cannot write {} : {}", path, e)))
+    }
+
+    /// the keys dumped with dataset, None if the dump has no keys
+    pub fn load(dataset: &str) -> io::Result<Option<Self>> {
+        let path: String = keys_file(dataset);
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+
+        let reader: BufReader<File> = BufReader::new(File::open(&path)?);
+        let dumped: KeyMap = bincode::deserialize_from(reader)
+            .map_err(|e| invalid_data(format!("cannot read {} : {}", path, e)))?;
+        if dumped.version != KEYS_VERSION {
+            return Err(invalid_data(format!(
+                "{} has version {}, expected {}",
+                path, dumped.version, KEYS_VERSION
+            )));
+        }
+
+        let mut keys: KeyMap = KeyMap::new();
+        keys.ids.reserve(dumped.keys.len());
+        for key in dumped.keys {
+            keys.insert(key)
+                .map_err(|e: io::Error| invalid_data(format!("{} : {}", path, e)))?;
+        }
+
+        Ok(Some(keys))
+    }
+} // end of impl KeyMap
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_key_map() {
+        assert_eq!(
+            "id:u64".parse::<KeySpec>().unwrap(),
+            KeySpec { column: String::from("id"), key_type: KeyType::U64 }
+        );
+        assert_eq!("id".parse::<KeySpec>().unwrap().key_type, KeyType::Str);
+        assert!("id:float".parse::<KeySpec>().is_err());
+        assert!(":u64".parse::<KeySpec>().is_err());
+
+        let mut keys: KeyMap = KeyMap::new();
+        assert_eq!(keys.insert(Key::Str(String::from("doc-a"))).unwrap(), 0);
+        assert_eq!(keys.insert(Key::U64(7)).unwrap(), 1);
+        // the same text as another type of key is another key
+        assert_eq!(keys.insert(Key::Str(String::from("7"))).unwrap(), 2);
+        let e: io::Error = keys.insert(Key::U64(7)).unwrap_err();
+        assert_eq!(e.to_string(), "duplicate key 7 : documents 1 and 3");
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys.get_id(&Key::U64(7)), Some(1));
+        assert_eq!(keys.get_id(&Key::U64(8)), None);
+        assert_eq!(keys.get_key(2), Some(&Key::Str(String::from("7"))));
+        assert_eq!(keys.get_key(3), None);
+
+        keys.dump("keys_test").unwrap();
+        let loaded: KeyMap = KeyMap::load("keys_test").unwrap().unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded.get_id(&Key::Str(String::from("doc-a"))), Some(0));
+        assert!(KeyMap::load("keys_test_none").unwrap().is_none());
+        fs::remove_file(keys_file("keys_test")).unwrap();
+    } // end of test_key_map
+
+    #[test]
+    fn test_read_keys() {
+        let path: &str = "keys_test.csv";
+        fs::write(path, "text,id,slug\na,12,x\nb,7,y\nc,12,\n").unwrap();
+
+        let spec: KeySpec = "id:u64".parse().unwrap();
+        let read: Vec<Key> = read_keys(path, &spec).unwrap();
+        assert_eq!(read, vec![Key::U64(12), Key::U64(7), Key::U64(12)]);
+        let mut keys: KeyMap = KeyMap::new();
+        let e: io::Error = read
+            .into_iter()
+            .map(|key: Key| keys.insert(key))
+            .collect::<io::Result<Vec<DataId>>>()
+            .unwrap_err();
+        assert_eq!(e.to_string(), "duplicate key 12 : documents 0 and 2");
+
+        assert!(read_keys(path, &"slug".parse().unwrap()).is_err());
+        assert!(read_keys(path, &"text:u64".parse().unwrap()).is_err());
+        assert!(read_keys(path, &"name".parse().unwrap()).is_err());
+        fs::remove_file(path).unwrap();
+    } // end of test_read_keys
+}
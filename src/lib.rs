@@ -1,5 +1,18 @@
+pub mod batch;
+pub mod collection;
+pub mod config;
+pub mod documents;
+pub mod error;
+pub mod eval;
 pub mod hnsw_index;
+pub mod index;
+pub mod keys;
+pub mod metadata;
+pub mod predicate;
 pub mod search;
+pub mod service;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
 pub mod utils;
 
 pub mod ss {
@@ -1,29 +1,46 @@
 use std::time::Instant;
-use std::{env, process};
 
 // use packed_simd::{i8x64, m8, FromCast, Simd};
 // use rand::distributions::Uniform;
 // use rand::rngs::ThreadRng;
 // use rand::{thread_rng, Rng};
 // use rayon::prelude::*;
+use anyhow::Result;
+use clap::Parser;
 use rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModel;
+use semantic_search::config::{Config, ConfigArgs, IndexType};
+use semantic_search::documents::DocumentStore;
 use semantic_search::hnsw_index::dist::{DistDot, DistHamming};
 use semantic_search::hnsw_index::hnsw::{quantize, Hnsw, Neighbour};
-use semantic_search::utils::{load_data, load_index, load_model, load_quantize_index, log_stats};
+use semantic_search::utils::{load_index, load_model, load_quantize_index, log_stats};
 
 static BENCH_SIZE: usize = 2000;
 static K: usize = 10;
 
+/// searches a query in the index
+#[derive(Parser)]
+struct Args {
+    query: String,
+    /// full or quantize, the index type of the settings by default
+    mode: Option<String>,
+    #[command(flatten)]
+    config: ConfigArgs,
+}
+
 #[allow(dead_code)]
-fn find_documents(query_embedding: &Vec<f32>, do_quantize: bool) {
-    let data: Vec<String> = load_data();
+fn find_documents(config: &Config, query_embedding: &Vec<f32>, do_quantize: bool) {
+    // the documents stored next to the dump by the embedding binary
+    let index_type: IndexType = if do_quantize { IndexType::Quantize } else { IndexType::Full };
+    let documents: DocumentStore = DocumentStore::load(&config.dump_name(index_type))
+        .unwrap()
+        .expect("no document store, rebuild the index with the embedding binary");
 
     let neighbors: Vec<Neighbour> = if !do_quantize {
-        let index: Hnsw<f32, DistDot> = load_index("news");
+        let index: Hnsw<f32, DistDot> = load_index(&config.index).unwrap();
 
         index.search(query_embedding, K, 30)
     } else {
-        let index: Hnsw<i8, DistHamming> = load_quantize_index("news");
+        let index: Hnsw<i8, DistHamming> = load_quantize_index(&config.index).unwrap();
 
         let query_embedding: Vec<i8> = quantize(query_embedding);
 
@@ -32,14 +49,14 @@ fn find_documents(query_embedding: &Vec<f32>, do_quantize: bool) {
 
     for (k, neighbor) in neighbors.iter().enumerate() {
         println!("top {} | id : {}, dist : {}", k + 1, neighbor.d_id, neighbor.distance);
-        println!("{}", data[neighbor.d_id]);
+        println!("{}", documents.get_text(neighbor.d_id).unwrap_or_default());
     }
 }
 
 #[allow(dead_code)]
-fn bench_search(query_embedding: &[f32]) {
-    let index: Hnsw<f32, DistDot> = load_index("news");
-    // let index: Hnsw<i8, DistHamming> = load_quantize_index("news");
+fn bench_search(config: &Config, query_embedding: &[f32]) {
+    let index: Hnsw<f32, DistDot> = load_index(&config.index).unwrap();
+    // let index: Hnsw<i8, DistHamming> = load_quantize_index("news").unwrap();
     // let query_embedding: Vec<i8> = quantize(query_embedding);
 
     for bs in [1024, 2048, 4096, 8192] {
@@ -57,26 +74,26 @@ fn bench_search(query_embedding: &[f32]) {
     }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() < 3 {
-        println!("Usage: main query [full or quantize]");
-        process::exit(1);
-    }
+fn main() -> Result<()> {
+    let args: Args = Args::parse();
+    let config: Config = Config::load(&args.config)?;
+    config.init_thread_pool()?;
 
-    let query: String = args[1].clone();
-    let do_quantize: bool = args[2] == "quantize";
+    let query: String = args.query;
+    let do_quantize: bool = match args.mode.as_deref() {
+        Some(mode) => mode == "quantize",
+        None => config.index_type == IndexType::Quantize,
+    };
 
     println!("query : {:?}", query);
     println!("do quantize : {:?}", do_quantize);
 
-    let model: SentenceEmbeddingsModel = load_model();
+    let model: SentenceEmbeddingsModel = load_model(&config.model_dir);
     let query_embedding: Vec<Vec<f32>> = model.encode(&[query]).unwrap();
     let query_embedding: &[f32] = &query_embedding[0];
 
-    // find_documents(query_embedding, do_quantize);
-    bench_search(query_embedding);
+    // find_documents(&config, query_embedding, do_quantize);
+    bench_search(&config, query_embedding);
 
     // let mut rng: ThreadRng = thread_rng();
     // let unif: Uniform<f32> = Uniform::<f32>::new(-1., 1.);
@@ -123,4 +140,6 @@ fn main() {
     //     hamming_i8_v2(&a, &b);
     // });
     // println!("pv2 {:.3?}", start.elapsed());
+
+    Ok(())
 }
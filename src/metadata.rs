@@ -0,0 +1,554 @@
+//! Metadata of the documents of an index : columns of string tags, integers and timestamps, keyed
+//! by DataId. The store is built by the embedding binary from columns of the csv file and dumped
+//! next to the index, in dataset.hnsw.meta.
+//!
+//! A Predicate (see the predicate module) compiled against a store gives a MetadataFilter, the
+//! FilterT of search_filter.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::{fmt, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::hnsw_index::filter::FilterT;
+use crate::hnsw_index::hnsw::DataId;
+use crate::predicate::{parse_timestamp, Comparison, Predicate, Value};
+
+/// version of the format of the .hnsw.meta dump
+const METADATA_VERSION: u32 = 1;
+
+/// code of a document without tag
+const NO_TAG: u32 = u32::MAX;
+
+/// type of the values of a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldType {
+    Tag,
+    Int,
+    Timestamp,
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldType::Tag => write!(f, "tag"),
+            FieldType::Int => write!(f, "int"),
+            FieldType::Timestamp => write!(f, "timestamp"),
+        }
+    }
+}
+
+impl FromStr for FieldType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "tag" => Ok(FieldType::Tag),
+            "int" => Ok(FieldType::Int),
+            "timestamp" => Ok(FieldType::Timestamp),
+            _ => Err(format!("unknown field type {}, expected tag, int or timestamp", s)),
+        }
+    }
+}
+
+/// a field to read from a column of the csv file, written name:type
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSpec {
+    pub name: String,
+    pub field_type: FieldType,
+}
+
+impl FromStr for FieldSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (name, field_type) = s
+            .split_once(':')
+            .ok_or_else(|| format!("field {} is not name:type", s))?;
+
+        Ok(FieldSpec { name: name.trim().to_string(), field_type: field_type.trim().parse()? })
+    }
+}
+
+/// the values of a field for all the documents, None where a document has no value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Column {
+    /// the distinct tags, sorted, and the code of the tag of each document, NO_TAG if none
+    Tag {
+        tags: Vec<String>,
+        codes: Vec<u32>,
+    },
+    Int(Vec<Option<i64>>),
+    /// seconds since the epoch
+    Timestamp(Vec<Option<i64>>),
+}
+
+impl Column {
+    pub fn tags(values: &[Option<String>]) -> Self {
+        let mut tags: Vec<String> = values.iter().flatten().cloned().collect();
+        tags.sort_unstable();
+        tags.dedup();
+
+        let codes: Vec<u32> = values
+            .iter()
+            .map(|value: &Option<String>| match value {
+                Some(tag) => tags.binary_search(tag).unwrap() as u32,
+                None => NO_TAG,
+            })
+            .collect();
+
+        Column::Tag { tags, codes }
+    }
+
+    pub fn get_type(&self) -> FieldType {
+        match self {
+            Column::Tag { .. } => FieldType::Tag,
+            Column::Int(_) => FieldType::Int,
+            Column::Timestamp(_) => FieldType::Timestamp,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Column::Tag { codes, .. } => codes.len(),
+            Column::Int(values) | Column::Timestamp(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+} // end of impl Column
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Field {
+    name: String,
+    column: Column,
+}
+
+/// the metadata of the nb_point documents of an index, by field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataStore {
+    version: u32,
+    nb_point: usize,
+    fields: Vec<Field>,
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// file of the metadata of the dump dataset
+pub fn metadata_file(dataset: &str) -> String {
+    format!("{}.hnsw.meta", dataset)
+}
+
+impl MetadataStore {
+    /// a store without field for nb_point documents
+    pub fn new(nb_point: usize) -> Self {
+        MetadataStore { version: METADATA_VERSION, nb_point, fields: Vec::new() }
+    }
+
+    /// adds a field, it must have a value (or None) for each document and a new name
+    pub fn add(&mut self, name: &str, column: Column) -> io::Result<()> {
+        if column.len() != self.nb_point {
+            return Err(invalid_data(format!(
+                "field {} has {} values for {} documents",
+                name,
+                column.len(),
+                self.nb_point
+            )));
+        }
+        if self.get_field(name).is_some() {
+            return Err(invalid_data(format!("field {} is defined twice", name)));
+        }
+
+        self.fields.push(Field { name: name.to_string(), column });
+        Ok(())
+    }
+
+    /// Reads the fields of specs from the columns of the csv file path with the same header, a
+    /// document by row. Empty cells have no value, other cells of int and timestamp fields must
+    /// parse (see parse_timestamp).
+    pub fn from_csv(path: &str, specs: &[FieldSpec]) -> io::Result<Self> {
+        let mut reader: csv::Reader<File> = csv::Reader::from_path(path)?;
+        let headers: csv::StringRecord = reader.headers()?.clone();
+        let positions: Vec<usize> = specs
+            .iter()
+            .map(|spec: &FieldSpec| {
+                headers
+                    .iter()
+                    .position(|header: &str| header == spec.name)
+                    .ok_or_else(|| invalid_data(format!("no column {} in {}", spec.name, path)))
+            })
+            .collect::<io::Result<Vec<usize>>>()?;
+
+        let mut values: Vec<Vec<Option<String>>> = vec![Vec::new(); specs.len()];
+        for record in reader.records() {
+            let record: csv::StringRecord = record?;
+            for (column, position) in values.iter_mut().zip(positions.iter()) {
+                let value: &str = record.get(*position).unwrap_or("").trim();
+                column.push(if value.is_empty() { None } else { Some(value.to_string()) });
+            }
+        }
+
+        let nb_point: usize = values.first().map_or(0, Vec::len);
+        let mut store: MetadataStore = MetadataStore::new(nb_point);
+        for (spec, values) in specs.iter().zip(values.iter()) {
+            let parse = |parser: fn(&str) -> Option<i64>| -> io::Result<Vec<Option<i64>>> {
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(row, value): (usize, &Option<String>)| match value {
+                        Some(value) => parser(value).map(Some).ok_or_else(|| {
+                            invalid_data(format!(
+                                "row {} : {} is not a {} of field {}",
+                                row, value, spec.field_type, spec.name
+                            ))
+                        }),
+                        None => Ok(None),
+                    })
+                    .collect()
+            };
+
+            let column: Column = match spec.field_type {
+                FieldType::Tag => Column::tags(values),
+                FieldType::Int => Column::Int(parse(|s: &str| s.parse::<i64>().ok())?),
+                FieldType::Timestamp => Column::Timestamp(parse(parse_timestamp)?),
+            };
+            store.add(&spec.name, column)?;
+        }
+
+        Ok(store)
+    }
+
+    // end of from_csv
+
+    pub fn get_nb_point(&self) -> usize {
+        self.nb_point
+    }
+
+    /// names and types of the fields
+    pub fn get_fields(&self) -> Vec<(&str, FieldType)> {
+        self.fields
+            .iter()
+            .map(|field: &Field| (field.name.as_str(), field.column.get_type()))
+            .collect()
+    }
+
+    pub fn get_field(&self, name: &str) -> Option<&Column> {
+        self.fields
+            .iter()
+            .find(|field: &&Field| field.name == name)
+            .map(|field: &Field| &field.column)
+    }
+
+    /// dumps the store in dataset.hnsw.meta
+    pub fn dump(&self, dataset: &str) -> io::Result<()> {
+        let path: String = metadata_file(dataset);
+        let writer: BufWriter<File> = BufWriter::new(File::create(&path)?);
+        bincode::serialize_into(writer, self)
+            .map_err(|e| invalid_data(format!("cannot write {} : {}", path, e)))
+    }
+
+    /// the store dumped with dataset, None if the dump has no metadata
+    pub fn load(dataset: &str) -> io::Result<Option<Self>> {
+        let path: String = metadata_file(dataset);
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+
+        let reader: BufReader<File> = BufReader::new(File::open(&path)?);
+        let store: MetadataStore = bincode::deserialize_from(reader)
+            .map_err(|e| invalid_data(format!("cannot read {} : {}", path, e)))?;
+        if store.version != METADATA_VERSION {
+            return Err(invalid_data(format!(
+                "{} has version {}, expected {}",
+                path, store.version, METADATA_VERSION
+            )));
+        }
+
+        Ok(Some(store))
+    }
+
+    /// Compiles predicate against the fields of the store. Errors tell about unknown fields and
+    /// values not of the type of their field.
+    pub fn compile(self: &Arc<Self>, predicate: &Predicate) -> Result<MetadataFilter, String> {
+        Ok(MetadataFilter { store: Arc::clone(self), root: self.compile_node(predicate)? })
+    }
+
+    fn compile_node(&self, predicate: &Predicate) -> Result<Node, String> {
+        let compile_all = |predicates: &[Predicate]| -> Result<Vec<Node>, String> {
+            predicates
+                .iter()
+                .map(|predicate: &Predicate| self.compile_node(predicate))
+                .collect()
+        };
+
+        match predicate {
+            Predicate::And(predicates) => Ok(Node::And(compile_all(predicates)?)),
+            Predicate::Or(predicates) => Ok(Node::Or(compile_all(predicates)?)),
+            Predicate::Not(predicate) => Ok(Node::Not(Box::new(self.compile_node(predicate)?))),
+            Predicate::Eq(name, value) => self.compile_in(name, std::slice::from_ref(value)),
+            Predicate::Ne(name, value) => {
+                let (field, _) = self.find(name)?;
+                let equal: Node = self.compile_in(name, std::slice::from_ref(value))?;
+                Ok(Node::And(vec![Node::Present { field }, Node::Not(Box::new(equal))]))
+            },
+            Predicate::In(name, values) => self.compile_in(name, values),
+            Predicate::Cmp(name, comparison, value) => {
+                let (field, column) = self.find(name)?;
+                if let Column::Tag { .. } = column {
+                    return Err(format!("tag field {} cannot be compared, use = or in", name));
+                }
+                let bound: i64 = to_integer(name, column, value)?;
+                let (min, max) = match comparison {
+                    Comparison::Lt => (i64::MIN, bound.saturating_sub(1)),
+                    Comparison::Le => (i64::MIN, bound),
+                    Comparison::Gt => (bound.saturating_add(1), i64::MAX),
+                    Comparison::Ge => (bound, i64::MAX),
+                };
+                Ok(Node::Range { field, min, max })
+            },
+        }
+    }
+
+    fn compile_in(&self, name: &str, values: &[Value]) -> Result<Node, String> {
+        let (field, column) = self.find(name)?;
+
+        match column {
+            Column::Tag { tags, .. } => {
+                let mut codes: Vec<u32> = Vec::with_capacity(values.len());
+                for value in values {
+                    match value {
+                        // a tag no document has matches nothing
+                        Value::Str(tag) => codes
+                            .extend(tags.binary_search(tag).ok().map(|code: usize| code as u32)),
+                        Value::Int(_) => {
+                            return Err(format!("tag field {} is compared to {}", name, value))
+                        },
+                    }
+                }
+                codes.sort_unstable();
+                Ok(Node::Tags { field, codes })
+            },
+            _ => {
+                let mut integers: Vec<i64> = values
+                    .iter()
+                    .map(|value: &Value| to_integer(name, column, value))
+                    .collect::<Result<Vec<i64>, String>>()?;
+                integers.sort_unstable();
+                integers.dedup();
+                Ok(Node::Values { field, values: integers })
+            },
+        }
+    }
+
+    fn find(&self, name: &str) -> Result<(usize, &Column), String> {
+        self.fields
+            .iter()
+            .position(|field: &Field| field.name == name)
+            .map(|position: usize| (position, &self.fields[position].column))
+            .ok_or_else(|| format!("unknown field {}", name))
+    }
+
+    fn integer(&self, field: usize, id: DataId) -> Option<i64> {
+        match &self.fields[field].column {
+            Column::Int(values) | Column::Timestamp(values) => values.get(id).copied().flatten(),
+            Column::Tag { .. } => None,
+        }
+    }
+
+    fn accept(&self, node: &Node, id: DataId) -> bool {
+        match node {
+            Node::Present { field } => match &self.fields[*field].column {
+                Column::Tag { codes, .. } => {
+                    codes.get(id).is_some_and(|code: &u32| *code != NO_TAG)
+                },
+                _ => self.integer(*field, id).is_some(),
+            },
+            Node::Tags { field, codes } => match &self.fields[*field].column {
+                Column::Tag { codes: tags, .. } => tags
+                    .get(id)
+                    .is_some_and(|code: &u32| *code != NO_TAG && codes.binary_search(code).is_ok()),
+                _ => false,
+            },
+            Node::Values { field, values } => self
+                .integer(*field, id)
+                .is_some_and(|value: i64| values.binary_search(&value).is_ok()),
+            Node::Range { field, min, max } => self
+                .integer(*field, id)
+                .is_some_and(|value: i64| *min <= value && value <= *max),
+            Node::And(nodes) => nodes.iter().all(|node: &Node| self.accept(node, id)),
+            Node::Or(nodes) => nodes.iter().any(|node: &Node| self.accept(node, id)),
+            Node::Not(node) => !self.accept(node, id),
+        }
+    }
+} // end of impl MetadataStore
+
+/// an integer or a timestamp value, given as an integer or a date for a timestamp
+fn to_integer(name: &str, column: &Column, value: &Value) -> Result<i64, String> {
+    match (column, value) {
+        (Column::Int(_), Value::Int(i)) | (Column::Timestamp(_), Value::Int(i)) => Ok(*i),
+        (Column::Timestamp(_), Value::Str(s)) => {
+            parse_timestamp(s).ok_or_else(|| format!("{} of field {} is not a date", value, name))
+        },
+        _ => Err(format!("{} field {} is compared to {}", column.get_type(), name, value)),
+    }
+}
+
+/// a predicate compiled against the fields of a store, sorted values are binary searched.
+/// Present matches the documents having a value for the field.
+#[derive(Debug, Clone)]
+enum Node {
+    Present { field: usize },
+    Tags { field: usize, codes: Vec<u32> },
+    Values { field: usize, values: Vec<i64> },
+    Range { field: usize, min: i64, max: i64 },
+    And(Vec<Node>),
+    Or(Vec<Node>),
+    Not(Box<Node>),
+}
+
+/// The filter of a predicate : documents whose metadata match it. A comparison, != included, does
+/// not match a document without value for its field, the negation of a comparison does.
+/// `label != "Sports"` does not match a document without label, `not label = "Sports"` does.
+#[derive(Clone)]
+pub struct MetadataFilter {
+    store: Arc<MetadataStore>,
+    root: Node,
+}
+
+impl fmt::Debug for MetadataFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MetadataFilter({:?})", self.root)
+    }
+}
+
+impl FilterT for MetadataFilter {
+    fn hnsw_filter(&self, id: &DataId) -> bool {
+        self.store.accept(&self.root, *id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::predicate::parse;
+
+    fn store() -> Arc<MetadataStore> {
+        let mut store: MetadataStore = MetadataStore::new(5);
+        let tags: Vec<Option<String>> = ["Sports", "World", "Sports", "Business"]
+            .iter()
+            .map(|tag: &&str| Some(tag.to_string()))
+            .chain([None])
+            .collect();
+        store.add("label", Column::tags(&tags)).unwrap();
+        store
+            .add("year", Column::Int(vec![Some(2003), Some(2004), Some(2005), None, Some(2004)]))
+            .unwrap();
+        store
+            .add(
+                "date",
+                Column::Timestamp(vec![Some(0), Some(86400), None, Some(1092528000), Some(-1)]),
+            )
+            .unwrap();
+
+        assert!(store.add("year", Column::Int(vec![None; 5])).is_err());
+        assert!(store.add("month", Column::Int(vec![None; 4])).is_err());
+        Arc::new(store)
+    }
+
+    fn matching(store: &Arc<MetadataStore>, predicate: &str) -> Vec<DataId> {
+        let filter: MetadataFilter = store.compile(&parse(predicate).unwrap()).unwrap();
+        (0..store.get_nb_point())
+            .filter(|id: &DataId| filter.hnsw_filter(id))
+            .collect()
+    }
+
+    #[test]
+    fn test_metadata_filter() {
+        let store: Arc<MetadataStore> = store();
+
+        assert_eq!(matching(&store, r#"label = "Sports""#), vec![0, 2]);
+        assert_eq!(matching(&store, r#"label in ("World", "Business", "Sci/Tech")"#), vec![1, 3]);
+        assert_eq!(matching(&store, r#"label = "Sci/Tech""#), Vec::<DataId>::new());
+        // != needs a value, not matches the documents without one
+        assert_eq!(matching(&store, r#"label != "Sports""#), vec![1, 3]);
+        assert_eq!(matching(&store, r#"not label = "Sports""#), vec![1, 3, 4]);
+        assert_eq!(matching(&store, "year != 2004"), vec![0, 2]);
+        assert_eq!(matching(&store, "not year = 2004"), vec![0, 2, 3]);
+        assert_eq!(matching(&store, "year >= 2004"), vec![1, 2, 4]);
+        assert_eq!(matching(&store, "year > 2003 and year < 2005"), vec![1, 4]);
+        assert_eq!(matching(&store, "year in (2003, 2005) or not year = 2004"), vec![0, 2, 3]);
+        assert_eq!(matching(&store, r#"date >= "1970-01-02""#), vec![1, 3]);
+        assert_eq!(
+            matching(&store, r#"date < "2004-08-15T00:00:01" and date >= 0"#),
+            vec![0, 1, 3]
+        );
+
+        for invalid in [
+            "month = 1",
+            "label = 1",
+            r#"label < "Sports""#,
+            r#"year = "2004""#,
+            r#"date > "yesterday""#,
+        ] {
+            assert!(store.compile(&parse(invalid).unwrap()).is_err(), "{}", invalid);
+        }
+    } // end of test_metadata_filter
+
+    #[test]
+    fn test_metadata_dump_and_csv() {
+        let dir: std::path::PathBuf = std::env::temp_dir();
+        let dataset: String = dir.join("ss_test_metadata").to_str().unwrap().to_string();
+        let csv: std::path::PathBuf = dir.join("ss_test_metadata.csv");
+        std::fs::write(
+            &csv,
+            "text,label,year,date\na,Sports,2003,2003-01-01\nb,World,,2004-08-15T10:30:00\nc,,\
+             2005,\n",
+        )
+        .unwrap();
+
+        let specs: Vec<FieldSpec> = ["label:tag", "year:int", "date:timestamp"]
+            .iter()
+            .map(|spec: &&str| spec.parse().unwrap())
+            .collect();
+        let store: MetadataStore = MetadataStore::from_csv(csv.to_str().unwrap(), &specs).unwrap();
+        assert_eq!(store.get_nb_point(), 3);
+        store.dump(&dataset).unwrap();
+
+        let store: Arc<MetadataStore> = Arc::new(MetadataStore::load(&dataset).unwrap().unwrap());
+        assert_eq!(
+            store.get_fields(),
+            vec![
+                ("label", FieldType::Tag),
+                ("year", FieldType::Int),
+                ("date", FieldType::Timestamp)
+            ]
+        );
+        assert_eq!(matching(&store, r#"label = "World" or year = 2005"#), vec![1, 2]);
+        assert_eq!(matching(&store, r#"date > "2004-08-15""#), vec![1]);
+        assert!(MetadataStore::load("ss_test_metadata_missing")
+            .unwrap()
+            .is_none());
+
+        // a column missing or a value not parsing
+        for spec in ["source:tag", "text:int", "label:float", "label"] {
+            let specs: Result<Vec<FieldSpec>, String> = vec![spec.parse()].into_iter().collect();
+            match specs {
+                Ok(specs) => {
+                    assert!(MetadataStore::from_csv(csv.to_str().unwrap(), &specs).is_err())
+                },
+                Err(_) => assert!(!spec.ends_with(":tag") && !spec.ends_with(":int")),
+            }
+        }
+
+        std::fs::remove_file(metadata_file(&dataset)).unwrap();
+        std::fs::remove_file(&csv).unwrap();
+    } // end of test_metadata_dump_and_csv
+}
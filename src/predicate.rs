@@ -0,0 +1,391 @@
+//! A small predicate language over the metadata of the documents, see MetadataStore::compile.
+//!
+//! ```text
+//! label = "Sports" and (year >= 2004 or not source in ("AP", "Reuters"))
+//! ```
+//!
+//! . comparisons : field = value, field != value, field in (value, ...), and field < value,
+//!   field <= value, field > value, field >= value on integer and timestamp fields. A comparison
+//!   does not match a document without value for its field
+//! . values : "strings" and integers. A timestamp is given in seconds since the epoch or as a
+//!   "YYYY-MM-DD" or "YYYY-MM-DDTHH:MM:SS" UTC date
+//! . combinations : and, or, not and parentheses, and binds tighter than or
+//! Keywords are case insensitive.
+
+use std::fmt;
+
+/// deepest nesting of parentheses and not accepted, parsing is recursive
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => write!(f, "{:?}", s),
+            Value::Int(i) => write!(f, "{}", i),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Eq(String, Value),
+    /// the field has a value, different from the value
+    Ne(String, Value),
+    In(String, Vec<Value>),
+    Cmp(String, Comparison, Value),
+    And(Vec<Predicate>),
+    Or(Vec<Predicate>),
+    Not(Box<Predicate>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    And,
+    Or,
+    Not,
+    In,
+    Eq,
+    Ne,
+    Cmp(Comparison),
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut i: usize = 0;
+
+    while i < chars.len() {
+        let c: char = chars[i];
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '(' | ')' | ',' | '=' => {
+                tokens.push(match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    ',' => Token::Comma,
+                    _ => Token::Eq,
+                });
+                i += 1;
+            },
+            '!' | '<' | '>' => {
+                let with_eq: bool = chars.get(i + 1) == Some(&'=');
+                tokens.push(match (c, with_eq) {
+                    ('!', true) => Token::Ne,
+                    ('!', false) => return Err(String::from("expected != at !")),
+                    ('<', true) => Token::Cmp(Comparison::Le),
+                    ('<', false) => Token::Cmp(Comparison::Lt),
+                    ('>', true) => Token::Cmp(Comparison::Ge),
+                    _ => Token::Cmp(Comparison::Gt),
+                });
+                i += if with_eq { 2 } else { 1 };
+            },
+            '"' => {
+                let mut s: String = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(String::from("unterminated string")),
+                        Some('"') => break,
+                        Some('\\') if i + 1 < chars.len() => {
+                            s.push(chars[i + 1]);
+                            i += 2;
+                        },
+                        Some(c) => {
+                            s.push(*c);
+                            i += 1;
+                        },
+                    }
+                }
+                tokens.push(Token::Str(s));
+                i += 1;
+            },
+            _ if c.is_ascii_digit() || c == '-' => {
+                let start: usize = i;
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let number: String = chars[start..i].iter().collect();
+                let value: i64 = number
+                    .parse()
+                    .map_err(|_| format!("invalid integer {}", number))?;
+                tokens.push(Token::Int(value));
+            },
+            _ if c.is_alphabetic() || c == '_' => {
+                let start: usize = i;
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(match word.to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "in" => Token::In,
+                    _ => Token::Ident(word),
+                });
+            },
+            _ => return Err(format!("unexpected character {:?}", c)),
+        }
+    }
+
+    Ok(tokens)
+} // end of tokenize
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token: Option<Token> = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("expected {:?}, found {:?}", expected, token)),
+            None => Err(format!("expected {:?} at the end", expected)),
+        }
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!("nested deeper than {}", MAX_DEPTH));
+        }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Predicate, String> {
+        let mut terms: Vec<Predicate> = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            terms.push(self.and()?);
+        }
+        Ok(if terms.len() == 1 { terms.pop().unwrap() } else { Predicate::Or(terms) })
+    }
+
+    fn and(&mut self) -> Result<Predicate, String> {
+        let mut terms: Vec<Predicate> = vec![self.unary()?];
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            terms.push(self.unary()?);
+        }
+        Ok(if terms.len() == 1 { terms.pop().unwrap() } else { Predicate::And(terms) })
+    }
+
+    fn unary(&mut self) -> Result<Predicate, String> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.next();
+                self.enter()?;
+                let predicate: Predicate = Predicate::Not(Box::new(self.unary()?));
+                self.depth -= 1;
+                Ok(predicate)
+            },
+            Some(Token::LParen) => {
+                self.next();
+                self.enter()?;
+                let predicate: Predicate = self.or()?;
+                self.expect(Token::RParen)?;
+                self.depth -= 1;
+                Ok(predicate)
+            },
+            _ => self.comparison(),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Value::Str(s)),
+            Some(Token::Int(i)) => Ok(Value::Int(i)),
+            Some(token) => Err(format!("expected a value, found {:?}", token)),
+            None => Err(String::from("expected a value at the end")),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Predicate, String> {
+        let field: String = match self.next() {
+            Some(Token::Ident(field)) => field,
+            Some(token) => return Err(format!("expected a field, found {:?}", token)),
+            None => return Err(String::from("expected a field at the end")),
+        };
+
+        match self.next() {
+            Some(Token::Eq) => Ok(Predicate::Eq(field, self.value()?)),
+            Some(Token::Ne) => Ok(Predicate::Ne(field, self.value()?)),
+            Some(Token::Cmp(comparison)) => Ok(Predicate::Cmp(field, comparison, self.value()?)),
+            Some(Token::In) => {
+                self.expect(Token::LParen)?;
+                let mut values: Vec<Value> = vec![self.value()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.next();
+                    values.push(self.value()?);
+                }
+                self.expect(Token::RParen)?;
+                Ok(Predicate::In(field, values))
+            },
+            Some(token) => Err(format!("expected a comparison after {}, found {:?}", field, token)),
+            None => Err(format!("expected a comparison after {}", field)),
+        }
+    }
+} // end of impl Parser
+
+/// parses a predicate, the error tells what is wrong
+pub fn parse(input: &str) -> Result<Predicate, String> {
+    let mut parser: Parser = Parser { tokens: tokenize(input)?, position: 0, depth: 0 };
+    if parser.tokens.is_empty() {
+        return Err(String::from("empty predicate"));
+    }
+
+    let predicate: Predicate = parser.or()?;
+    match parser.peek() {
+        Some(token) => Err(format!("unexpected {:?} after the predicate", token)),
+        None => Ok(predicate),
+    }
+}
+
+/// days since 1970-01-01 of a date of the proleptic gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year: i64 = if month <= 2 { year - 1 } else { year };
+    let era: i64 = year.div_euclid(400);
+    let year_of_era: i64 = year - era * 400;
+    let day_of_year: i64 = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era: i64 = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// seconds since the epoch of a "YYYY-MM-DD" or "YYYY-MM-DDTHH:MM:SS[Z]" UTC date, or of an
+/// integer number of seconds
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    let s: &str = s.trim();
+    if let Ok(seconds) = s.parse::<i64>() {
+        return Some(seconds);
+    }
+
+    let (date, time) = match s.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time.trim_end_matches('Z'))),
+        None => (s, None),
+    };
+
+    let parts: Vec<i64> = date
+        .split('-')
+        .map(|part: &str| part.parse::<i64>().ok())
+        .collect::<Option<Vec<i64>>>()?;
+    let (year, month, day) = match parts.as_slice() {
+        [year, month, day] => (*year, *month, *day),
+        _ => return None,
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let seconds: i64 = match time {
+        Some(time) => {
+            let parts: Vec<i64> = time
+                .split(':')
+                .map(|part: &str| part.parse::<i64>().ok())
+                .collect::<Option<Vec<i64>>>()?;
+            match parts.as_slice() {
+                [h, m, s] if *h < 24 && *m < 60 && *s < 60 => h * 3600 + m * 60 + s,
+                [h, m] if *h < 24 && *m < 60 => h * 3600 + m * 60,
+                _ => return None,
+            }
+        },
+        None => 0,
+    };
+
+    Some(days_from_civil(year, month, day) * 86400 + seconds)
+} // end of parse_timestamp
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let predicate: Predicate =
+            parse(r#"label = "Sports" and (year >= 2004 OR not source in ("AP", "Reuters"))"#)
+                .unwrap();
+        assert_eq!(
+            predicate,
+            Predicate::And(vec![
+                Predicate::Eq(String::from("label"), Value::Str(String::from("Sports"))),
+                Predicate::Or(vec![
+                    Predicate::Cmp(String::from("year"), Comparison::Ge, Value::Int(2004)),
+                    Predicate::Not(Box::new(Predicate::In(
+                        String::from("source"),
+                        vec![
+                            Value::Str(String::from("AP")),
+                            Value::Str(String::from("Reuters"))
+                        ]
+                    ))),
+                ]),
+            ])
+        );
+
+        // and binds tighter than or
+        let predicate: Predicate = parse("a = 1 or b = 2 and c != -3").unwrap();
+        assert!(matches!(predicate, Predicate::Or(ref terms) if terms.len() == 2));
+        assert_eq!(parse("c != -3").unwrap(), Predicate::Ne(String::from("c"), Value::Int(-3)));
+
+        let too_deep: String = format!("{}a = 1", "not ".repeat(MAX_DEPTH + 1));
+        for invalid in [
+            "",
+            "label",
+            "label = ",
+            "label = \"Sports",
+            "label in (1, 2",
+            "(a = 1",
+            "a = 1 b = 2",
+            "a < b",
+            "a ~ 1",
+            &too_deep,
+        ] {
+            assert!(parse(invalid).is_err(), "{}", invalid);
+        }
+    } // end of test_parse
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01"), Some(0));
+        assert_eq!(parse_timestamp("2004-08-15"), Some(1092528000));
+        assert_eq!(parse_timestamp("2004-08-15T10:30:00Z"), Some(1092528000 + 37800));
+        assert_eq!(parse_timestamp("1092528000"), Some(1092528000));
+        assert_eq!(parse_timestamp("1969-12-31"), Some(-86400));
+        for invalid in ["2004-13-01", "2004-08", "yesterday", "2004-08-15T25:00:00"] {
+            assert_eq!(parse_timestamp(invalid), None, "{}", invalid);
+        }
+    }
+}
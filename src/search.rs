@@ -1,67 +1,1286 @@
+use std::io;
+use std::ops::Deref;
+use std::sync::Arc;
 use std::time::Instant;
 
 use mimalloc::MiMalloc;
-#[allow(unused_imports)]
+use parking_lot::{Condvar, Mutex};
 use rayon::prelude::*;
 use rust_bert::pipelines::sentence_embeddings::SentenceEmbeddingsModel;
 
-#[allow(unused_imports)]
-use crate::hnsw_index::dist::{DistDot, DistHamming};
-#[allow(unused_imports)]
-use crate::hnsw_index::hnsw::{quantize, Hnsw, Neighbour};
-use crate::ss::{Features, Index, PredictRequest, PredictResponse};
-#[allow(unused_imports)]
-use crate::utils::{load_index, load_model, load_quantize_index};
+use crate::collection::{Collection, CollectionIndex, Collections};
+use crate::config::Config;
+use crate::documents::DocumentStore;
+use crate::error::SearchError;
+use crate::hnsw_index::filter::{And, FilterT};
+use crate::hnsw_index::hnsw::{DataId, Neighbour};
+use crate::hnsw_index::rerank::RERANK_FACTOR;
+use crate::index::{
+    search_adaptive, FilterPolicy, FilteredSearch, IndexConfig, SearchIndex, SearchStrategy,
+};
+use crate::keys::{Key, KeyMap};
+use crate::metadata::MetadataFilter;
+use crate::predicate::{parse, Predicate};
+use crate::ss;
+use crate::ss::{
+    CollectionInfo, Features, Hit, Index, ListCollectionsResponse, LoadCollectionRequest,
+    PredictRequest, PredictResponse, ReloadIndexRequest, ReloadIndexResponse,
+    SearchByVectorRequest, SearchByVectorResponse, UnloadCollectionRequest, Vector,
+};
+use crate::utils::load_model;
 
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
-thread_local! {
-    pub static MODEL: SentenceEmbeddingsModel = load_model();
-    // pub static INDEX: Hnsw<f32, DistDot> = load_index("news");
-    pub static INDEX: Hnsw<i8, DistHamming> = load_quantize_index("news");
+/// A bounded pool of items, used for models. A request takes an item for the time of its use and
+/// waits when all the items are busy, so memory does not grow with the number of threads.
+pub struct Pool<T> {
+    items: Mutex<Vec<T>>,
+    available: Condvar,
+    size: usize,
 }
 
-pub fn preprocess(request: &PredictRequest) -> (Vec<String>, usize) {
-    let query: Vec<String> = request
-        .features
-        .iter()
-        .map(|f: &Features| f.query.clone() as String)
-        .collect();
+/// an item taken from the pool, given back on drop (even if its user panics)
+pub struct Pooled<'a, T> {
+    pool: &'a Pool<T>,
+    item: Option<T>,
+}
+
+impl<'a, T> Deref for Pooled<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.item.as_ref().unwrap()
+    }
+}
+
+impl<'a, T> Drop for Pooled<'a, T> {
+    fn drop(&mut self) {
+        if let Some(item) = self.item.take() {
+            self.pool.items.lock().push(item);
+            self.pool.available.notify_one();
+        }
+    }
+}
+
+impl<T> Pool<T> {
+    pub fn new(items: Vec<T>) -> Self {
+        assert!(!items.is_empty(), "a pool needs at least one item");
+        let size: usize = items.len();
+
+        Pool { items: Mutex::new(items), available: Condvar::new(), size }
+    }
+
+    /// number of items in the pool
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+
+    /// takes an item, waiting for one to be given back if all are busy
+    pub fn get(&self) -> Pooled<'_, T> {
+        let mut items = self.items.lock();
+        while items.is_empty() {
+            self.available.wait(&mut items);
+        }
 
-    let k: usize = request.k as usize;
+        Pooled { pool: self, item: items.pop() }
+    }
+} // end of impl Pool
+
+/// A model embedding queries. The server uses SentenceEmbeddingsModel, other models (or fakes in
+/// tests) can be pooled in a Searcher.
+pub trait Embedder: Send {
+    fn encode(&self, queries: &[String]) -> Result<Vec<Vec<f32>>, SearchError>;
+}
 
-    (query, k)
+impl Embedder for SentenceEmbeddingsModel {
+    fn encode(&self, queries: &[String]) -> Result<Vec<Vec<f32>>, SearchError> {
+        SentenceEmbeddingsModel::encode(self, queries)
+            .map_err(|e| SearchError::Internal(format!("model failed : {}", e)))
+    }
 }
 
-pub fn search(request: PredictRequest) -> PredictResponse {
-    let (query, k) = preprocess(&request);
+pub type ModelPool = Pool<Box<dyn Embedder>>;
 
-    let start: Instant = Instant::now();
-    let query_embeddings: Vec<Vec<f32>> =
-        MODEL.with(|model: &SentenceEmbeddingsModel| model.encode(&query).unwrap());
-    let model_latency: u64 = start.elapsed().as_nanos() as u64;
+/// loads size models of model_dir in a pool
+pub fn load_model_pool(model_dir: &str, size: usize) -> ModelPool {
+    Pool::new(
+        (0..size.max(1))
+            .map(|_| Box::new(load_model(model_dir)) as Box<dyn Embedder>)
+            .collect(),
+    )
+}
 
-    let query_embeddings: Vec<Vec<i8>> = query_embeddings.par_iter().map(quantize).collect();
+/// Defaults and bounds of the search parameters of requests, see Config::search_limits
+/// . default_k : k of requests asking k = 0
+/// . max_k : largest k accepted
+/// . default_ef : ef of requests asking ef = 0
+/// . max_ef : largest ef accepted
+/// . max_filter_ids : largest number of allow_ids + deny_ids accepted
+/// . filter_policy : how filtered requests are searched
+#[derive(Debug, Clone)]
+pub struct SearchLimits {
+    pub default_k: usize,
+    pub max_k: usize,
+    pub default_ef: usize,
+    pub max_ef: usize,
+    pub max_filter_ids: usize,
+    pub filter_policy: FilterPolicy,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        SearchLimits {
+            default_k: 10,
+            max_k: 1000,
+            default_ef: 30,
+            max_ef: 10_000,
+            max_filter_ids: 1_000_000,
+            filter_policy: FilterPolicy::default(),
+        }
+    }
+}
+
+impl SearchLimits {
+    /// resolves k and ef asked by a request : 0 gives the default, negative values or values over
+    /// the maximum are rejected.
+    pub fn check(&self, k: i32, ef: i32) -> Result<(usize, usize), SearchError> {
+        let resolve =
+            |name: &str, value: i32, default: usize, max: usize| match usize::try_from(value) {
+                Ok(0) => Ok(default),
+                Ok(value) if value <= max => Ok(value),
+                _ => Err(SearchError::InvalidArgument(format!(
+                    "{} must be between 0 and {}, got {}",
+                    name, max, value
+                ))),
+            };
+
+        Ok((
+            resolve("k", k, self.default_k, self.max_k)?,
+            resolve("ef", ef, self.default_ef, self.max_ef)?,
+        ))
+    }
+} // end of impl SearchLimits
+
+/// filter of a request : ids of allow, if any, minus ids of deny. Both are sorted so the
+/// FilterT impl of Vec<usize> applies.
+#[derive(Debug, Clone)]
+pub struct IdFilter {
+    allow: Option<Vec<DataId>>,
+    deny: Vec<DataId>,
+}
+
+impl IdFilter {
+    /// an empty allow list allows all ids
+    pub fn new(allow: &[u64], deny: &[u64]) -> Self {
+        let sorted = |ids: &[u64]| -> Vec<DataId> {
+            let mut ids: Vec<DataId> = ids.iter().map(|id: &u64| *id as DataId).collect();
+            ids.sort_unstable();
+            ids.dedup();
+            ids
+        };
+
+        IdFilter {
+            allow: if allow.is_empty() { None } else { Some(sorted(allow)) },
+            deny: sorted(deny),
+        }
+    }
+}
+
+impl FilterT for IdFilter {
+    fn hnsw_filter(&self, id: &DataId) -> bool {
+        let allowed: bool = match self.allow.as_ref() {
+            Some(allow) => allow.hnsw_filter(id),
+            None => true,
+        };
+
+        allowed && !self.deny.hnsw_filter(id)
+    }
+
+    fn candidate_ids(&self) -> Option<&[DataId]> {
+        self.allow.as_deref()
+    }
+
+    fn selectivity(&self, nb_point: usize) -> f32 {
+        if nb_point == 0 {
+            return 1.;
+        }
+        let nb_accepted: usize = match self.allow.as_ref() {
+            Some(allow) => allow.len(),
+            None => nb_point.saturating_sub(self.deny.len()),
+        };
+        (nb_accepted as f32 / nb_point as f32).min(1.)
+    }
+}
+
+/// search parameters of a PredictRequest, checked against the SearchLimits of the server
+#[derive(Debug, Clone)]
+pub struct SearchParams {
+    pub k: usize,
+    pub ef: usize,
+    /// hits farther than max_distance are dropped
+    pub max_distance: Option<f32>,
+    pub filter: Option<IdFilter>,
+    /// predicate on the metadata of the documents, compiled against the collection searched
+    pub predicate: Option<Predicate>,
+    pub filter_policy: FilterPolicy,
+    /// stored text and fields of the documents returned with the hits
+    pub include_text: bool,
+    pub include_fields: Vec<String>,
+}
+
+impl SearchParams {
+    pub fn from_request(
+        request: &PredictRequest,
+        limits: &SearchLimits,
+    ) -> Result<Self, SearchError> {
+        let (k, ef) = limits.check(request.k, request.ef)?;
+
+        if request.max_distance.is_nan() || request.max_distance < 0. {
+            return Err(SearchError::InvalidArgument(format!(
+                "max_distance must be positive, got {}",
+                request.max_distance
+            )));
+        }
+        let max_distance: Option<f32> =
+            if request.max_distance > 0. { Some(request.max_distance) } else { None };
+
+        let nb_filter_id: usize = request.allow_ids.len() + request.deny_ids.len();
+        if nb_filter_id > limits.max_filter_ids {
+            return Err(SearchError::InvalidArgument(format!(
+                "at most {} allow_ids and deny_ids are accepted, got {}",
+                limits.max_filter_ids, nb_filter_id
+            )));
+        }
+        let filter: Option<IdFilter> = if nb_filter_id > 0 {
+            Some(IdFilter::new(&request.allow_ids, &request.deny_ids))
+        } else {
+            None
+        };
+
+        let predicate: Option<Predicate> = if request.filter.trim().is_empty() {
+            None
+        } else {
+            Some(parse(&request.filter).map_err(|e: String| {
+                SearchError::InvalidArgument(format!("invalid filter : {}", e))
+            })?)
+        };
+
+        Ok(SearchParams {
+            k,
+            ef,
+            max_distance,
+            filter,
+            predicate,
+            filter_policy: limits.filter_policy.clone(),
+            include_text: request.include_text,
+            include_fields: request.include_fields.clone(),
+        })
+    }
+
+    /// searches query in index with these parameters
+    #[allow(clippy::ptr_arg)]
+    pub fn search(&self, index: &dyn SearchIndex, query: &Vec<f32>) -> Vec<Neighbour> {
+        self.search_filtered(index, query, None).neighbours
+    }
 
-    let start: Instant = Instant::now();
-    // let neighbor_index: Vec<Vec<Neighbour>> =
-    //     INDEX.with(|index: &Hnsw<f32, DistDot>| index.parallel_search(&query_embeddings, k, 30));
-    let neighbor_index: Vec<Vec<Neighbour>> =
-        INDEX.with(|index: &Hnsw<i8, DistHamming>| index.parallel_search(&query_embeddings, k, 30));
-    let search_latency: u64 = start.elapsed().as_nanos() as u64;
+    /// searches query in index with these parameters, the hits must also be accepted by
+    /// metadata_filter. A filtered search adapts to the selectivity of the filters, see
+    /// search_adaptive.
+    #[allow(clippy::ptr_arg)]
+    pub fn search_filtered(
+        &self,
+        index: &dyn SearchIndex,
+        query: &Vec<f32>,
+        metadata_filter: Option<&MetadataFilter>,
+    ) -> FilteredSearch {
+        let both: And<&dyn FilterT, &dyn FilterT>;
+        let filter: Option<&dyn FilterT> = match (self.filter.as_ref(), metadata_filter) {
+            (None, None) => None,
+            (Some(f), None) => Some(f as &dyn FilterT),
+            (None, Some(f)) => Some(f as &dyn FilterT),
+            (Some(ids), Some(metadata)) => {
+                both = And(ids, metadata);
+                Some(&both)
+            },
+        };
+        let mut searched: FilteredSearch =
+            search_adaptive(index, query, self.k, self.ef, filter, &self.filter_policy);
 
-    PredictResponse {
-        indices: neighbor_index
+        if let Some(max_distance) = self.max_distance {
+            // neighbours are sorted by increasing distance
+            let nb_kept: usize = searched
+                .neighbours
+                .partition_point(|n: &Neighbour| n.distance <= max_distance);
+            searched.neighbours.truncate(nb_kept);
+        }
+
+        searched
+    }
+} // end of impl SearchParams
+
+/// name of the default collection of a Searcher built by new
+pub const DEFAULT_COLLECTION: &str = "default";
+
+/// State shared by all the requests of the server : the collections and the models. Until the
+/// default collection is loaded, requests are answered with SearchError::Unavailable.
+pub struct Searcher {
+    models: ModelPool,
+    collections: Collections,
+    limits: SearchLimits,
+}
+
+impl Searcher {
+    /// a searcher without collection, see set_index
+    pub fn new(models: ModelPool, limits: SearchLimits) -> Self {
+        Searcher { models, collections: Collections::new(DEFAULT_COLLECTION), limits }
+    }
+
+    /// loads the collections and the models of config, the default collection is config.index
+    pub fn load(config: &Config) -> io::Result<Self> {
+        let collections: Collections = Collections::new(&config.index);
+        for name in config.collection_names() {
+            let collection: Collection = Collection::load(&name, config.collection_config(&name))?;
+            collections
+                .insert(collection)
+                .map_err(|e: SearchError| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        }
+
+        let models: ModelPool = load_model_pool(&config.model_dir, config.model_pool);
+
+        Ok(Searcher { models, collections, limits: config.search_limits() })
+    }
+
+    /// sets the index of the default collection, which is added with the default IndexConfig if
+    /// missing
+    pub fn set_index(&self, index: Arc<dyn SearchIndex>) {
+        match self.collections.get("") {
+            Ok(collection) => collection.set_index(index),
+            Err(_) => {
+                let name: &str = self.collections.get_default_name();
+                _ = self
+                    .collections
+                    .insert(Collection::new(name, IndexConfig::default(), index));
+            },
+        }
+    }
+
+    /// the index of the default collection, SearchError::Unavailable if it is not loaded
+    pub fn get_index(&self) -> Result<Arc<dyn SearchIndex>, SearchError> {
+        Ok(self.collections.get("")?.get_index())
+    }
+
+    pub fn get_collections(&self) -> &Collections {
+        &self.collections
+    }
+
+    /// the dump of an admin request : dataset, with rerank_dataset if not empty
+    fn dump_config(&self, dataset: String, rerank_dataset: String) -> IndexConfig {
+        let rerank_factor: usize = match self.collections.get("") {
+            Ok(collection) => collection.get_config().rerank_factor,
+            Err(_) => RERANK_FACTOR,
+        };
+
+        IndexConfig {
+            dataset,
+            rerank_dataset: Some(rerank_dataset)
+                .filter(|rerank_dataset: &String| !rerank_dataset.is_empty()),
+            rerank_factor,
+        }
+    }
+
+    /// Reloads the index of a collection from the dump of request, or the one of the collection,
+    /// see Collection::reload. It blocks for the time of the load. NotFound for an unknown
+    /// collection.
+    pub fn reload(&self, request: ReloadIndexRequest) -> Result<ReloadIndexResponse, SearchError> {
+        let collection: Arc<Collection> = self.collections.get(&request.collection)?;
+        let config: Option<IndexConfig> = if request.dataset.is_empty() {
+            None
+        } else {
+            Some(self.dump_config(request.dataset, request.rerank_dataset))
+        };
+
+        collection.reload(config)
+    }
+
+    pub fn list_collections(&self) -> ListCollectionsResponse {
+        let default: &str = self.collections.get_default_name();
+
+        ListCollectionsResponse {
+            collections: self
+                .collections
+                .list()
+                .iter()
+                .map(|collection: &Arc<Collection>| {
+                    collection.info(collection.get_name() == default)
+                })
+                .collect(),
+        }
+    }
+
+    /// Loads a collection from the dump of request, its name by default. It blocks for the time of
+    /// the load. Errors are InvalidArgument without name, FailedPrecondition if the collection is
+    /// already loaded, Internal if the dump cannot be loaded.
+    pub fn load_collection(
+        &self,
+        request: LoadCollectionRequest,
+    ) -> Result<CollectionInfo, SearchError> {
+        if request.name.trim().is_empty() {
+            return Err(SearchError::InvalidArgument(String::from("the collection has no name")));
+        }
+        // checked before the long load, and again when the collection is added
+        if self.collections.contains(&request.name) {
+            return Err(SearchError::FailedPrecondition(format!(
+                "collection {} is already loaded",
+                request.name
+            )));
+        }
+
+        let dataset: String =
+            if request.dataset.is_empty() { request.name.clone() } else { request.dataset };
+        let config: IndexConfig = self.dump_config(dataset, request.rerank_dataset);
+        let collection: Collection = Collection::load(&request.name, config)?;
+        let collection: Arc<Collection> = self.collections.insert(collection)?;
+
+        Ok(collection.info(collection.get_name() == self.collections.get_default_name()))
+    }
+
+    /// removes a collection, the searches running on it end on its index
+    pub fn unload_collection(
+        &self,
+        request: UnloadCollectionRequest,
+    ) -> Result<CollectionInfo, SearchError> {
+        let collection: Arc<Collection> = self.collections.remove(&request.name)?;
+        log::info!("collection {} unloaded", collection.get_name());
+
+        Ok(collection.info(false))
+    }
+
+    pub fn get_model_pool(&self) -> &ModelPool {
+        &self.models
+    }
+
+    pub fn get_limits(&self) -> &SearchLimits {
+        &self.limits
+    }
+
+    /// Checks a request, resolves its collection and compiles its filter, without calling the
+    /// model.
+    /// Errors are InvalidArgument for no or empty queries, parameters out of the limits and
+    /// invalid filters or document fields, Unavailable without index, NotFound for an unknown
+    /// collection, FailedPrecondition for a filter on a collection without metadata or documents
+    /// asked from a collection without document store.
+    pub fn prepare(&self, request: &PredictRequest) -> Result<PreparedSearch, SearchError> {
+        let params: SearchParams = SearchParams::from_request(request, &self.limits)?;
+        let queries: Vec<String> = preprocess(request)?;
+        let collection_index: Arc<CollectionIndex> = self
+            .collections
+            .get(&request.collection)?
+            .get_collection_index();
+        let metadata_filter: Option<MetadataFilter> = match params.predicate.as_ref() {
+            Some(predicate) => Some(collection_index.compile_filter(predicate)?),
+            None => None,
+        };
+        let documents: Option<Arc<DocumentStore>> =
+            if params.include_text || !params.include_fields.is_empty() {
+                Some(collection_index.get_documents(&params.include_fields)?)
+            } else {
+                None
+            };
+
+        Ok(PreparedSearch {
+            params,
+            queries,
+            index: Arc::clone(&collection_index.index),
+            metadata_filter,
+            keys: collection_index.keys.clone(),
+            documents,
+        })
+    }
+
+    /// Embeds the queries of all the searches with one call of the model, then searches them in
+    /// parallel. Returns one response by search, in order, the model and search latencies are the
+    /// ones of the whole batch. It blocks on inference and search, so it must not run on the async
+    /// executor.
+    /// If the model fails, every search fails with SearchError::Internal.
+    pub fn search_batch(
+        &self,
+        searches: Vec<PreparedSearch>,
+    ) -> Vec<Result<PredictResponse, SearchError>> {
+        let queries: Vec<String> = searches
             .iter()
-            .map(|indices: &Vec<Neighbour>| Index {
-                index: indices
-                    .iter()
-                    .map(|idx: &Neighbour| idx.d_id as i32)
-                    .collect(),
+            .flat_map(|search: &PreparedSearch| search.queries.iter().cloned())
+            .collect();
+
+        let start: Instant = Instant::now();
+        let encoded: Result<Vec<Vec<f32>>, SearchError> = self.models.get().encode(&queries);
+        let model_latency: u64 = start.elapsed().as_nanos() as u64;
+
+        let query_embeddings: Vec<Vec<f32>> = match encoded {
+            Ok(query_embeddings) if query_embeddings.len() == queries.len() => query_embeddings,
+            Ok(query_embeddings) => {
+                let e: SearchError = SearchError::Internal(format!(
+                    "model returned {} embeddings for {} queries",
+                    query_embeddings.len(),
+                    queries.len()
+                ));
+                return searches.iter().map(|_| Err(e.clone())).collect();
+            },
+            Err(e) => return searches.iter().map(|_| Err(e.clone())).collect(),
+        };
+
+        // the embeddings of each search, in order
+        let mut query_embeddings = query_embeddings.into_iter();
+        let batch: Vec<(&PreparedSearch, Vec<Vec<f32>>)> = searches
+            .iter()
+            .map(|search: &PreparedSearch| {
+                (
+                    search,
+                    query_embeddings
+                        .by_ref()
+                        .take(search.queries.len())
+                        .collect(),
+                )
             })
+            .collect();
+
+        let start: Instant = Instant::now();
+        let neighbor_indices: Vec<Result<Vec<FilteredSearch>, SearchError>> = batch
+            .par_iter()
+            .map(|(search, embeddings): &(&PreparedSearch, Vec<Vec<f32>>)| {
+                search.search(embeddings)
+            })
+            .collect();
+        let search_latency: u64 = start.elapsed().as_nanos() as u64;
+
+        neighbor_indices
+            .into_iter()
+            .zip(searches.iter())
+            .map(|(neighbor_index, search)| {
+                let keys: Option<&KeyMap> = search.keys.as_deref();
+                Ok(PredictResponse {
+                    indices: neighbor_index?
+                        .iter()
+                        .map(|searched: &FilteredSearch| {
+                            let mut index: Index = to_filtered_index(searched, keys);
+                            if let Some(documents) = search.documents.as_ref() {
+                                add_documents(&mut index, documents, &search.params);
+                            }
+                            index
+                        })
+                        .collect(),
+                    model_latency,
+                    search_latency,
+                    queue_latency: 0,
+                    batch_size: queries.len() as u32,
+                })
+            })
+            .collect()
+    }
+
+    // end of search_batch
+
+    /// Embeds the queries of a request and searches them, see prepare and search_batch.
+    pub fn search(&self, request: PredictRequest) -> Result<PredictResponse, SearchError> {
+        let search: PreparedSearch = self.prepare(&request)?;
+        self.search_batch(vec![search]).pop().unwrap()
+    }
+
+    /// Searches query vectors already embedded. Vectors are checked against the dimension of the
+    /// index, an invalid request gives SearchError::InvalidArgument.
+    pub fn search_by_vector(
+        &self,
+        request: SearchByVectorRequest,
+    ) -> Result<SearchByVectorResponse, SearchError> {
+        let (k, ef) = self.limits.check(request.k, request.ef)?;
+        let collection_index: Arc<CollectionIndex> = self
+            .collections
+            .get(&request.collection)?
+            .get_collection_index();
+        let index: &Arc<dyn SearchIndex> = &collection_index.index;
+        let queries: Vec<VectorQuery> = validate_vectors(request.vectors, index.as_ref())?;
+
+        let start: Instant = Instant::now();
+        let neighbor_index: Vec<Vec<Neighbour>> = queries
+            .par_iter()
+            .map(|query: &VectorQuery| match query {
+                VectorQuery::Float(v) => Ok(index.search(v, k, ef)),
+                VectorQuery::Quantized(v) => index.search_quantized(v, k, ef).ok_or_else(|| {
+                    SearchError::Internal(format!("{} cannot search i8 vectors", index.name()))
+                }),
+            })
+            .collect::<Result<Vec<Vec<Neighbour>>, SearchError>>()?;
+        let search_latency: u64 = start.elapsed().as_nanos() as u64;
+
+        Ok(SearchByVectorResponse {
+            indices: neighbor_index
+                .iter()
+                .map(|neighbours: &Vec<Neighbour>| {
+                    to_index(neighbours, collection_index.keys.as_deref())
+                })
+                .collect(),
+            search_latency,
+        })
+    }
+} // end of impl Searcher
+
+/// a PredictRequest checked by Searcher::prepare, ready to be embedded and searched
+pub struct PreparedSearch {
+    params: SearchParams,
+    queries: Vec<String>,
+    index: Arc<dyn SearchIndex>,
+    metadata_filter: Option<MetadataFilter>,
+    /// keys of the documents of index, to translate the hits
+    keys: Option<Arc<KeyMap>>,
+    /// store of the documents, when the request asks for their text or fields
+    documents: Option<Arc<DocumentStore>>,
+}
+
+impl PreparedSearch {
+    pub fn get_nb_query(&self) -> usize {
+        self.queries.len()
+    }
+
+    /// searches the embeddings of the queries, Internal if the model does not agree with the index
+    /// on dimension
+    fn search(&self, query_embeddings: &[Vec<f32>]) -> Result<Vec<FilteredSearch>, SearchError> {
+        if let Some(embedding) = query_embeddings
+            .iter()
+            .find(|embedding: &&Vec<f32>| embedding.len() != self.index.get_dimension())
+        {
+            return Err(SearchError::Internal(format!(
+                "model dimension {} does not match index dimension {}",
+                embedding.len(),
+                self.index.get_dimension()
+            )));
+        }
+
+        Ok(query_embeddings
+            .par_iter()
+            .map(|query: &Vec<f32>| {
+                self.params.search_filtered(
+                    self.index.as_ref(),
+                    query,
+                    self.metadata_filter.as_ref(),
+                )
+            })
+            .collect())
+    }
+}
+
+/// a query vector of a SearchByVectorRequest
+#[derive(Debug, PartialEq)]
+pub enum VectorQuery {
+    Float(Vec<f32>),
+    Quantized(Vec<i8>),
+}
+
+/// checks that each vector has exactly one of values or quantized, with the dimension of the
+/// index, and that quantized vectors are sent only to a quantized index.
+pub fn validate_vectors(
+    vectors: Vec<Vector>,
+    index: &dyn SearchIndex,
+) -> Result<Vec<VectorQuery>, SearchError> {
+    let dimension: usize = index.get_dimension();
+    let check_dimension = |rank: usize, len: usize| -> Result<(), SearchError> {
+        if len != dimension {
+            return Err(SearchError::InvalidArgument(format!(
+                "vector {} has dimension {}, index dimension is {}",
+                rank, len, dimension
+            )));
+        }
+        Ok(())
+    };
+
+    vectors
+        .into_iter()
+        .enumerate()
+        .map(|(rank, vector): (usize, Vector)| {
+            match (vector.values.is_empty(), vector.quantized.is_empty()) {
+                (false, true) => {
+                    check_dimension(rank, vector.values.len())?;
+                    Ok(VectorQuery::Float(vector.values))
+                },
+                (true, false) => {
+                    if !index.is_quantized() {
+                        return Err(SearchError::InvalidArgument(format!(
+                            "vector {} is quantized but the index served ({}) is not",
+                            rank,
+                            index.name()
+                        )));
+                    }
+                    check_dimension(rank, vector.quantized.len())?;
+                    Ok(VectorQuery::Quantized(
+                        vector.quantized.iter().map(|b: &u8| *b as i8).collect(),
+                    ))
+                },
+                (true, true) => {
+                    Err(SearchError::InvalidArgument(format!("vector {} is empty", rank)))
+                },
+                (false, false) => Err(SearchError::InvalidArgument(format!(
+                    "vector {} has both values and quantized",
+                    rank
+                ))),
+            }
+        })
+        .collect()
+} // end of validate_vectors
+
+/// converts the neighbours of a query to its response, with the keys of the documents if any
+#[allow(clippy::ptr_arg)]
+pub fn to_index(neighbours: &Vec<Neighbour>, keys: Option<&KeyMap>) -> Index {
+    Index {
+        index: neighbours
+            .iter()
+            .map(|n: &Neighbour| n.d_id as i32)
             .collect(),
-        model_latency,
-        search_latency,
+        hits: neighbours
+            .iter()
+            .map(|n: &Neighbour| Hit {
+                id: n.d_id as u64,
+                distance: n.distance,
+                key: keys
+                    .and_then(|keys: &KeyMap| keys.get_key(n.d_id))
+                    .map(|key: &Key| key.to_string())
+                    .unwrap_or_default(),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// converts the neighbours of a filtered search to its response, with the strategy used
+pub fn to_filtered_index(searched: &FilteredSearch, keys: Option<&KeyMap>) -> Index {
+    let (strategy, ef) = match searched.strategy {
+        SearchStrategy::Graph(ef) => (ss::SearchStrategy::Graph, ef),
+        SearchStrategy::Widened(ef) => (ss::SearchStrategy::Widened, ef),
+        SearchStrategy::Scan => (ss::SearchStrategy::Scan, 0),
+    };
+
+    Index { strategy: strategy as i32, ef: ef as u32, ..to_index(&searched.neighbours, keys) }
+}
+
+/// fills the hits of index with the text and the fields of their documents asked by params
+pub fn add_documents(index: &mut Index, documents: &DocumentStore, params: &SearchParams) {
+    // each field asked with its rank in the documents, the text being first
+    let ranks: Vec<(&String, usize)> = params
+        .include_fields
+        .iter()
+        .filter_map(|field: &String| {
+            documents
+                .get_fields()
+                .iter()
+                .position(|stored: &String| stored == field)
+                .map(|rank: usize| (field, rank + 1))
+        })
+        .collect();
+
+    for hit in index.hits.iter_mut() {
+        let values: Vec<&str> = match documents.get(hit.id as DataId) {
+            Some(values) => values,
+            None => continue,
+        };
+        if params.include_text {
+            hit.text = values[0].to_string();
+        }
+        for (field, rank) in ranks.iter() {
+            hit.fields
+                .insert(field.to_string(), values[*rank].to_string());
+        }
     }
 }
+
+/// queries of a request, there must be at least one and none can be empty
+pub fn preprocess(request: &PredictRequest) -> Result<Vec<String>, SearchError> {
+    if request.features.is_empty() {
+        return Err(SearchError::InvalidArgument(String::from("request has no query")));
+    }
+    if let Some(rank) = request
+        .features
+        .iter()
+        .position(|f: &Features| f.query.trim().is_empty())
+    {
+        return Err(SearchError::InvalidArgument(format!("query {} is empty", rank)));
+    }
+
+    Ok(request
+        .features
+        .iter()
+        .map(|f: &Features| f.query.clone() as String)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use std::{fs, thread};
+
+    use super::*;
+    use crate::documents::documents_file;
+    use crate::hnsw_index::api::AnnT;
+    use crate::hnsw_index::dist::{DistL1, DistL2, Distance};
+    use crate::hnsw_index::hnsw::{Hnsw, PointId};
+    use crate::metadata::{Column, MetadataStore};
+    use crate::testing::FakeIndex;
+
+    #[test]
+    fn test_pool_bounded() {
+        let pool: Pool<usize> = Pool::new(vec![0, 1]);
+        let busy: AtomicUsize = AtomicUsize::new(0);
+        let max_busy: AtomicUsize = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..6 {
+                s.spawn(|| {
+                    for _ in 0..5 {
+                        let item = pool.get();
+                        let nb: usize = busy.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_busy.fetch_max(nb, Ordering::SeqCst);
+                        assert!(*item < 2);
+                        thread::sleep(Duration::from_millis(1));
+                        busy.fetch_sub(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+
+        assert!(max_busy.load(Ordering::SeqCst) <= pool.get_size());
+        // all the items came back
+        let mut items: Vec<usize> = pool.items.lock().clone();
+        items.sort_unstable();
+        assert_eq!(items, vec![0, 1]);
+    } // end of test_pool_bounded
+
+    #[test]
+    fn test_to_index() {
+        let neighbours: Vec<Neighbour> = vec![
+            Neighbour::new(3, 0.1, PointId(0, 0)),
+            Neighbour::new(1 << 40, 0.5, PointId(0, 1)),
+        ];
+        let index: Index = to_index(&neighbours, None);
+
+        assert_eq!(index.hits.len(), 2);
+        assert_eq!(index.hits[0].id, 3);
+        assert_eq!(index.hits[0].distance, 0.1);
+        // ids over i32 are exact in hits only
+        assert_eq!(index.hits[1].id, 1 << 40);
+        assert_eq!(index.index[0], 3);
+        assert_eq!(index.hits[0].key, "");
+        assert_eq!((index.strategy, index.ef), (ss::SearchStrategy::Graph as i32, 0));
+
+        let searched: FilteredSearch =
+            FilteredSearch { neighbours, strategy: SearchStrategy::Widened(120) };
+        let mut keys: KeyMap = KeyMap::new();
+        for key in ["a", "b", "c", "d"] {
+            keys.insert(Key::Str(key.to_string())).unwrap();
+        }
+        let index: Index = to_filtered_index(&searched, Some(&keys));
+        assert_eq!((index.strategy, index.ef), (ss::SearchStrategy::Widened as i32, 120));
+        assert_eq!(index.hits.len(), 2);
+        // an id without key, as of a point added after the keys were dumped, has an empty key
+        assert_eq!((index.hits[0].key.as_str(), index.hits[1].key.as_str()), ("d", ""));
+    } // end of test_to_index
+
+    fn float_vector(dim: usize) -> Vector {
+        Vector { values: vec![0.5; dim], quantized: Vec::new() }
+    }
+
+    fn quantized_vector(dim: usize) -> Vector {
+        Vector { values: Vec::new(), quantized: vec![255u8; dim] }
+    }
+
+    #[test]
+    fn test_validate_vectors() {
+        let float_index: FakeIndex = FakeIndex { dimension: 4, ..FakeIndex::default() };
+        let quantized_index: FakeIndex =
+            FakeIndex { dimension: 4, quantized: true, ..FakeIndex::default() };
+
+        let queries: Vec<VectorQuery> =
+            validate_vectors(vec![float_vector(4), float_vector(4)], &float_index).unwrap();
+        assert_eq!(queries.len(), 2);
+        assert_eq!(queries[0], VectorQuery::Float(vec![0.5; 4]));
+
+        let queries: Vec<VectorQuery> =
+            validate_vectors(vec![quantized_vector(4), float_vector(4)], &quantized_index).unwrap();
+        assert_eq!(queries[0], VectorQuery::Quantized(vec![-1i8; 4]));
+
+        let e: SearchError =
+            validate_vectors(vec![float_vector(4), float_vector(3)], &float_index).unwrap_err();
+        assert!(matches!(e, SearchError::InvalidArgument(_)));
+        assert!(e.to_string().contains("vector 1 has dimension 3"));
+
+        let e: SearchError = validate_vectors(vec![quantized_vector(4)], &float_index).unwrap_err();
+        assert!(matches!(e, SearchError::InvalidArgument(_)));
+
+        let e: SearchError =
+            validate_vectors(vec![quantized_vector(5)], &quantized_index).unwrap_err();
+        assert!(matches!(e, SearchError::InvalidArgument(_)));
+
+        let empty: Vector = Vector { values: Vec::new(), quantized: Vec::new() };
+        assert!(validate_vectors(vec![empty], &float_index).is_err());
+
+        let both: Vector = Vector { values: vec![0.5; 4], quantized: vec![1u8; 4] };
+        assert!(validate_vectors(vec![both], &quantized_index).is_err());
+    } // end of test_validate_vectors
+
+    #[test]
+    fn test_search_limits() {
+        let limits: SearchLimits = SearchLimits::default();
+
+        assert_eq!(limits.check(0, 0).unwrap(), (limits.default_k, limits.default_ef));
+        assert_eq!(limits.check(5, 64).unwrap(), (5, 64));
+        assert_eq!(limits.check(limits.max_k as i32, 0).unwrap().0, limits.max_k);
+
+        // negative values must not wrap to huge usize
+        for (k, ef) in [
+            (-1, 0),
+            (0, -1),
+            (limits.max_k as i32 + 1, 0),
+            (0, limits.max_ef as i32 + 1),
+        ] {
+            let e: SearchError = limits.check(k, ef).unwrap_err();
+            assert!(matches!(e, SearchError::InvalidArgument(_)));
+        }
+    } // end of test_search_limits
+
+    #[test]
+    fn test_search_params() {
+        let limits: SearchLimits = SearchLimits { max_filter_ids: 4, ..SearchLimits::default() };
+        let index: FakeIndex = FakeIndex::range(10, 4, 0.1);
+        let query: Vec<f32> = vec![0.; 4];
+        let ids = |neighbours: Vec<Neighbour>| -> Vec<DataId> {
+            neighbours.iter().map(|n: &Neighbour| n.d_id).collect()
+        };
+
+        let request: PredictRequest = PredictRequest { k: 3, ..Default::default() };
+        let params: SearchParams = SearchParams::from_request(&request, &limits).unwrap();
+        assert_eq!(params.ef, limits.default_ef);
+        assert!(params.filter.is_none());
+        assert_eq!(ids(params.search(&index, &query)), vec![0, 1, 2]);
+
+        let request: PredictRequest =
+            PredictRequest { k: 5, max_distance: 0.25, ..Default::default() };
+        let params: SearchParams = SearchParams::from_request(&request, &limits).unwrap();
+        assert_eq!(ids(params.search(&index, &query)), vec![0, 1, 2]);
+
+        let request: PredictRequest = PredictRequest {
+            k: 5,
+            allow_ids: vec![7, 2, 5],
+            deny_ids: vec![5],
+            ..Default::default()
+        };
+        let params: SearchParams = SearchParams::from_request(&request, &limits).unwrap();
+        assert_eq!(ids(params.search(&index, &query)), vec![2, 7]);
+
+        let request: PredictRequest =
+            PredictRequest { k: 3, deny_ids: vec![0, 1], ..Default::default() };
+        let params: SearchParams = SearchParams::from_request(&request, &limits).unwrap();
+        assert_eq!(ids(params.search(&index, &query)), vec![2, 3, 4]);
+
+        let invalid: Vec<PredictRequest> = vec![
+            PredictRequest { k: -3, ..Default::default() },
+            PredictRequest { max_distance: -1., ..Default::default() },
+            PredictRequest { max_distance: f32::NAN, ..Default::default() },
+            PredictRequest { allow_ids: vec![1, 2, 3], deny_ids: vec![4, 5], ..Default::default() },
+        ];
+        for request in invalid.iter() {
+            let e: SearchError = SearchParams::from_request(request, &limits).unwrap_err();
+            assert!(matches!(e, SearchError::InvalidArgument(_)));
+        }
+    } // end of test_search_params
+
+    /// a model embedding each query to a vector of dimension dim, or failing
+    struct FakeModel {
+        dim: usize,
+        fail: bool,
+    }
+
+    impl Embedder for FakeModel {
+        fn encode(&self, queries: &[String]) -> Result<Vec<Vec<f32>>, SearchError> {
+            if self.fail {
+                return Err(SearchError::Internal(String::from("model failed : out of memory")));
+            }
+            Ok(queries.iter().map(|_| vec![0.5; self.dim]).collect())
+        }
+    }
+
+    fn fake_searcher(dim: usize, fail: bool) -> Searcher {
+        let models: ModelPool =
+            Pool::new(vec![Box::new(FakeModel { dim, fail }) as Box<dyn Embedder>]);
+        Searcher::new(models, SearchLimits::default())
+    }
+
+    fn mock_index() -> Arc<dyn SearchIndex> {
+        Arc::new(FakeIndex::range(5, 4, 0.1))
+    }
+
+    fn request(queries: &[&str], k: i32) -> PredictRequest {
+        PredictRequest {
+            features: queries
+                .iter()
+                .map(|query: &&str| Features { query: query.to_string() })
+                .collect(),
+            k,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_search_errors() {
+        // no index loaded yet
+        let searcher: Searcher = fake_searcher(4, false);
+        let e: SearchError = searcher.search(request(&["school life"], 3)).unwrap_err();
+        assert!(matches!(e, SearchError::Unavailable(_)));
+        let vectors: SearchByVectorRequest =
+            SearchByVectorRequest { vectors: vec![float_vector(4)], k: 3, ..Default::default() };
+        let e: SearchError = searcher.search_by_vector(vectors.clone()).unwrap_err();
+        assert!(matches!(e, SearchError::Unavailable(_)));
+
+        searcher.set_index(mock_index());
+        let response: PredictResponse = searcher
+            .search(request(&["school life", "sports"], 3))
+            .unwrap();
+        assert_eq!(response.indices.len(), 2);
+        assert_eq!(response.indices[0].hits.len(), 3);
+        assert_eq!(searcher.search_by_vector(vectors).unwrap().indices.len(), 1);
+
+        // invalid requests
+        for bad in [
+            request(&[], 3),
+            request(&["school life", " "], 3),
+            request(&["school life"], -1),
+        ] {
+            let e: SearchError = searcher.search(bad).unwrap_err();
+            assert!(matches!(e, SearchError::InvalidArgument(_)), "{}", e);
+        }
+
+        // the model fails or does not agree with the index
+        for searcher in [fake_searcher(4, true), fake_searcher(8, false)] {
+            searcher.set_index(mock_index());
+            let e: SearchError = searcher.search(request(&["school life"], 3)).unwrap_err();
+            assert!(matches!(e, SearchError::Internal(_)), "{}", e);
+        }
+    } // end of test_search_errors
+
+    #[test]
+    fn test_metadata_filter_search() {
+        let searcher: Searcher = fake_searcher(4, false);
+        searcher.set_index(mock_index());
+        let mut filtered: PredictRequest = request(&["school life"], 3);
+        filtered.filter = String::from("year >= 2004");
+        let e: SearchError = searcher.search(filtered.clone()).unwrap_err();
+        assert!(matches!(e, SearchError::FailedPrecondition(_)), "{}", e);
+
+        let mut metadata: MetadataStore = MetadataStore::new(5);
+        let years: Vec<Option<i64>> = vec![Some(2003), Some(2004), None, Some(2005), Some(2004)];
+        metadata.add("year", Column::Int(years)).unwrap();
+        searcher
+            .get_collections()
+            .get("")
+            .unwrap()
+            .set_collection_index(CollectionIndex {
+                index: mock_index(),
+                metadata: Some(Arc::new(metadata)),
+                keys: None,
+                documents: None,
+            });
+
+        let ids = |response: PredictResponse| -> Vec<u64> {
+            response.indices[0]
+                .hits
+                .iter()
+                .map(|hit: &Hit| hit.id)
+                .collect()
+        };
+        assert_eq!(ids(searcher.search(filtered.clone()).unwrap()), vec![1, 3, 4]);
+        filtered.deny_ids = vec![3];
+        assert_eq!(ids(searcher.search(filtered.clone()).unwrap()), vec![1, 4]);
+
+        for filter in ["year >=", "month = 1", "year = \"2004\""] {
+            filtered.filter = filter.to_string();
+            let e: SearchError = searcher.search(filtered.clone()).unwrap_err();
+            assert!(matches!(e, SearchError::InvalidArgument(_)), "{}", e);
+        }
+    } // end of test_metadata_filter_search
+
+    #[test]
+    fn test_include_documents() {
+        let searcher: Searcher = fake_searcher(4, false);
+        searcher.set_index(mock_index());
+        let mut asked: PredictRequest = request(&["school life"], 2);
+        asked.include_text = true;
+        let e: SearchError = searcher.search(asked.clone()).unwrap_err();
+        assert!(matches!(e, SearchError::FailedPrecondition(_)), "{}", e);
+
+        let fields: Vec<String> = vec![String::from("label")];
+        let stored: Vec<Vec<String>> = (0..5)
+            .map(|i: usize| vec![format!("text {}", i), format!("label {}", i % 2)])
+            .collect();
+        DocumentStore::dump("search_test_documents", &fields, &stored).unwrap();
+        let documents: DocumentStore = DocumentStore::load("search_test_documents")
+            .unwrap()
+            .unwrap();
+        fs::remove_file(documents_file("search_test_documents")).unwrap();
+        searcher
+            .get_collections()
+            .get("")
+            .unwrap()
+            .set_collection_index(CollectionIndex {
+                index: mock_index(),
+                metadata: None,
+                keys: None,
+                documents: Some(Arc::new(documents)),
+            });
+
+        let response: PredictResponse = searcher.search(asked.clone()).unwrap();
+        let hits: &Vec<Hit> = &response.indices[0].hits;
+        assert_eq!((hits[0].text.as_str(), hits[1].text.as_str()), ("text 0", "text 1"));
+        assert!(hits[0].fields.is_empty());
+
+        asked.include_text = false;
+        asked.include_fields = fields.clone();
+        let response: PredictResponse = searcher.search(asked.clone()).unwrap();
+        let hits: &Vec<Hit> = &response.indices[0].hits;
+        assert_eq!(hits[1].text, "");
+        assert_eq!(hits[1].fields.get("label").map(String::as_str), Some("label 1"));
+
+        // nothing is read when nothing is asked
+        let response: PredictResponse = searcher.search(request(&["school life"], 2)).unwrap();
+        assert!(response.indices[0].hits[0].fields.is_empty());
+
+        asked.include_fields = vec![String::from("title")];
+        let e: SearchError = searcher.search(asked).unwrap_err();
+        assert!(matches!(e, SearchError::InvalidArgument(_)), "{}", e);
+    } // end of test_include_documents
+
+    fn dump_hnsw<D: Distance<f32> + Send + Sync>(name: &str, nb_elem: usize, dim: usize, dist: D) {
+        let hnsw: Hnsw<f32, D> = Hnsw::<f32, D>::new(8, nb_elem, 16, 50, dist);
+        for i in 0..nb_elem {
+            let v: Vec<f32> = (0..dim)
+                .map(|j: usize| ((i * dim + j) % 7) as f32)
+                .collect();
+            hnsw.insert((&v, i));
+        }
+        hnsw.file_dump(name).unwrap();
+    }
+
+    #[test]
+    fn test_collection_admin() {
+        dump_hnsw("search_test_reload_a", 50, 4, DistL2 {});
+        dump_hnsw("search_test_reload_b", 80, 4, DistL2 {});
+        dump_hnsw("search_test_reload_dim", 80, 8, DistL2 {});
+        dump_hnsw("search_test_reload_dist", 80, 4, DistL1 {});
+
+        let searcher: Searcher = fake_searcher(4, false);
+        let load = |name: &str, dataset: &str| -> Result<CollectionInfo, SearchError> {
+            searcher.load_collection(LoadCollectionRequest {
+                name: name.to_string(),
+                dataset: dataset.to_string(),
+                ..Default::default()
+            })
+        };
+        let reload =
+            |collection: &str, dataset: &str| -> Result<ReloadIndexResponse, SearchError> {
+                searcher.reload(ReloadIndexRequest {
+                    dataset: dataset.to_string(),
+                    collection: collection.to_string(),
+                    ..Default::default()
+                })
+            };
+
+        let e: SearchError = reload("", "search_test_reload_b").unwrap_err();
+        assert!(matches!(e, SearchError::Unavailable(_)), "{}", e);
+
+        // each collection with its own distance
+        let info: CollectionInfo = load(DEFAULT_COLLECTION, "search_test_reload_a").unwrap();
+        assert_eq!(
+            (info.index.as_str(), info.nb_point, info.default),
+            ("hnsw f32 DistL2", 50, true)
+        );
+        let info: CollectionInfo = load("tickets", "search_test_reload_dist").unwrap();
+        assert_eq!(
+            (info.index.as_str(), info.dimension, info.default),
+            ("hnsw f32 DistL1", 4, false)
+        );
+        for (name, dataset) in [
+            ("tickets", "search_test_reload_b"),
+            (" ", "search_test_reload_b"),
+        ] {
+            assert!(load(name, dataset).is_err());
+        }
+        let e: SearchError = load("docs", "search_test_reload_missing").unwrap_err();
+        assert!(matches!(e, SearchError::Internal(_)), "{}", e);
+
+        let names: Vec<String> = searcher
+            .list_collections()
+            .collections
+            .into_iter()
+            .map(|info: CollectionInfo| info.name)
+            .collect();
+        assert_eq!(names, vec![DEFAULT_COLLECTION, "tickets"]);
+
+        let mut tickets: PredictRequest = request(&["school life"], 3);
+        tickets.collection = String::from("tickets");
+        assert_eq!(
+            searcher.search(tickets.clone()).unwrap().indices[0]
+                .hits
+                .len(),
+            3
+        );
+        tickets.collection = String::from("docs");
+        let e: SearchError = searcher.search(tickets).unwrap_err();
+        assert!(matches!(e, SearchError::NotFound(_)), "{}", e);
+
+        // a search holding the index keeps it after the swap
+        let held: Arc<dyn SearchIndex> = searcher.get_index().unwrap();
+        let response: ReloadIndexResponse = reload("", "search_test_reload_b").unwrap();
+        assert_eq!((response.previous_nb_point, response.nb_point), (50, 80));
+        assert_eq!(held.get_nb_point(), 50);
+        assert_eq!(held.search(&vec![1.; 4], 3, 16).len(), 3);
+        assert_eq!(searcher.get_index().unwrap().get_nb_point(), 80);
+        // the dump reloaded is now the one of the collection
+        let dataset = || -> String {
+            searcher
+                .list_collections()
+                .collections
+                .iter()
+                .find(|info: &&CollectionInfo| info.default)
+                .unwrap()
+                .dataset
+                .clone()
+        };
+        assert_eq!(dataset(), "search_test_reload_b");
+        assert_eq!(reload("", "").unwrap().nb_point, 80);
+        assert_eq!(dataset(), "search_test_reload_b");
+
+        // dumps not compatible are refused, the index served is kept
+        for dataset in ["search_test_reload_dim", "search_test_reload_dist"] {
+            let e: SearchError = reload("", dataset).unwrap_err();
+            assert!(matches!(e, SearchError::FailedPrecondition(_)), "{}", e);
+        }
+        let e: SearchError = reload("", "search_test_reload_missing").unwrap_err();
+        assert!(matches!(e, SearchError::Internal(_)), "{}", e);
+        assert_eq!(searcher.get_index().unwrap().get_nb_point(), 80);
+
+        // a collection reloads its own dump by default
+        assert_eq!(reload("tickets", "").unwrap().name, "hnsw f32 DistL1");
+
+        let request: UnloadCollectionRequest =
+            UnloadCollectionRequest { name: String::from("tickets") };
+        assert_eq!(
+            searcher
+                .unload_collection(request.clone())
+                .unwrap()
+                .nb_point,
+            80
+        );
+        let e: SearchError = searcher.unload_collection(request).unwrap_err();
+        assert!(matches!(e, SearchError::NotFound(_)), "{}", e);
+        let request: UnloadCollectionRequest =
+            UnloadCollectionRequest { name: DEFAULT_COLLECTION.to_string() };
+        let e: SearchError = searcher.unload_collection(request).unwrap_err();
+        assert!(matches!(e, SearchError::FailedPrecondition(_)), "{}", e);
+    } // end of test_collection_admin
+} // end of mod tests
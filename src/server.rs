@@ -1,36 +1,36 @@
 use std::net::SocketAddr;
 
 use anyhow::Result;
-use semantic_search::search::search;
-use semantic_search::ss::inference_server::{Inference, InferenceServer};
-use semantic_search::ss::{PredictRequest, PredictResponse};
-use tonic::transport::Server;
-use tonic::{Request, Response, Status};
-
-#[derive(Debug, Default)]
-pub struct VectorSearchService {}
-
-#[tonic::async_trait]
-impl Inference for VectorSearchService {
-    async fn predict(
-        &self,
-        request: Request<PredictRequest>,
-    ) -> Result<Response<PredictResponse>, Status> {
-        let reply: PredictResponse = search(request.into_inner());
-
-        Ok(Response::new(reply))
-    }
+use clap::Parser;
+use semantic_search::config::{Config, ConfigArgs};
+use semantic_search::search::Searcher;
+use semantic_search::service::{serve, shutdown_signal, ServeOptions};
+use tokio::net::TcpListener;
+
+/// gRPC server of the semantic search
+#[derive(Parser)]
+struct Args {
+    #[command(flatten)]
+    config: ConfigArgs,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let addr: SocketAddr = "127.0.0.1:50051".parse()?;
-    let service: VectorSearchService = VectorSearchService::default();
+    env_logger::init();
+
+    let config: Config = Config::load(&Args::parse().config)?;
+    config.init_thread_pool()?;
+
+    let addr: SocketAddr = config.addr.parse()?;
+    let listener: TcpListener = TcpListener::bind(addr).await?;
+    println!("listening on {}, loading index & models", addr);
+
+    // requests are accepted while the index & models load, the health service tells when they are
+    // ready
+    let options: ServeOptions = config.serve_options();
+    serve(listener, options, move || Searcher::load(&config), shutdown_signal()).await?;
 
-    Server::builder()
-        .add_service(InferenceServer::new(service))
-        .serve(addr)
-        .await?;
+    println!("server stopped");
 
     Ok(())
 }
@@ -0,0 +1,288 @@
+//! gRPC service of the search, and the serving loop of the server binary.
+//!
+//! The server accepts connections at once and loads the index and the models in the background.
+//! The standard health service (grpc.health.v1) reports ss.Inference NOT_SERVING until they are
+//! loaded, requests received before are answered UNAVAILABLE. On shutdown the server stops
+//! accepting connections and drains the requests in flight.
+//! The Admin service, which reloads the index, is served only when enabled.
+
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, OnceLock};
+
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinError;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tonic_health::server::HealthReporter;
+
+use crate::batch::{BatchConfig, Batcher};
+use crate::error::SearchError;
+use crate::search::Searcher;
+use crate::ss::admin_server::{Admin, AdminServer};
+use crate::ss::inference_server::{Inference, InferenceServer};
+use crate::ss::{
+    CollectionInfo, ListCollectionsRequest, ListCollectionsResponse, LoadCollectionRequest,
+    PredictRequest, PredictResponse, ReloadIndexRequest, ReloadIndexResponse,
+    SearchByVectorRequest, SearchByVectorResponse, UnloadCollectionRequest,
+};
+
+/// options of the server, see Config::serve_options
+/// . max_message_size : largest gRPC message sent or received, in bytes
+/// . admin : serve the Admin service
+/// . batch : limits of the batches of Predict requests
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    pub max_message_size: usize,
+    pub admin: bool,
+    pub batch: BatchConfig,
+}
+
+/// the Inference service. Clones share the same Searcher, set once loaded. Predict requests are
+/// run in batches, see Batcher.
+#[derive(Clone)]
+pub struct VectorSearchService {
+    batch_config: BatchConfig,
+    batcher: Arc<OnceLock<Batcher>>,
+}
+
+impl VectorSearchService {
+    /// a service answering UNAVAILABLE until set_searcher
+    pub fn new(batch_config: BatchConfig) -> Self {
+        VectorSearchService { batch_config, batcher: Arc::new(OnceLock::new()) }
+    }
+
+    /// a service ready to answer
+    pub fn with_searcher(searcher: Searcher, batch_config: BatchConfig) -> Self {
+        let service: VectorSearchService = VectorSearchService::new(batch_config);
+        service.set_searcher(searcher);
+        service
+    }
+
+    /// sets the searcher answering requests and starts its batch workers, returns false if one
+    /// was already set
+    pub fn set_searcher(&self, searcher: Searcher) -> bool {
+        if self.batcher.get().is_some() {
+            return false;
+        }
+        self.batcher
+            .set(Batcher::new(Arc::new(searcher), self.batch_config.clone()))
+            .is_ok()
+    }
+
+    pub fn get_searcher(&self) -> Option<&Searcher> {
+        self.batcher.get().map(Batcher::get_searcher)
+    }
+
+    fn get_batcher(&self) -> Result<&Batcher, SearchError> {
+        self.batcher
+            .get()
+            .ok_or_else(|| SearchError::Unavailable(String::from("index and models are loading")))
+    }
+
+    /// runs f with the searcher on the blocking pool, inference & search block and must not run on
+    /// the executor. A SearchError is answered with its status code.
+    async fn run_blocking<F, T>(&self, f: F) -> Result<T, Status>
+    where
+        F: FnOnce(&Searcher) -> Result<T, SearchError> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.get_batcher()?;
+
+        let batcher: Arc<OnceLock<Batcher>> = Arc::clone(&self.batcher);
+        let reply: T =
+            tokio::task::spawn_blocking(move || f(batcher.get().unwrap().get_searcher()))
+                .await
+                .map_err(|e: JoinError| Status::internal(format!("search failed : {}", e)))??;
+
+        Ok(reply)
+    }
+}
+
+#[tonic::async_trait]
+impl Inference for VectorSearchService {
+    async fn predict(
+        &self,
+        request: Request<PredictRequest>,
+    ) -> Result<Response<PredictResponse>, Status> {
+        let request: PredictRequest = request.into_inner();
+        let reply: PredictResponse = self.get_batcher()?.search(request).await?;
+
+        Ok(Response::new(reply))
+    }
+
+    async fn search_by_vector(
+        &self,
+        request: Request<SearchByVectorRequest>,
+    ) -> Result<Response<SearchByVectorResponse>, Status> {
+        let request: SearchByVectorRequest = request.into_inner();
+        let reply: SearchByVectorResponse = self
+            .run_blocking(move |searcher: &Searcher| searcher.search_by_vector(request))
+            .await?;
+
+        Ok(Response::new(reply))
+    }
+}
+
+#[tonic::async_trait]
+impl Admin for VectorSearchService {
+    async fn reload_index(
+        &self,
+        request: Request<ReloadIndexRequest>,
+    ) -> Result<Response<ReloadIndexResponse>, Status> {
+        let request: ReloadIndexRequest = request.into_inner();
+        let reply: ReloadIndexResponse = self
+            .run_blocking(move |searcher: &Searcher| searcher.reload(request))
+            .await?;
+
+        Ok(Response::new(reply))
+    }
+
+    async fn list_collections(
+        &self,
+        _request: Request<ListCollectionsRequest>,
+    ) -> Result<Response<ListCollectionsResponse>, Status> {
+        let reply: ListCollectionsResponse = self
+            .run_blocking(|searcher: &Searcher| Ok(searcher.list_collections()))
+            .await?;
+
+        Ok(Response::new(reply))
+    }
+
+    async fn load_collection(
+        &self,
+        request: Request<LoadCollectionRequest>,
+    ) -> Result<Response<CollectionInfo>, Status> {
+        let request: LoadCollectionRequest = request.into_inner();
+        let reply: CollectionInfo = self
+            .run_blocking(move |searcher: &Searcher| searcher.load_collection(request))
+            .await?;
+
+        Ok(Response::new(reply))
+    }
+
+    async fn unload_collection(
+        &self,
+        request: Request<UnloadCollectionRequest>,
+    ) -> Result<Response<CollectionInfo>, Status> {
+        let request: UnloadCollectionRequest = request.into_inner();
+        let reply: CollectionInfo = self
+            .run_blocking(move |searcher: &Searcher| searcher.unload_collection(request))
+            .await?;
+
+        Ok(Response::new(reply))
+    }
+}
+
+/// resolves on SIGINT (ctrl-c) or SIGTERM
+pub async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            log::error!("cannot listen to SIGINT : {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            },
+            Err(e) => {
+                log::error!("cannot listen to SIGTERM : {}", e);
+                std::future::pending::<()>().await;
+            },
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => log::info!("SIGINT received, shutting down"),
+        _ = terminate => log::info!("SIGTERM received, shutting down"),
+    }
+}
+
+/// Serves the Inference and health services on listener until shutdown resolves, then drains the
+/// requests in flight.
+/// load runs on the blocking pool while the server accepts requests : the health of ss.Inference
+/// is NOT_SERVING until it returns. If it fails the server shuts down and its error is returned.
+pub async fn serve<L, S>(
+    listener: TcpListener,
+    options: ServeOptions,
+    load: L,
+    shutdown: S,
+) -> anyhow::Result<()>
+where
+    L: FnOnce() -> io::Result<Searcher> + Send + 'static,
+    S: Future<Output = ()>,
+{
+    let (mut reporter, health_service) = tonic_health::server::health_reporter();
+    reporter
+        .set_not_serving::<InferenceServer<VectorSearchService>>()
+        .await;
+
+    let service: VectorSearchService = VectorSearchService::new(options.batch.clone());
+    let (failed_tx, failed_rx) = oneshot::channel::<String>();
+    {
+        let service: VectorSearchService = service.clone();
+        let mut reporter: HealthReporter = reporter.clone();
+        tokio::spawn(async move {
+            let loaded: Result<io::Result<Searcher>, JoinError> =
+                tokio::task::spawn_blocking(load).await;
+            match loaded {
+                Ok(Ok(searcher)) => {
+                    for info in searcher.list_collections().collections.iter() {
+                        println!(
+                            "serving collection {} : {} ({} points)",
+                            info.name, info.index, info.nb_point
+                        );
+                    }
+                    println!("with {} models", searcher.get_model_pool().get_size());
+                    service.set_searcher(searcher);
+                    reporter
+                        .set_serving::<InferenceServer<VectorSearchService>>()
+                        .await;
+                },
+                Ok(Err(e)) => _ = failed_tx.send(e.to_string()),
+                Err(e) => _ = failed_tx.send(e.to_string()),
+            }
+        });
+    }
+
+    let mut load_error: Option<String> = None;
+    let stop = async {
+        tokio::select! {
+            _ = shutdown => {},
+            Ok(e) = failed_rx => {
+                log::error!("cannot load the index and models : {}", e);
+                load_error = Some(e);
+            },
+        }
+        reporter
+            .set_not_serving::<InferenceServer<VectorSearchService>>()
+            .await;
+    };
+
+    let admin_service: Option<AdminServer<VectorSearchService>> =
+        options.admin.then(|| AdminServer::new(service.clone()));
+
+    Server::builder()
+        .add_service(health_service)
+        .add_service(
+            InferenceServer::new(service)
+                .max_decoding_message_size(options.max_message_size)
+                .max_encoding_message_size(options.max_message_size),
+        )
+        .add_optional_service(admin_service)
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), stop)
+        .await?;
+
+    match load_error {
+        Some(e) => Err(anyhow::anyhow!("cannot load the index and models : {}", e)),
+        None => Ok(()),
+    }
+} // end of serve
@@ -0,0 +1,62 @@
+//! Test support : a SearchIndex returning fixed neighbours, for the tests of the search, the
+//! collections and the server which need an index but not a graph.
+//! Built for the tests of the crate and with the test-util feature.
+
+use crate::hnsw_index::filter::FilterT;
+use crate::hnsw_index::hnsw::{Neighbour, PointId};
+use crate::index::SearchIndex;
+
+/// An index whose neighbours are the same for any query : the neighbours accepted by the filter of
+/// the search, in their order.
+#[derive(Debug, Clone, Default)]
+pub struct FakeIndex {
+    pub nb_point: usize,
+    pub dimension: usize,
+    pub neighbours: Vec<Neighbour>,
+    pub quantized: bool,
+}
+
+impl FakeIndex {
+    /// an index of nb_point points, whose nearest neighbours are 0, 1, 2, ... at distances 0,
+    /// step, 2 * step, ...
+    pub fn range(nb_point: usize, dimension: usize, step: f32) -> Self {
+        let neighbours: Vec<Neighbour> = (0..nb_point)
+            .map(|id: usize| Neighbour::new(id, step * id as f32, PointId(0, id as i32)))
+            .collect();
+
+        FakeIndex { nb_point, dimension, neighbours, quantized: false }
+    }
+}
+
+impl SearchIndex for FakeIndex {
+    fn name(&self) -> String {
+        String::from("fake")
+    }
+
+    fn get_nb_point(&self) -> usize {
+        self.nb_point
+    }
+
+    fn get_dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn search_filter(
+        &self,
+        _query: &Vec<f32>,
+        knbn: usize,
+        _ef: usize,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour> {
+        self.neighbours
+            .iter()
+            .filter(|n: &&Neighbour| !filter.is_some_and(|f: &dyn FilterT| !f.hnsw_filter(&n.d_id)))
+            .take(knbn)
+            .cloned()
+            .collect()
+    }
+
+    fn is_quantized(&self) -> bool {
+        self.quantized
+    }
+}
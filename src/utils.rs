@@ -1,4 +1,5 @@
 use std::fs::{File, OpenOptions};
+use std::io;
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
 
@@ -12,8 +13,9 @@ use crate::hnsw_index::dist::{DistDot, DistHamming};
 use crate::hnsw_index::hnsw::Hnsw;
 use crate::hnsw_index::hnswio::{load_description, load_hnsw, Description};
 
-pub fn load_data() -> Vec<String> {
-    let file: File = File::open("./data/ag_news.csv").unwrap();
+/// texts of the documents, the first column of the csv file path
+pub fn load_data(path: &str) -> Vec<String> {
+    let file: File = File::open(path).unwrap();
     let mut reader: csv::Reader<File> = csv::Reader::from_reader(file);
 
     let data: Vec<String> = reader
@@ -23,10 +25,11 @@ pub fn load_data() -> Vec<String> {
     data
 }
 
-pub fn load_model() -> SentenceEmbeddingsModel {
-    let model: SentenceEmbeddingsModel = if Path::new("models").is_dir() {
+/// loads the model of model_dir, or downloads it if model_dir does not exist
+pub fn load_model(model_dir: &str) -> SentenceEmbeddingsModel {
+    let model: SentenceEmbeddingsModel = if Path::new(model_dir).is_dir() {
         println!("load model from local");
-        SentenceEmbeddingsBuilder::local("models")
+        SentenceEmbeddingsBuilder::local(model_dir)
             .create_model()
             .unwrap()
     } else {
@@ -38,57 +41,69 @@ pub fn load_model() -> SentenceEmbeddingsModel {
     model
 }
 
-fn load_file(filename: &String) -> BufReader<File> {
+fn load_file(filename: &String) -> io::Result<BufReader<File>> {
     let path: PathBuf = PathBuf::from(filename);
-    let res: File = OpenOptions::new().read(true).open(path).unwrap();
-
-    BufReader::new(res)
+    let res: File = OpenOptions::new()
+        .read(true)
+        .open(&path)
+        .map_err(|e: io::Error| {
+            io::Error::new(e.kind(), format!("cannot open {:?} : {}", path, e))
+        })?;
+
+    Ok(BufReader::new(res))
 }
 
 #[allow(unused)]
-pub fn load_index(dataset: &str) -> Hnsw<f32, DistDot> {
+pub fn load_index(dataset: &str) -> io::Result<Hnsw<f32, DistDot>> {
     println!("load index");
 
     let index: Hnsw<f32, DistDot> = {
-        let mut graph: BufReader<File> = load_file(&format!("{}.hnsw.graph", dataset));
+        let mut graph: BufReader<File> = load_file(&format!("{}.hnsw.graph", dataset))?;
         // todo: offload to the disk (memmmap) to save the memmory
-        let mut data: BufReader<File> = load_file(&format!("{}.hnsw.data", dataset));
+        let mut data: BufReader<File> = load_file(&format!("{}.hnsw.data", dataset))?;
 
-        let description: Description = load_description(&mut graph).unwrap();
+        let description: Description = load_description(&mut graph)?;
 
-        let mut index: Hnsw<f32, DistDot> = load_hnsw(&mut graph, &description, &mut data).unwrap();
+        let index: Hnsw<f32, DistDot> = load_hnsw(&mut graph, &description, &mut data)?;
         index.set_searching_mode(true);
 
         index
     };
 
-    index
+    Ok(index)
 }
 
 #[allow(unused)]
-pub fn load_quantize_index(dataset: &str) -> Hnsw<i8, DistHamming> {
+pub fn load_quantize_index(dataset: &str) -> io::Result<Hnsw<i8, DistHamming>> {
     println!("load quantize index");
 
     let index: Hnsw<i8, DistHamming> = {
-        let mut graph: BufReader<File> = load_file(&format!("{}_q.hnsw.graph", dataset));
+        let mut graph: BufReader<File> = load_file(&format!("{}_q.hnsw.graph", dataset))?;
         // todo: offload to the disk (memmmap) to save the memmory
-        let mut data: BufReader<File> = load_file(&format!("{}_q.hnsw.data", dataset));
+        let mut data: BufReader<File> = load_file(&format!("{}_q.hnsw.data", dataset))?;
 
-        let description: Description = load_description(&mut graph).unwrap();
+        let description: Description = load_description(&mut graph)?;
 
-        let mut index: Hnsw<i8, DistHamming> =
-            load_hnsw(&mut graph, &description, &mut data).unwrap();
+        let index: Hnsw<i8, DistHamming> = load_hnsw(&mut graph, &description, &mut data)?;
         index.set_searching_mode(true);
 
         index
     };
 
-    index
+    Ok(index)
 }
 
-fn percentiles(ps: &[f32], lats: &Vec<u64>) -> Vec<(f32, u64)> {
+/// the percentiles ps (in \[0, 1\]) of the sorted latencies lats, empty if there are no latencies
+pub fn percentiles(ps: &[f32], lats: &[u64]) -> Vec<(f32, u64)> {
+    if lats.is_empty() {
+        return Vec::new();
+    }
+
     ps.iter()
-        .map(|p: &f32| (*p, lats[((lats.len() as f32) * p) as usize]))
+        .map(|p: &f32| {
+            let rank: usize = ((lats.len() as f32) * p) as usize;
+            (*p, lats[rank.min(lats.len() - 1)])
+        })
         .collect()
 }
 
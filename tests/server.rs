@@ -0,0 +1,177 @@
+//! Starts the server in-process on an ephemeral port and talks to it with gRPC clients.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use semantic_search::batch::BatchConfig;
+use semantic_search::error::SearchError;
+use semantic_search::search::{Embedder, ModelPool, Pool, SearchLimits, Searcher};
+use semantic_search::service::{serve, ServeOptions};
+use semantic_search::ss::admin_client::AdminClient;
+use semantic_search::ss::inference_client::InferenceClient;
+use semantic_search::ss::{
+    Features, ListCollectionsRequest, PredictRequest, PredictResponse, ReloadIndexRequest,
+};
+use semantic_search::testing::FakeIndex;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tonic::transport::Channel;
+use tonic::Code;
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+
+const DIMENSION: usize = 8;
+
+/// a slow model, so requests are still in flight when the server is asked to stop
+struct SlowModel;
+
+impl Embedder for SlowModel {
+    fn encode(&self, queries: &[String]) -> Result<Vec<Vec<f32>>, SearchError> {
+        thread::sleep(Duration::from_millis(300));
+        Ok(queries.iter().map(|_| vec![0.1; DIMENSION]).collect())
+    }
+}
+
+fn load_searcher() -> std::io::Result<Searcher> {
+    // the index is not available at once
+    thread::sleep(Duration::from_millis(500));
+
+    let models: ModelPool = Pool::new(vec![
+        Box::new(SlowModel) as Box<dyn Embedder>,
+        Box::new(SlowModel) as Box<dyn Embedder>,
+    ]);
+    let searcher: Searcher = Searcher::new(models, SearchLimits::default());
+    searcher.set_index(Arc::new(FakeIndex::range(100, DIMENSION, 1.)));
+
+    Ok(searcher)
+}
+
+fn options(admin: bool) -> ServeOptions {
+    ServeOptions { max_message_size: 4 * 1024 * 1024, admin, batch: BatchConfig::default() }
+}
+
+fn request(k: i32) -> PredictRequest {
+    PredictRequest {
+        features: vec![Features { query: String::from("The story about the school life") }],
+        k,
+        ..Default::default()
+    }
+}
+
+async fn serving_status(health: &mut HealthClient<Channel>) -> ServingStatus {
+    let request: HealthCheckRequest = HealthCheckRequest { service: String::from("ss.Inference") };
+    let status: i32 = health.check(request).await.unwrap().into_inner().status;
+
+    ServingStatus::from_i32(status).unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_server_health_and_shutdown() {
+    let listener: TcpListener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr: SocketAddr = listener.local_addr().unwrap();
+
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let server: JoinHandle<anyhow::Result<()>> =
+        tokio::spawn(serve(listener, options(true), load_searcher, async {
+            _ = stop_rx.await;
+        }));
+
+    let channel: Channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let mut health: HealthClient<Channel> = HealthClient::new(channel.clone());
+    let mut client: InferenceClient<Channel> = InferenceClient::new(channel.clone());
+
+    // loading : not serving, requests are answered unavailable
+    assert_eq!(serving_status(&mut health).await, ServingStatus::NotServing);
+    let status: tonic::Status = client.predict(request(3)).await.unwrap_err();
+    assert_eq!(status.code(), Code::Unavailable);
+
+    let mut nb_try: usize = 0;
+    while serving_status(&mut health).await != ServingStatus::Serving {
+        nb_try += 1;
+        assert!(nb_try < 100, "the server is still loading");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let response: PredictResponse = client.predict(request(3)).await.unwrap().into_inner();
+    assert_eq!(response.indices.len(), 1);
+    let ids: Vec<u64> = response.indices[0].hits.iter().map(|hit| hit.id).collect();
+    assert_eq!(ids, vec![0, 1, 2]);
+
+    let status: tonic::Status = client.predict(request(-1)).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    // a failed reload keeps the index served
+    let mut admin: AdminClient<Channel> = AdminClient::new(channel.clone());
+    let reload: ReloadIndexRequest =
+        ReloadIndexRequest { dataset: String::from("server_test_missing"), ..Default::default() };
+    let status: tonic::Status = admin.reload_index(reload).await.unwrap_err();
+    assert_eq!(status.code(), Code::Internal);
+    assert_eq!(
+        client
+            .predict(request(3))
+            .await
+            .unwrap()
+            .into_inner()
+            .indices
+            .len(),
+        1
+    );
+
+    let collections: Vec<String> = admin
+        .list_collections(ListCollectionsRequest {})
+        .await
+        .unwrap()
+        .into_inner()
+        .collections
+        .into_iter()
+        .map(|info| info.name)
+        .collect();
+    assert_eq!(collections, vec!["default"]);
+    let mut unknown: PredictRequest = request(3);
+    unknown.collection = String::from("tickets");
+    assert_eq!(client.predict(unknown).await.unwrap_err().code(), Code::NotFound);
+
+    // a request in flight when the shutdown starts is answered
+    let in_flight: JoinHandle<Result<tonic::Response<PredictResponse>, tonic::Status>> = {
+        let mut client: InferenceClient<Channel> = client.clone();
+        tokio::spawn(async move { client.predict(request(5)).await })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    stop_tx.send(()).unwrap();
+
+    let response: PredictResponse = in_flight.await.unwrap().unwrap().into_inner();
+    assert_eq!(response.indices[0].hits.len(), 5);
+
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .expect("the server did not stop")
+        .unwrap()
+        .unwrap();
+} // end of test_server_health_and_shutdown
+
+#[tokio::test]
+async fn test_server_load_failure() {
+    let listener: TcpListener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+    let result: anyhow::Result<()> = tokio::time::timeout(
+        Duration::from_secs(5),
+        serve(
+            listener,
+            options(false),
+            || Err(std::io::Error::new(std::io::ErrorKind::NotFound, "no dump news_q")),
+            std::future::pending::<()>(),
+        ),
+    )
+    .await
+    .expect("the server did not stop");
+
+    assert!(result.unwrap_err().to_string().contains("no dump news_q"));
+} // end of test_server_load_failure